@@ -0,0 +1,155 @@
+/* The raytracer's C API. Generated from src/capi.rs by cbindgen (see README.md); don't edit. */
+
+#ifndef RAYTRACING_H
+#define RAYTRACING_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// What a call into the library came to. Anything but `RT_STATUS_OK` leaves a message for
+// `rt_last_error`.
+typedef enum {
+  RT_STATUS_OK = 0,
+  // A null pointer or a value out of range was passed in.
+  RT_STATUS_INVALID_ARGUMENT = 1,
+  // A file couldn't be read.
+  RT_STATUS_IO = 2,
+  // The render was cancelled before it was done.
+  RT_STATUS_CANCELLED = 3,
+  // The library hit a bug. The handles involved shouldn't be used again.
+  RT_STATUS_PANIC = 4,
+} RtStatus;
+
+// A pinhole camera. Its aspect ratio is the rendered image's.
+typedef struct RtCamera RtCamera;
+
+// A material, which can be given to any number of objects.
+typedef struct RtMaterial RtMaterial;
+
+// A render running on its own threads.
+typedef struct RtRenderJob RtRenderJob;
+
+// The objects and lights of a scene. Render jobs take a copy, so it can keep changing.
+typedef struct RtScene RtScene;
+
+typedef struct {
+  double x;
+  double y;
+  double z;
+} RtVec3;
+
+typedef struct {
+  uint32_t width;
+  uint32_t height;
+  uint32_t samples;
+  uint32_t max_depth;
+  uint64_t seed;
+  // Trace wavelengths instead of RGB colors.
+  bool spectral;
+  bool denoise;
+} RtRenderSettings;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// The message for the last call on this thread that didn't return `RT_STATUS_OK`, or an
+// empty string. It stays valid until the next call on the thread.
+const char *rt_last_error(void);
+
+// A diffuse material.
+RtStatus rt_material_lambertian(RtVec3 albedo, RtMaterial **out);
+
+// A mirror, blurred by `fuzz`.
+RtStatus rt_material_metal(RtVec3 albedo, double fuzz, RtMaterial **out);
+
+// Glass or another clear material with index of refraction `ior`.
+RtStatus rt_material_dielectric(double ior, RtMaterial **out);
+
+// The glTF metallic-roughness material, which glows with `emissive` unless it's black.
+RtStatus rt_material_pbr(RtVec3 base_color,
+                         double metallic,
+                         double roughness,
+                         RtVec3 emissive,
+                         RtMaterial **out);
+
+void rt_material_free(RtMaterial *material);
+
+RtStatus rt_scene_new(RtScene **out);
+
+void rt_scene_free(RtScene *scene);
+
+RtStatus rt_scene_add_sphere(RtScene *scene,
+                             RtVec3 center,
+                             double radius,
+                             const RtMaterial *material);
+
+// Adds a mesh of `triangle_count` triangles, each three indices into the `vertex_count`
+// vertices, which are three doubles each.
+//
+// # Safety
+//
+// `vertices` and `indices` must point to that many values.
+RtStatus rt_scene_add_triangles(RtScene *scene,
+                                const double *vertices,
+                                size_t vertex_count,
+                                const uint32_t *indices,
+                                size_t triangle_count,
+                                const RtMaterial *material);
+
+// Adds a PLY or STL mesh from the file at `path`.
+//
+// # Safety
+//
+// `path` must be a null-terminated string.
+RtStatus rt_scene_add_mesh(RtScene *scene, const char *path, const RtMaterial *material);
+
+RtStatus rt_scene_add_point_light(RtScene *scene, RtVec3 position, RtVec3 intensity);
+
+// Adds a light infinitely far away, shining along `direction`.
+RtStatus rt_scene_add_directional_light(RtScene *scene, RtVec3 direction, RtVec3 irradiance);
+
+// A camera at `look_from` looking towards `look_at`, with `up` pointing up in the image
+// and a vertical field of view in degrees.
+RtStatus rt_camera_new(RtVec3 look_from,
+                       RtVec3 look_at,
+                       RtVec3 up,
+                       double vertical_fov,
+                       RtCamera **out);
+
+void rt_camera_free(RtCamera *camera);
+
+// 800x450 pixels at 16 samples per pixel, with 50 bounces.
+RtRenderSettings rt_render_settings_default(void);
+
+// Starts rendering `scene` as it is now on other threads.
+RtStatus rt_render_job_start(const RtScene *scene,
+                             const RtCamera *camera,
+                             RtRenderSettings settings,
+                             RtRenderJob **out);
+
+// The fraction of the job's samples taken so far, from 0 to 1.
+double rt_render_job_progress(const RtRenderJob *job);
+
+// Asks the job to stop. `rt_render_job_wait` then returns the image so far.
+void rt_render_job_cancel(const RtRenderJob *job);
+
+// Waits for the job to finish and copies its linear RGB image to `pixels`, top row first.
+// Returns `RT_STATUS_CANCELLED`, with the image so far, if the job was cancelled.
+//
+// # Safety
+//
+// `pixels` must have room for `len` floats, at least width * height * 3.
+RtStatus rt_render_job_wait(const RtRenderJob *job, float *pixels, size_t len);
+
+// Cancels the job if it's still running, and frees it once it has stopped.
+void rt_render_job_free(RtRenderJob *job);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* RAYTRACING_H */
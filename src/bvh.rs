@@ -0,0 +1,171 @@
+use crate::fps::{self, Counter};
+use crate::ray::Ray;
+use crate::vec3::{Point, Vec3};
+
+const LEAF_SIZE: usize = 4;
+
+/// An axis-aligned bounding box.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Aabb {
+    pub min: Point,
+    pub max: Point,
+}
+
+impl Aabb {
+    pub fn empty() -> Aabb {
+        Aabb{
+            min: Point::new(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+            max: Point::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+        }
+    }
+
+    pub fn from_points(points: &[Point]) -> Aabb {
+        points.iter().fold(Aabb::empty(), |b, p| b.grow(p))
+    }
+
+    pub fn grow(&self, p: &Point) -> Aabb {
+        Aabb{
+            min: Point::new(self.min.x.min(p.x), self.min.y.min(p.y), self.min.z.min(p.z)),
+            max: Point::new(self.max.x.max(p.x), self.max.y.max(p.y), self.max.z.max(p.z)),
+        }
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        self.grow(&other.min).grow(&other.max)
+    }
+
+    pub fn centroid(&self) -> Point {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn extent(&self) -> Vec3 {
+        self.max - self.min
+    }
+
+    /// Slab test: whether `r` passes through the box somewhere in [t_min, t_max].
+    pub fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> bool {
+        let (mut t0, mut t1) = (t_min, t_max);
+        for axis in 0..3 {
+            let inv_d = 1. / r.dir[axis];
+            let mut near = (self.min[axis] - r.origin[axis]) * inv_d;
+            let mut far = (self.max[axis] - r.origin[axis]) * inv_d;
+            if inv_d < 0. {
+                std::mem::swap(&mut near, &mut far);
+            }
+            t0 = if near > t0 { near } else { t0 };
+            t1 = if far < t1 { far } else { t1 };
+            if t1 < t0 {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+struct Node {
+    bounds: Aabb,
+    // Leaves cover `count` primitives starting at `start` in `Bvh::order`. Interior nodes
+    // have `count == 0`; their left child directly follows them and the right child is at `start`.
+    start: usize,
+    count: usize,
+}
+
+/// A bounding volume hierarchy over a set of primitives, stored as a flattened tree.
+/// The BVH only knows primitive bounds; callers intersect the primitives themselves.
+pub struct Bvh {
+    nodes: Vec<Node>,
+    order: Vec<usize>,
+}
+
+impl Bvh {
+    pub fn build(bounds: &[Aabb]) -> Bvh {
+        let mut bvh = Bvh{nodes: Vec::new(), order: (0..bounds.len()).collect()};
+        if !bounds.is_empty() {
+            bvh.build_node(bounds, 0, bounds.len());
+        }
+        bvh
+    }
+
+    fn build_node(&mut self, bounds: &[Aabb], start: usize, end: usize) -> usize {
+        let node_bounds = self.order[start..end].iter().fold(Aabb::empty(), |b, &i| b.union(&bounds[i]));
+        let index = self.nodes.len();
+        self.nodes.push(Node{bounds: node_bounds, start, count: end - start});
+        if end - start <= LEAF_SIZE {
+            return index;
+        }
+
+        // Split at the median centroid along the axis where the centroids spread the most.
+        let centroids = self.order[start..end].iter().fold(Aabb::empty(), |b, &i| b.grow(&bounds[i].centroid()));
+        let extent = centroids.extent();
+        let axis = if extent.x >= extent.y && extent.x >= extent.z { 0 } else if extent.y >= extent.z { 1 } else { 2 };
+        let mid = (start + end) / 2;
+        self.order[start..end].select_nth_unstable_by(mid - start, |&a, &b| {
+            bounds[a].centroid()[axis].partial_cmp(&bounds[b].centroid()[axis]).unwrap_or(std::cmp::Ordering::Equal)
+        });
+
+        self.build_node(bounds, start, mid);
+        let right = self.build_node(bounds, mid, end);
+        self.nodes[index].start = right;
+        self.nodes[index].count = 0;
+        index
+    }
+
+    /// Visits every primitive whose bounds `r` may pass through before the closest hit so far.
+    /// `hit_primitive` is called with a primitive index and the current `t_max`, and returns
+    /// the distance of a new closest hit, if any.
+    pub fn traverse<F: FnMut(usize, f64) -> Option<f64>>(&self, r: &Ray, t_min: f64, t_max: f64, mut hit_primitive: F) {
+        if self.nodes.is_empty() {
+            return;
+        }
+        let mut closest = t_max;
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            fps::count(Counter::BvhNodesVisited);
+            if !node.bounds.hit(r, t_min, closest) {
+                continue;
+            }
+            if node.count > 0 {
+                fps::count_many(Counter::IntersectionTests, node.count as u64);
+                for &primitive in &self.order[node.start..node.start + node.count] {
+                    if let Some(t) = hit_primitive(primitive, closest) {
+                        closest = t;
+                    }
+                }
+            } else {
+                stack.push(node.start);
+                stack.push(index + 1);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_traverse_finds_closest() {
+        // A row of unit boxes along -z; the ray should only need to report the nearest one.
+        let bounds: Vec<Aabb> = (0..20)
+            .map(|i| Aabb{min: Point::new(-0.5, -0.5, -(i as f64) - 1.5), max: Point::new(0.5, 0.5, -(i as f64) - 0.5)})
+            .collect();
+        let bvh = Bvh::build(&bounds);
+
+        let r = Ray::new(Point::zeros(), Vec3::new(0., 0., -1.), 0.);
+        let mut closest = None;
+        bvh.traverse(&r, 0.001, f64::INFINITY, |i, t_max| {
+            let t = i as f64 + 0.5;
+            if t < t_max {
+                closest = Some(i);
+                Some(t)
+            } else {
+                None
+            }
+        });
+        assert_eq!(closest, Some(0));
+
+        let miss = Ray::new(Point::new(2., 0., 0.), Vec3::new(0., 0., -1.), 0.);
+        bvh.traverse(&miss, 0.001, f64::INFINITY, |_, _| panic!("should not visit any primitive"));
+    }
+}
@@ -1,35 +1,96 @@
+use crate::matrix::Matrix;
 use crate::ray::Ray;
 use crate::vec3::{Point, Vec3};
 
+#[derive(Copy, Clone)]
 pub struct Camera {
     origin: Point,
     upper_left_corner: Point,
     horizontal: Vec3,
     vertical: Vec3,
+    shutter_open: f64,
+    shutter_close: f64,
 }
 
 impl Camera {
     pub fn new(vertical_fov: f64, aspect_ratio: f64) -> Camera {
+        Camera::look_at(Point::zeros(), Point::new(0., 0., -1.), Vec3::new(0., 1., 0.), vertical_fov, aspect_ratio)
+    }
+
+    /// A camera at `look_from` looking towards `look_at`, with `up` pointing (roughly) up
+    /// in the image. `vertical_fov` is in degrees.
+    pub fn look_at(look_from: Point, look_at: Point, up: Vec3, vertical_fov: f64, aspect_ratio: f64) -> Camera {
+        let w = (look_from - look_at).unit_vec();
+        let u = up.cross(&w).unit_vec();
+        let v = w.cross(&u);
+        Camera::with_basis(look_from, u, v, w, vertical_fov, aspect_ratio)
+    }
+
+    /// A camera at `origin` whose image spans `u` (right) and `v` (up), looking along `-w`.
+    /// The vectors must be unit length but need not form a right-handed basis, which lets
+    /// scene formats with mirrored cameras keep their orientation.
+    pub fn with_basis(origin: Point, u: Vec3, v: Vec3, w: Vec3, vertical_fov: f64, aspect_ratio: f64) -> Camera {
         // Calculate viewport width and height using vertical
-        let theta = vertical_fov * 180./std::f64::consts::PI;
+        let theta = vertical_fov * std::f64::consts::PI / 180.;
         let h = (theta/2.).tan().abs();
         let viewport_height = 2. * h;
         let viewport_width = aspect_ratio * viewport_height;
 
-        let focal_length = 1.;
-        let origin = Point::zeros();
-        let horizontal = Vec3::new(viewport_width, 0., 0.);
-        let vertical = Vec3::new(0., viewport_height, 0.);
-        let upper_left_corner = origin - horizontal/2. + vertical/2. - Vec3::new(0., 0., focal_length);
-        Camera{origin, horizontal, vertical, upper_left_corner}
+        let horizontal = viewport_width * u;
+        let vertical = viewport_height * v;
+        let upper_left_corner = origin - horizontal/2. + vertical/2. - w;
+        Camera{origin, horizontal, vertical, upper_left_corner, shutter_open: 0., shutter_close: 0.}
+    }
+
+    /// Sets the interval over which the shutter is open. Rays are spread across it.
+    pub fn set_shutter(&mut self, open: f64, close: f64) {
+        self.shutter_open = open;
+        self.shutter_close = close;
+    }
+
+    /// Returns the ray through viewport coordinates (x, y), at fraction `time` of the way
+    /// through the shutter interval.
+    pub fn get_ray(&self, x: f64, y: f64, time: f64) -> Ray {
+        let time = self.shutter_open + (self.shutter_close - self.shutter_open) * time;
+        Ray::new(self.origin, self.upper_left_corner + self.horizontal*x - self.vertical*y - self.origin, time)
+    }
+
+    /// The transform from camera space (looking along -z, y up) to world space.
+    pub fn camera_to_world(&self) -> Matrix {
+        let u = self.horizontal.unit_vec();
+        let v = self.vertical.unit_vec();
+        let w = self.origin - (self.upper_left_corner + self.horizontal/2. - self.vertical/2.);
+        let column = |a: Vec3, last| [a.x, a.y, a.z, last];
+        [column(u, 0.), column(v, 0.), column(w, 0.), column(self.origin, 1.)]
     }
-    
-    pub fn get_ray(&self, x: f64, y: f64) -> Ray {
-        Ray::new(self.origin, self.upper_left_corner + self.horizontal*x - self.vertical*y)
+
+    /// The vertical field of view in degrees.
+    pub fn vertical_fov(&self) -> f64 {
+        2. * (self.vertical.length() / 2.).atan().to_degrees()
     }
 
     pub fn shift(&mut self, movement: &Vec3) {
         self.origin += *movement;
+        self.upper_left_corner += *movement;
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_look_at() {
+        let camera = Camera::look_at(Point::new(1., 0., 0.), Point::new(1., 0., -5.), Vec3::new(0., 1., 0.), 90., 2.);
+        let center = camera.get_ray(0.5, 0.5, 0.);
+        assert_eq!(center.origin, Point::new(1., 0., 0.));
+        assert!((center.dir - Vec3::new(0., 0., -1.)).length() < 1e-9);
+        // A 90 degree vertical field of view reaches one unit up at unit distance.
+        let top_left = camera.get_ray(0., 0., 0.);
+        assert!((top_left.dir - Vec3::new(-2., 1., -1.)).length() < 1e-9);
+        assert!((camera.vertical_fov() - 90.).abs() < 1e-9);
+        let to_world = camera.camera_to_world();
+        assert_eq!(to_world[3], [1., 0., 0., 1.]);
+        assert!((to_world[2][2] - 1.).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,465 @@
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::io;
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread::{self, JoinHandle};
+
+use crate::denoise::Denoiser;
+use crate::mesh::TriangleMesh;
+use crate::renderer::{CancellationToken, Progress, RenderLimits, RenderStatus, Renderer};
+use crate::scene_builder::{CameraDesc, MaterialDesc, SceneBuilder};
+use crate::vec3::{Point, Vec3};
+
+/// What a call into the library came to. Anything but `RT_STATUS_OK` leaves a message for
+/// `rt_last_error`.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum RtStatus {
+    Ok = 0,
+    /// A null pointer or a value out of range was passed in.
+    InvalidArgument = 1,
+    /// A file couldn't be read.
+    Io = 2,
+    /// The render was cancelled before it was done.
+    Cancelled = 3,
+    /// The library hit a bug. The handles involved shouldn't be used again.
+    Panic = 4,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RtVec3 {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+/// A material, which can be given to any number of objects.
+pub struct RtMaterial(MaterialDesc);
+
+/// The objects and lights of a scene. Render jobs take a copy, so it can keep changing.
+pub struct RtScene(SceneBuilder);
+
+/// A pinhole camera. Its aspect ratio is the rendered image's.
+pub struct RtCamera(CameraDesc);
+
+// What a render job's thread leaves: how it ended and the image.
+type JobResult = (RenderStatus, Vec<f32>);
+
+/// A render running on its own threads.
+pub struct RtRenderJob {
+    width: u32,
+    height: u32,
+    cancel: CancellationToken,
+    // The fraction of the samples taken so far, as the bits of an f64.
+    progress: Arc<AtomicU64>,
+    thread: Mutex<Option<JoinHandle<JobResult>>>,
+    result: Mutex<Option<JobResult>>,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RtRenderSettings {
+    pub width: u32,
+    pub height: u32,
+    pub samples: u32,
+    pub max_depth: u32,
+    pub seed: u64,
+    /// Trace wavelengths instead of RGB colors.
+    pub spectral: bool,
+    pub denoise: bool,
+}
+
+struct Error(RtStatus, String);
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        let status = if e.kind() == io::ErrorKind::InvalidInput { RtStatus::InvalidArgument } else { RtStatus::Io };
+        Error(status, e.to_string())
+    }
+}
+
+fn invalid_argument(msg: &str) -> Error {
+    Error(RtStatus::InvalidArgument, msg.to_string())
+}
+
+fn required<T>(value: Option<T>, name: &str) -> Result<T, Error> {
+    value.ok_or_else(|| invalid_argument(&format!("{} is null", name)))
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+/// Runs `f`, turning its error or panic into a status and the message `rt_last_error` returns.
+fn call<F: FnOnce() -> Result<(), Error>>(f: F) -> RtStatus {
+    let result = panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        let message = payload.downcast_ref::<&str>().map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_string());
+        Err(Error(RtStatus::Panic, message))
+    });
+    let (status, message) = match result {
+        Ok(()) => (RtStatus::Ok, String::new()),
+        Err(Error(status, message)) => (status, message),
+    };
+    LAST_ERROR.with(|last| *last.borrow_mut() = CString::new(message.replace('\0', " ")).unwrap_or_default());
+    status
+}
+
+fn vec3(v: RtVec3) -> Vec3 {
+    Vec3::new(v.x, v.y, v.z)
+}
+
+/// Hands a new object to the caller through `out`.
+fn give<T>(out: Option<&mut *mut T>, value: T) -> Result<(), Error> {
+    *required(out, "out")? = Box::into_raw(Box::new(value));
+    Ok(())
+}
+
+/// Hands out `material` if its parameters are valid.
+fn give_material(out: Option<&mut *mut RtMaterial>, material: MaterialDesc) -> Result<(), Error> {
+    material.validate()?;
+    give(out, RtMaterial(material))
+}
+
+/// The message for the last call on this thread that didn't return `RT_STATUS_OK`, or an
+/// empty string. It stays valid until the next call on the thread.
+#[no_mangle]
+pub extern "C" fn rt_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ptr())
+}
+
+/// A diffuse material.
+#[no_mangle]
+pub extern "C" fn rt_material_lambertian(albedo: RtVec3, out: Option<&mut *mut RtMaterial>) -> RtStatus {
+    call(|| give_material(out, MaterialDesc::Lambertian{albedo: vec3(albedo)}))
+}
+
+/// A mirror, blurred by `fuzz`.
+#[no_mangle]
+pub extern "C" fn rt_material_metal(albedo: RtVec3, fuzz: f64, out: Option<&mut *mut RtMaterial>) -> RtStatus {
+    call(|| give_material(out, MaterialDesc::Metal{albedo: vec3(albedo), fuzz}))
+}
+
+/// Glass or another clear material with index of refraction `ior`.
+#[no_mangle]
+pub extern "C" fn rt_material_dielectric(ior: f64, out: Option<&mut *mut RtMaterial>) -> RtStatus {
+    call(|| give_material(out, MaterialDesc::Dielectric{ior}))
+}
+
+/// The glTF metallic-roughness material, which glows with `emissive` unless it's black.
+#[no_mangle]
+pub extern "C" fn rt_material_pbr(
+    base_color: RtVec3, metallic: f64, roughness: f64, emissive: RtVec3, out: Option<&mut *mut RtMaterial>,
+) -> RtStatus {
+    call(|| give_material(out, MaterialDesc::Pbr{base_color: vec3(base_color), metallic, roughness, emissive: vec3(emissive)}))
+}
+
+#[no_mangle]
+pub extern "C" fn rt_material_free(material: Option<Box<RtMaterial>>) {
+    drop(material);
+}
+
+#[no_mangle]
+pub extern "C" fn rt_scene_new(out: Option<&mut *mut RtScene>) -> RtStatus {
+    call(|| give(out, RtScene(SceneBuilder::new())))
+}
+
+#[no_mangle]
+pub extern "C" fn rt_scene_free(scene: Option<Box<RtScene>>) {
+    drop(scene);
+}
+
+#[no_mangle]
+pub extern "C" fn rt_scene_add_sphere(scene: Option<&mut RtScene>, center: RtVec3, radius: f64, material: Option<&RtMaterial>) -> RtStatus {
+    call(|| {
+        let (scene, material) = (required(scene, "scene")?, required(material, "material")?);
+        Ok(scene.0.add_sphere(vec3(center), radius, material.0)?)
+    })
+}
+
+/// Adds a mesh of `triangle_count` triangles, each three indices into the `vertex_count`
+/// vertices, which are three doubles each.
+///
+/// # Safety
+///
+/// `vertices` and `indices` must point to that many values.
+#[no_mangle]
+pub unsafe extern "C" fn rt_scene_add_triangles(
+    scene: Option<&mut RtScene>, vertices: *const f64, vertex_count: usize, indices: *const u32, triangle_count: usize, material: Option<&RtMaterial>,
+) -> RtStatus {
+    call(|| {
+        let (scene, material) = (required(scene, "scene")?, required(material, "material")?);
+        if (vertices.is_null() && vertex_count > 0) || (indices.is_null() && triangle_count > 0) {
+            return Err(invalid_argument("vertices or indices is null"));
+        }
+        let (vertex_len, index_len) = match (vertex_count.checked_mul(3), triangle_count.checked_mul(3)) {
+            (Some(vertex_len), Some(index_len)) => (vertex_len, index_len),
+            _ => return Err(invalid_argument("too many vertices or triangles")),
+        };
+        let vertices = if vertex_count == 0 { &[][..] } else { std::slice::from_raw_parts(vertices, vertex_len) };
+        let indices = if triangle_count == 0 { &[][..] } else { std::slice::from_raw_parts(indices, index_len) };
+        let vertices = vertices.chunks_exact(3).map(|v| Point::new(v[0], v[1], v[2])).collect();
+        let triangles = indices.chunks_exact(3).map(|t| [t[0], t[1], t[2]]).collect();
+        Ok(scene.0.add_triangles(vertices, triangles, material.0)?)
+    })
+}
+
+/// Adds a PLY or STL mesh from the file at `path`.
+///
+/// # Safety
+///
+/// `path` must be a null-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn rt_scene_add_mesh(scene: Option<&mut RtScene>, path: *const c_char, material: Option<&RtMaterial>) -> RtStatus {
+    call(|| {
+        let (scene, material) = (required(scene, "scene")?, required(material, "material")?);
+        if path.is_null() {
+            return Err(invalid_argument("path is null"));
+        }
+        let path = CStr::from_ptr(path).to_str().map_err(|_| invalid_argument("path isn't UTF-8"))?;
+        let mesh = TriangleMesh::load(path).map_err(|e| Error(RtStatus::Io, format!("can't load {}: {}", path, e)))?;
+        scene.0.add_mesh(mesh, material.0);
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn rt_scene_add_point_light(scene: Option<&mut RtScene>, position: RtVec3, intensity: RtVec3) -> RtStatus {
+    call(|| {
+        required(scene, "scene")?.0.add_point_light(vec3(position), vec3(intensity));
+        Ok(())
+    })
+}
+
+/// Adds a light infinitely far away, shining along `direction`.
+#[no_mangle]
+pub extern "C" fn rt_scene_add_directional_light(scene: Option<&mut RtScene>, direction: RtVec3, irradiance: RtVec3) -> RtStatus {
+    call(|| {
+        required(scene, "scene")?.0.add_directional_light(vec3(direction), vec3(irradiance));
+        Ok(())
+    })
+}
+
+/// A camera at `look_from` looking towards `look_at`, with `up` pointing up in the image
+/// and a vertical field of view in degrees.
+#[no_mangle]
+pub extern "C" fn rt_camera_new(
+    look_from: RtVec3, look_at: RtVec3, up: RtVec3, vertical_fov: f64, out: Option<&mut *mut RtCamera>,
+) -> RtStatus {
+    call(|| {
+        let camera = CameraDesc::new(vec3(look_from), vec3(look_at), vec3(up), vertical_fov)?;
+        give(out, RtCamera(camera))
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn rt_camera_free(camera: Option<Box<RtCamera>>) {
+    drop(camera);
+}
+
+/// 800x450 pixels at 16 samples per pixel, with 50 bounces.
+#[no_mangle]
+pub extern "C" fn rt_render_settings_default() -> RtRenderSettings {
+    RtRenderSettings{width: 800, height: 450, samples: 16, max_depth: 50, seed: 0, spectral: false, denoise: false}
+}
+
+/// Starts rendering `scene` as it is now on other threads.
+#[no_mangle]
+pub extern "C" fn rt_render_job_start(
+    scene: Option<&RtScene>, camera: Option<&RtCamera>, settings: RtRenderSettings, out: Option<&mut *mut RtRenderJob>,
+) -> RtStatus {
+    call(|| {
+        let (scene, camera) = (required(scene, "scene")?, required(camera, "camera")?);
+        let out = required(out, "out")?;
+        if settings.width == 0 || settings.height == 0 || settings.samples == 0 {
+            return Err(invalid_argument("width, height and samples must be positive"));
+        }
+        let (width, height) = (settings.width, settings.height);
+        let camera = camera.0.build(width as f64 / height as f64);
+        let mut renderer = Renderer::new(width, height, settings.max_depth as usize, settings.samples as usize);
+        renderer.set_seed(settings.seed);
+        renderer.set_spectral(settings.spectral);
+        let progress = Arc::new(AtomicU64::new(0f64.to_bits()));
+        let reported = progress.clone();
+        renderer.set_progress(Some(Arc::new(move |progress: &Progress| reported.store(progress.fraction().to_bits(), Ordering::Relaxed))));
+        let (scene, cancel) = (scene.0.clone(), CancellationToken::new());
+        let limits = RenderLimits{deadline: None, cancel: Some(cancel.clone())};
+        let thread = thread::spawn(move || {
+            let mut state = renderer.start();
+            let status = renderer.render(&scene.build(), &camera, &mut state, &limits);
+            let mut film = renderer.new_film();
+            renderer.resolve(&state, &mut film);
+            let beauty = if settings.denoise { Denoiser::default().denoise(&film) } else { film.beauty };
+            (status, beauty.iter().flat_map(|c| [c.x as f32, c.y as f32, c.z as f32]).collect())
+        });
+        *out = Box::into_raw(Box::new(RtRenderJob{
+            width, height, cancel, progress, thread: Mutex::new(Some(thread)), result: Mutex::new(None),
+        }));
+        Ok(())
+    })
+}
+
+/// The fraction of the job's samples taken so far, from 0 to 1.
+#[no_mangle]
+pub extern "C" fn rt_render_job_progress(job: Option<&RtRenderJob>) -> f64 {
+    job.map_or(0., |job| f64::from_bits(job.progress.load(Ordering::Relaxed)))
+}
+
+/// Asks the job to stop. `rt_render_job_wait` then returns the image so far.
+#[no_mangle]
+pub extern "C" fn rt_render_job_cancel(job: Option<&RtRenderJob>) {
+    if let Some(job) = job {
+        job.cancel.cancel();
+    }
+}
+
+/// Waits for the job to finish and copies its linear RGB image to `pixels`, top row first.
+/// Returns `RT_STATUS_CANCELLED`, with the image so far, if the job was cancelled.
+///
+/// # Safety
+///
+/// `pixels` must have room for `len` floats, at least width * height * 3.
+#[no_mangle]
+pub unsafe extern "C" fn rt_render_job_wait(job: Option<&RtRenderJob>, pixels: *mut f32, len: usize) -> RtStatus {
+    call(|| {
+        let job = required(job, "job")?;
+        let needed = job.width as usize * job.height as usize * 3;
+        if pixels.is_null() || len < needed {
+            return Err(invalid_argument(&format!("pixels needs room for {} floats", needed)));
+        }
+        let panicked = || Error(RtStatus::Panic, "the render thread panicked".to_string());
+        let mut result = job.result.lock().unwrap_or_else(PoisonError::into_inner);
+        if result.is_none() {
+            // The thread is only gone without a result if it panicked.
+            let thread = job.thread.lock().unwrap_or_else(PoisonError::into_inner).take().ok_or_else(panicked)?;
+            *result = Some(thread.join().map_err(|_| panicked())?);
+        }
+        let (status, image) = result.as_ref().unwrap();
+        std::slice::from_raw_parts_mut(pixels, needed).copy_from_slice(image);
+        match status {
+            RenderStatus::Cancelled => Err(Error(RtStatus::Cancelled, "the render was cancelled".to_string())),
+            _ => Ok(()),
+        }
+    })
+}
+
+/// Cancels the job if it's still running, and frees it once it has stopped.
+#[no_mangle]
+pub extern "C" fn rt_render_job_free(job: Option<Box<RtRenderJob>>) {
+    if let Some(job) = job {
+        job.cancel.cancel();
+        if let Some(thread) = job.thread.lock().unwrap_or_else(PoisonError::into_inner).take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ptr;
+
+    fn v(x: f64, y: f64, z: f64) -> RtVec3 {
+        RtVec3{x, y, z}
+    }
+
+    fn last_error() -> String {
+        unsafe { CStr::from_ptr(rt_last_error()) }.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_render_job() {
+        let (mut scene, mut ground, mut glass, mut camera, mut job) = (ptr::null_mut(), ptr::null_mut(), ptr::null_mut(), ptr::null_mut(), ptr::null_mut());
+        assert_eq!(rt_scene_new(Some(&mut scene)), RtStatus::Ok);
+        assert_eq!(rt_material_lambertian(v(0.5, 0.5, 0.5), Some(&mut ground)), RtStatus::Ok);
+        assert_eq!(rt_material_dielectric(1.5, Some(&mut glass)), RtStatus::Ok);
+        assert_eq!(rt_camera_new(v(0., 0., 0.), v(0., 0., -1.), v(0., 1., 0.), 90., Some(&mut camera)), RtStatus::Ok);
+        unsafe {
+            assert_eq!(rt_scene_add_sphere(scene.as_mut(), v(0., -100.5, -1.), 100., ground.as_ref()), RtStatus::Ok);
+            let vertices = [-1., 0., -2., 1., 0., -2., 0., 1., -2.];
+            assert_eq!(rt_scene_add_triangles(scene.as_mut(), vertices.as_ptr(), 3, [0, 1, 2].as_ptr(), 1, glass.as_ref()), RtStatus::Ok);
+
+            let settings = RtRenderSettings{width: 16, height: 8, samples: 4, max_depth: 10, ..rt_render_settings_default()};
+            assert_eq!(rt_render_job_start(scene.as_ref(), camera.as_ref(), settings, Some(&mut job)), RtStatus::Ok);
+            // Scenes are copied into the job, so freeing them while it runs is fine.
+            rt_scene_free(Some(Box::from_raw(scene)));
+            let mut pixels = vec![-1f32; 16 * 8 * 3];
+            assert_eq!(rt_render_job_wait(job.as_ref(), pixels.as_mut_ptr(), pixels.len() - 1), RtStatus::InvalidArgument);
+            assert_eq!(rt_render_job_wait(job.as_ref(), pixels.as_mut_ptr(), pixels.len()), RtStatus::Ok);
+            assert_eq!(last_error(), "");
+            assert_eq!(rt_render_job_progress(job.as_ref()), 1.);
+            assert!(pixels.iter().all(|&p| p >= 0.) && pixels.iter().any(|&p| p > 0.));
+
+            rt_render_job_free(Some(Box::from_raw(job)));
+            rt_camera_free(Some(Box::from_raw(camera)));
+            rt_material_free(Some(Box::from_raw(ground)));
+            rt_material_free(Some(Box::from_raw(glass)));
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut material = ptr::null_mut();
+        assert_eq!(rt_material_dielectric(-1., Some(&mut material)), RtStatus::InvalidArgument);
+        assert_eq!(last_error(), "the index of refraction must be positive");
+        assert!(material.is_null());
+        assert_eq!(rt_material_lambertian(v(f64::NAN, 0.5, 0.5), Some(&mut material)), RtStatus::InvalidArgument);
+        assert_eq!(last_error(), "the albedo must be finite and not negative");
+        assert_eq!(rt_material_metal(v(0.5, 0.5, 0.5), 2., Some(&mut material)), RtStatus::InvalidArgument);
+        assert_eq!(last_error(), "the fuzz must be between 0 and 1");
+        assert_eq!(rt_material_pbr(v(0.5, 0.5, 0.5), -1., 0.5, v(0., 0., 0.), Some(&mut material)), RtStatus::InvalidArgument);
+        assert_eq!(last_error(), "metallic and roughness must be between 0 and 1");
+        assert!(material.is_null());
+        assert_eq!(rt_scene_add_sphere(None, v(0., 0., 0.), 1., None), RtStatus::InvalidArgument);
+        assert_eq!(last_error(), "scene is null");
+
+        let mut scene = RtScene(SceneBuilder::new());
+        let lambertian = RtMaterial(MaterialDesc::Lambertian{albedo: Vec3::new(0.5, 0.5, 0.5)});
+        let status = unsafe { rt_scene_add_triangles(Some(&mut scene), [0.; 3].as_ptr(), 1, [0, 1, 2].as_ptr(), 1, Some(&lambertian)) };
+        assert_eq!((status, last_error().as_str()), (RtStatus::InvalidArgument, "triangle vertex index out of range"));
+        let status = unsafe { rt_scene_add_triangles(Some(&mut scene), [0.; 3].as_ptr(), usize::MAX, [0, 1, 2].as_ptr(), 1, Some(&lambertian)) };
+        assert_eq!((status, last_error().as_str()), (RtStatus::InvalidArgument, "too many vertices or triangles"));
+        let path = CString::new("missing.ply").unwrap();
+        assert_eq!(unsafe { rt_scene_add_mesh(Some(&mut scene), path.as_ptr(), Some(&lambertian)) }, RtStatus::Io);
+        assert!(last_error().starts_with("can't load missing.ply"));
+        assert_eq!(rt_scene_add_sphere(Some(&mut scene), v(0., 0., 0.), 0., Some(&lambertian)), RtStatus::InvalidArgument);
+        assert_eq!(last_error(), "the sphere's radius must be positive");
+        assert_eq!(rt_scene_add_sphere(Some(&mut scene), v(f64::INFINITY, 0., 0.), 1., Some(&lambertian)), RtStatus::InvalidArgument);
+        assert!(scene.0.is_empty());
+        assert_eq!(call(|| panic!("oops")), RtStatus::Panic);
+        assert_eq!(last_error(), "oops");
+    }
+
+    #[test]
+    fn test_header_is_up_to_date() {
+        let dir = env!("CARGO_MANIFEST_DIR");
+        let config = cbindgen::Config::from_file(format!("{}/cbindgen.toml", dir)).unwrap();
+        let mut header = Vec::new();
+        cbindgen::Builder::new().with_src(format!("{}/src/capi.rs", dir)).with_config(config).generate().unwrap().write(&mut header);
+        let committed = std::fs::read(format!("{}/include/raytracing.h", dir)).unwrap();
+        assert!(committed == header, "include/raytracing.h is out of date; regenerate it with `cbindgen --config cbindgen.toml --output include/raytracing.h src/capi.rs`");
+    }
+
+    #[test]
+    fn test_panicked_render_job() {
+        let job = RtRenderJob{
+            width: 1,
+            height: 1,
+            cancel: CancellationToken::new(),
+            progress: Arc::new(AtomicU64::new(0)),
+            thread: Mutex::new(Some(thread::spawn(|| -> JobResult { panic!("render failed") }))),
+            result: Mutex::new(None),
+        };
+        let mut pixels = [0f32; 3];
+        for _ in 0..2 {
+            assert_eq!(unsafe { rt_render_job_wait(Some(&job), pixels.as_mut_ptr(), pixels.len()) }, RtStatus::Panic);
+            assert_eq!(last_error(), "the render thread panicked");
+        }
+        rt_render_job_free(Some(Box::new(job)));
+    }
+}
@@ -0,0 +1,188 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::renderer::{RenderState, Renderer};
+use crate::vec3::Vec3;
+
+const MAGIC: &[u8; 8] = b"RAYSCKPT";
+const VERSION: u32 = 1;
+
+/// Saves a render in progress, so it can be resumed after the process is gone.
+///
+/// The on-disk format is the magic `RAYSCKPT` and a u32 version, followed by a string
+/// describing the renderer's settings and the scene, then the render state as written by
+/// `RenderState::write`. Strings are a u32 byte count and UTF-8; numbers are little-endian.
+/// The checkpoint is written next to `path` first and then moved over it, so a crash while
+/// saving keeps the previous one.
+pub fn save<P: AsRef<Path>>(path: P, renderer: &Renderer, state: &RenderState, scene: &str) -> io::Result<()> {
+    let path = path.as_ref();
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+    {
+        let mut writer = BufWriter::new(File::create(&partial)?);
+        write(&mut writer, renderer, state, scene)?;
+        writer.flush()?;
+    }
+    fs::rename(&partial, path)
+}
+
+/// Loads a render saved by `save` to continue it with `renderer`, which must have the same
+/// settings, as must the scene.
+pub fn load<P: AsRef<Path>>(path: P, renderer: &Renderer, scene: &str) -> io::Result<RenderState> {
+    read(&mut BufReader::new(File::open(path)?), renderer, scene)
+}
+
+fn settings(renderer: &Renderer, scene: &str) -> String {
+    format!("{}; scene {}", renderer.settings(), scene)
+}
+
+pub fn write<W: Write>(writer: &mut W, renderer: &Renderer, state: &RenderState, scene: &str) -> io::Result<()> {
+    writer.write_all(MAGIC)?;
+    write_u32(writer, VERSION)?;
+    write_str(writer, &settings(renderer, scene))?;
+    state.write(writer)
+}
+
+pub fn read<R: Read>(reader: &mut R, renderer: &Renderer, scene: &str) -> io::Result<RenderState> {
+    let mut magic = [0u8; 8];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid_data("not a render checkpoint"));
+    }
+    if read_u32(reader)? != VERSION {
+        return Err(invalid_data("unsupported checkpoint version"));
+    }
+    let saved = read_string(reader)?;
+    if saved != settings(renderer, scene) {
+        return Err(invalid_data(&format!("the checkpoint was made with different settings: {}", saved)));
+    }
+    RenderState::read(reader, renderer)
+}
+
+pub fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+pub fn write_u32<W: Write>(writer: &mut W, value: u32) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+pub fn write_u64<W: Write>(writer: &mut W, value: u64) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+pub fn write_f64<W: Write>(writer: &mut W, value: f64) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+pub fn write_vec3<W: Write>(writer: &mut W, v: &Vec3) -> io::Result<()> {
+    write_f64(writer, v.x)?;
+    write_f64(writer, v.y)?;
+    write_f64(writer, v.z)
+}
+
+fn write_str<W: Write>(writer: &mut W, s: &str) -> io::Result<()> {
+    write_u32(writer, s.len() as u32)?;
+    writer.write_all(s.as_bytes())
+}
+
+pub fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+pub fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+pub fn read_f64<R: Read>(reader: &mut R) -> io::Result<f64> {
+    Ok(f64::from_bits(read_u64(reader)?))
+}
+
+pub fn read_vec3<R: Read>(reader: &mut R) -> io::Result<Vec3> {
+    Ok(Vec3::new(read_f64(reader)?, read_f64(reader)?, read_f64(reader)?))
+}
+
+fn read_string<R: Read>(reader: &mut R) -> io::Result<String> {
+    let mut bytes = vec![0u8; read_u32(reader)? as usize];
+    reader.read_exact(&mut bytes)?;
+    String::from_utf8(bytes).map_err(|_| invalid_data("checkpoint strings must be UTF-8"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use crate::camera::Camera;
+    use crate::film::Film;
+    use crate::hittable::HittableList;
+    use crate::material::{Dielectric, Lambertian};
+    use crate::renderer::{CancellationToken, Progress, RenderLimits, RenderStatus};
+    use crate::scene::Scene;
+    use crate::sphere::Sphere;
+    use crate::vec3::Color;
+
+    fn scene() -> Scene {
+        Scene::new(HittableList::new(vec![
+            Box::new(Sphere::new(0., -100.5, -1., 100., Box::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))))),
+            Box::new(Sphere::new(0., 0., -1., 0.5, Box::new(Dielectric::new(1.5)))),
+            Box::new(Sphere::new(1., 0., -1., 0.5, Box::new(Lambertian::new(Color::new(0.8, 0.3, 0.3))))),
+        ]), Vec::new())
+    }
+
+    /// Stops a render partway through `pass`, as if the process was killed, and checks that
+    /// resuming it from a checkpoint gives the same image as rendering it in one go.
+    fn check_resumed_render(pass: usize) {
+        let camera = Camera::new(90., 2.);
+        let mut renderer = Renderer::new(16, 8, 10, 40);
+        let mut uninterrupted = Film::new(16, 8);
+        renderer.draw(&scene(), &camera, &mut uninterrupted);
+
+        let cancel = CancellationToken::new();
+        let token = cancel.clone();
+        renderer.set_progress(Some(Arc::new(move |progress: &Progress| {
+            if progress.pass == pass && progress.tiles_done == 3 {
+                token.cancel();
+            }
+        })));
+        let mut state = renderer.start();
+        let limits = RenderLimits{deadline: None, cancel: Some(cancel)};
+        assert_eq!(renderer.render(&scene(), &camera, &mut state, &limits), RenderStatus::Cancelled);
+        let mut saved = Vec::new();
+        write(&mut saved, &renderer, &state, "test").unwrap();
+
+        renderer.set_progress(None);
+        let mut resumed = read(&mut saved.as_slice(), &renderer, "test").unwrap();
+        assert_eq!(renderer.render(&scene(), &camera, &mut resumed, &RenderLimits::default()), RenderStatus::Complete);
+        let mut film = Film::new(16, 8);
+        renderer.resolve(&resumed, &mut film);
+        assert_eq!(film.beauty, uninterrupted.beauty);
+        assert_eq!(film.material_id, uninterrupted.material_id);
+        assert_eq!(film.samples, uninterrupted.samples);
+    }
+
+    #[test]
+    fn test_resumed_render_is_identical() {
+        check_resumed_render(2);
+    }
+
+    #[test]
+    fn test_render_resumed_in_its_first_pass_is_identical() {
+        // Pixels not reached before the checkpoint only find their material after resuming.
+        check_resumed_render(1);
+    }
+
+    #[test]
+    fn test_checkpoint_needs_the_same_settings() {
+        let mut renderer = Renderer::new(16, 8, 10, 40);
+        let mut saved = Vec::new();
+        write(&mut saved, &renderer, &renderer.start(), "test").unwrap();
+        assert!(read(&mut saved.as_slice(), &renderer, "other").is_err());
+        renderer.set_seed(7);
+        assert!(read(&mut saved.as_slice(), &renderer, "test").is_err());
+    }
+}
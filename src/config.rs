@@ -0,0 +1,216 @@
+use std::str::FromStr;
+
+use crate::filter::FilterKind;
+use crate::sampler::SamplerKind;
+use crate::scenes::SCENES;
+use crate::tonemap::{NEUTRAL_WHITE_BALANCE, ToneCurve};
+
+// The most seconds a time limit or checkpoint interval may be, well short of overflowing
+// a deadline.
+const MAX_SECONDS: f64 = 1e9;
+
+/// Options parsed from the command line.
+pub struct Config {
+    pub scene: String,
+    pub meshes: Vec<String>,
+    /// A glTF file that replaces the built-in scene.
+    pub gltf: Option<String>,
+    /// A pbrt-v3 scene file that replaces the built-in scene.
+    pub pbrt: Option<String>,
+    pub volume: Option<String>,
+    pub volume_density: f64,
+    pub volume_emission: f64,
+    pub heightmap: Option<String>,
+    pub heightmap_scale: f64,
+    pub shutter_open: f64,
+    pub shutter_close: f64,
+    pub samples: usize,
+    /// Samples per pixel for the denoised previews shown while moving the camera.
+    pub preview_samples: usize,
+    /// Denoise final renders too, not just previews.
+    pub denoise: bool,
+    /// Seed for the random numbers; the same seed gives the same render.
+    pub seed: u64,
+    pub sampler: SamplerKind,
+    pub filter: FilterKind,
+    /// Filter radius in pixels, or the filter's default.
+    pub filter_radius: Option<f64>,
+    /// Stop sampling a pixel once the relative standard error of its mean falls below this.
+    pub adaptive: Option<f64>,
+    /// Samples per pixel before, and between, adaptive convergence checks.
+    pub min_samples: usize,
+    /// Seconds to keep rendering passes for when saving to `output`, instead of a sample count.
+    pub time_limit: Option<f64>,
+    /// A file to save the render in progress to every `checkpoint_interval` seconds, and
+    /// whenever it stops.
+    pub checkpoint: Option<String>,
+    pub checkpoint_interval: f64,
+    /// Continue the render saved in `checkpoint`.
+    pub resume: bool,
+    /// An address to wait for workers on, to render `output` on them instead of locally.
+    pub coordinator: Option<String>,
+    /// The address of a coordinator to render for. The scene comes from the coordinator.
+    pub worker: Option<String>,
+    /// An address to serve renders over HTTP on, instead of opening a window.
+    pub serve: Option<String>,
+    /// Trace wavelengths instead of RGB colors.
+    pub spectral: bool,
+    /// Exposure adjustment in stops.
+    pub exposure: f64,
+    pub tone_curve: ToneCurve,
+    /// Color temperature (in K) that should come out white.
+    pub white_balance: f64,
+    pub dither: bool,
+    /// Render once without a window and save the image here: tone mapped for PNG, linear
+    /// for OpenEXR.
+    pub output: Option<String>,
+    /// Also save the auxiliary buffers, next to `output` or as layers of an EXR.
+    pub aovs: bool,
+    /// Store EXR colors and normals as half floats.
+    pub half: bool,
+    /// A JSON file to save the render statistics to.
+    pub stats: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config{
+            scene: SCENES[0].to_string(),
+            meshes: Vec::new(),
+            gltf: None,
+            pbrt: None,
+            volume: None,
+            volume_density: 10.,
+            volume_emission: 1.,
+            heightmap: None,
+            heightmap_scale: 3.,
+            shutter_open: 0.,
+            shutter_close: 1.,
+            samples: 100,
+            preview_samples: 8,
+            denoise: false,
+            seed: 0,
+            sampler: SamplerKind::Sobol,
+            filter: FilterKind::Box,
+            filter_radius: None,
+            adaptive: None,
+            min_samples: 16,
+            time_limit: None,
+            checkpoint: None,
+            checkpoint_interval: 300.,
+            resume: false,
+            coordinator: None,
+            worker: None,
+            serve: None,
+            spectral: false,
+            exposure: 0.,
+            tone_curve: ToneCurve::Clamp,
+            white_balance: NEUTRAL_WHITE_BALANCE,
+            dither: false,
+            output: None,
+            aovs: false,
+            half: false,
+            stats: None,
+        }
+    }
+}
+
+fn value<T: FromStr>(flag: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or(format!("missing value for {}", flag))?;
+    value.parse().map_err(|_| format!("invalid value for {}: {}", flag, value))
+}
+
+impl Config {
+    pub fn from_args() -> Result<Config, String> {
+        Config::parse(std::env::args().skip(1))
+    }
+
+    pub fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Config, String> {
+        let mut config = Config::default();
+        while let Some(flag) = args.next() {
+            match flag.as_str() {
+                "--scene" => {
+                    config.scene = value(&flag, args.next())?;
+                    if !SCENES.contains(&config.scene.as_str()) {
+                        return Err(format!("unknown scene {}, expected one of {:?}", config.scene, SCENES));
+                    }
+                }
+                "--gltf" => config.gltf = Some(value(&flag, args.next())?),
+                "--pbrt" => config.pbrt = Some(value(&flag, args.next())?),
+                "--mesh" => config.meshes.push(value(&flag, args.next())?),
+                "--volume" => config.volume = Some(value(&flag, args.next())?),
+                "--volume-density" => config.volume_density = value(&flag, args.next())?,
+                "--volume-emission" => config.volume_emission = value(&flag, args.next())?,
+                "--heightmap" => config.heightmap = Some(value(&flag, args.next())?),
+                "--heightmap-scale" => config.heightmap_scale = value(&flag, args.next())?,
+                "--shutter-open" => config.shutter_open = value(&flag, args.next())?,
+                "--shutter-close" => config.shutter_close = value(&flag, args.next())?,
+                "--samples" => config.samples = value(&flag, args.next())?,
+                "--preview-samples" => config.preview_samples = value(&flag, args.next())?,
+                "--denoise" => config.denoise = true,
+                "--seed" => config.seed = value(&flag, args.next())?,
+                "--sampler" => config.sampler = value(&flag, args.next())?,
+                "--filter" => config.filter = value(&flag, args.next())?,
+                "--filter-radius" => config.filter_radius = Some(value(&flag, args.next())?),
+                "--adaptive" => config.adaptive = Some(value(&flag, args.next())?),
+                "--min-samples" => config.min_samples = value(&flag, args.next())?,
+                "--time-limit" => config.time_limit = Some(value(&flag, args.next())?),
+                "--checkpoint" => config.checkpoint = Some(value(&flag, args.next())?),
+                "--checkpoint-interval" => config.checkpoint_interval = value(&flag, args.next())?,
+                "--resume" => config.resume = true,
+                "--coordinator" => config.coordinator = Some(value(&flag, args.next())?),
+                "--worker" => config.worker = Some(value(&flag, args.next())?),
+                "--serve" => config.serve = Some(value(&flag, args.next())?),
+                "--spectral" => config.spectral = true,
+                "--exposure" => config.exposure = value(&flag, args.next())?,
+                "--tonemap" => config.tone_curve = value(&flag, args.next())?,
+                "--white-balance" => config.white_balance = value(&flag, args.next())?,
+                "--dither" => config.dither = true,
+                "--output" => config.output = Some(value(&flag, args.next())?),
+                "--aovs" => config.aovs = true,
+                "--half" => config.half = true,
+                "--stats" => config.stats = Some(value(&flag, args.next())?),
+                _ => return Err(format!("unknown argument: {}", flag)),
+            }
+        }
+        if config.time_limit.is_some_and(|limit| !(0. ..=MAX_SECONDS).contains(&limit)) {
+            return Err(format!("--time-limit must be between 0 and {} seconds", MAX_SECONDS));
+        }
+        if !(config.checkpoint_interval > 0. && config.checkpoint_interval <= MAX_SECONDS) {
+            return Err(format!("--checkpoint-interval must be more than 0 and at most {} seconds", MAX_SECONDS));
+        }
+        if config.resume && config.checkpoint.is_none() {
+            return Err("--resume needs the --checkpoint to resume from".to_string());
+        }
+        if config.coordinator.is_some() && (config.output.is_none() || config.time_limit.is_some() || config.checkpoint.is_some()) {
+            return Err("--coordinator needs an --output, and works without --time-limit or --checkpoint".to_string());
+        }
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Config, String> {
+        Config::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_time_limit() {
+        assert_eq!(parse(&["--time-limit", "2.5"]).unwrap().time_limit, Some(2.5));
+        assert_eq!(parse(&["--time-limit", "0"]).unwrap().time_limit, Some(0.));
+        for limit in ["-1", "NaN", "inf", "1e300"] {
+            assert!(parse(&["--time-limit", limit]).is_err(), "{}", limit);
+        }
+    }
+
+    #[test]
+    fn test_checkpoint_interval() {
+        assert_eq!(parse(&["--checkpoint-interval", "0.5"]).unwrap().checkpoint_interval, 0.5);
+        for interval in ["0", "-1", "NaN", "inf", "1e300"] {
+            assert!(parse(&["--checkpoint-interval", interval]).is_err(), "{}", interval);
+        }
+    }
+}
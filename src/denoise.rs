@@ -0,0 +1,141 @@
+use rayon::prelude::*;
+
+use crate::film::Film;
+use crate::vec3::Color;
+
+// B3 spline weights of the 5x5 à-trous kernel.
+const KERNEL: [f64; 5] = [1. / 16., 1. / 4., 3. / 8., 1. / 4., 1. / 16.];
+
+/// Edge-avoiding à-trous wavelet filter (Dammertz et al. 2010). Each pass blurs with a
+/// kernel twice as wide as the last, weighting neighbours down where the normal, albedo,
+/// depth or the color itself differ, so edges and texture survive.
+#[derive(Debug, Copy, Clone)]
+pub struct Denoiser {
+    pub iterations: usize,
+    pub sigma_color: f64,
+    pub sigma_normal: f64,
+    pub sigma_albedo: f64,
+    /// Tolerated relative depth difference.
+    pub sigma_depth: f64,
+}
+
+impl Default for Denoiser {
+    fn default() -> Denoiser {
+        Denoiser{iterations: 5, sigma_color: 1.0, sigma_normal: 0.3, sigma_albedo: 0.1, sigma_depth: 0.1}
+    }
+}
+
+fn squared(c: &Color) -> f64 {
+    c.length_squared()
+}
+
+impl Denoiser {
+    /// Returns a denoised copy of the film's beauty image.
+    pub fn denoise(&self, film: &Film) -> Vec<Color> {
+        // Filter the lighting rather than the final color, so texture detail in the albedo
+        // isn't blurred away, and multiply the albedo back in at the end.
+        let demodulate = |albedo: f64| if albedo > 1e-3 { albedo } else { 1. };
+        let mut image: Vec<Color> = film.beauty.iter().zip(film.albedo.iter())
+            .map(|(c, a)| Color::new(c.x / demodulate(a.x), c.y / demodulate(a.y), c.z / demodulate(a.z)))
+            .collect();
+
+        for iteration in 0..self.iterations {
+            let step = 1 << iteration;
+            // Later passes see less noise, so they can be stricter about color differences.
+            let sigma_color = self.sigma_color / 2f64.powf(iteration as f64 / 2.);
+            let mut filtered = vec![Color::zeros(); image.len()];
+            filtered.par_chunks_mut(film.width).enumerate().for_each(|(y, row)| {
+                for (x, out) in row.iter_mut().enumerate() {
+                    *out = self.filter_pixel(film, &image, x, y, step, sigma_color);
+                }
+            });
+            image = filtered;
+        }
+
+        image.iter().zip(film.albedo.iter())
+            .map(|(c, a)| Color::new(c.x * demodulate(a.x), c.y * demodulate(a.y), c.z * demodulate(a.z)))
+            .collect()
+    }
+
+    fn filter_pixel(&self, film: &Film, image: &[Color], x: usize, y: usize, step: usize, sigma_color: f64) -> Color {
+        let p = y * film.width + x;
+        let (color_p, normal_p, albedo_p, depth_p) = (image[p], film.normal[p], film.albedo[p], film.depth[p]);
+        let mut sum = Color::zeros();
+        let mut total_weight = 0.;
+        for (j, ky) in KERNEL.iter().enumerate() {
+            let qy = y as i64 + (j as i64 - 2) * step as i64;
+            if qy < 0 || qy >= film.height as i64 {
+                continue;
+            }
+            for (i, kx) in KERNEL.iter().enumerate() {
+                let qx = x as i64 + (i as i64 - 2) * step as i64;
+                if qx < 0 || qx >= film.width as i64 {
+                    continue;
+                }
+                let q = qy as usize * film.width + qx as usize;
+                let depth_q = film.depth[q];
+                // Background pixels only mix with other background pixels.
+                let depth_difference = match (depth_p.is_finite(), depth_q.is_finite()) {
+                    (true, true) => (depth_p - depth_q).abs() / depth_p.max(1e-6),
+                    (false, false) => 0.,
+                    _ => continue,
+                };
+                let weight = kx * ky
+                    * (-squared(&(color_p - image[q])) / (sigma_color * sigma_color)).exp()
+                    * (-squared(&(normal_p - film.normal[q])) / (self.sigma_normal * self.sigma_normal)).exp()
+                    * (-squared(&(albedo_p - film.albedo[q])) / (self.sigma_albedo * self.sigma_albedo)).exp()
+                    * (-(depth_difference * depth_difference) / (self.sigma_depth * self.sigma_depth)).exp();
+                sum += image[q] * weight;
+                total_weight += weight;
+            }
+        }
+        // The center pixel always has a positive weight.
+        sum / total_weight
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec3::Vec3;
+    use crate::sampler::{IndependentSampler, Sampler};
+
+    fn noisy_film(width: usize, height: usize) -> Film {
+        let mut film = Film::new(width, height);
+        let mut sampler = IndependentSampler::new(0);
+        for y in 0..height {
+            for x in 0..width {
+                let p = y * width + x;
+                // Two walls meeting at a crease in the middle, one lit twice as brightly.
+                let left = x < width / 2;
+                let brightness = if left { 0.25 } else { 0.5 };
+                let noise = sampler.uniform(-0.2, 0.2);
+                film.beauty[p] = Color::new(brightness + noise, brightness + noise, brightness + noise);
+                film.normal[p] = if left { Vec3::new(1., 0., 0.) } else { Vec3::new(0., 0., 1.) };
+                film.albedo[p] = Color::new(0.5, 0.5, 0.5);
+                film.depth[p] = 2.;
+            }
+        }
+        film
+    }
+
+    fn error(image: &[Color], film: &Film) -> f64 {
+        image.iter().enumerate()
+            .map(|(p, c)| {
+                let expected = if p % film.width < film.width / 2 { 0.25 } else { 0.5 };
+                (c.x - expected).abs()
+            })
+            .sum::<f64>() / image.len() as f64
+    }
+
+    #[test]
+    fn test_denoise_removes_noise_but_keeps_edges() {
+        let film = noisy_film(32, 16);
+        let denoised = Denoiser::default().denoise(&film);
+        assert!(error(&denoised, &film) < error(&film.beauty, &film) / 4.);
+        // The pixels either side of the crease stay apart.
+        let row = 8 * film.width;
+        assert!(denoised[row + 15].x < 0.3);
+        assert!(denoised[row + 16].x > 0.45);
+    }
+}
@@ -0,0 +1,446 @@
+use std::fs;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use std::time::Duration;
+
+use crate::camera::Camera;
+use crate::checkpoint::{invalid_data, read_u32, read_u64, write_u32, write_u64};
+use crate::renderer::{CancellationToken, RenderState, RenderStatus, Renderer};
+use crate::sampler::hash;
+use crate::scene::Scene;
+
+const MAGIC: &[u8; 8] = b"RAYSDIST";
+const VERSION: u32 = 2;
+// How often the coordinator checks for new workers and cancellation while it waits.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+// How long the coordinator waits on a worker before handing its bands to the others. A
+// worker sends nothing while it renders a batch, so this has to cover the slowest batch.
+const WORKER_TIMEOUT: Duration = Duration::from_secs(600);
+
+// Camera rays per side of the grid a scene's fingerprint traces.
+const FINGERPRINT_RAYS: u32 = 16;
+
+/// Command line flags whose value is a file the scene is loaded from.
+const FILE_FLAGS: [&str; 5] = ["--mesh", "--gltf", "--pbrt", "--volume", "--heightmap"];
+/// Command line flags, with a value, that only matter to the process they're given to.
+const LOCAL_FLAGS: [&str; 2] = ["--coordinator", "--worker"];
+
+/// What a worker needs to build the coordinator's scene: its command line, and the contents
+/// of the files the command line names, in order.
+///
+/// Files are sent on their own and saved under new names, so files they refer to in turn
+/// (a `.gltf` file's external buffers, or a pbrt file's PLY meshes) can't be found on the
+/// workers: such scenes fail to load there, and need a `.glb` or a self-contained file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SceneDescription {
+    pub args: Vec<String>,
+    pub files: Vec<Vec<u8>>,
+}
+
+impl SceneDescription {
+    /// Describes the scene of command line `args`, reading the files they name.
+    pub fn from_args(args: &[String]) -> io::Result<SceneDescription> {
+        let mut description = SceneDescription{args: Vec::new(), files: Vec::new()};
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if LOCAL_FLAGS.contains(&arg.as_str()) {
+                args.next();
+                continue;
+            }
+            description.args.push(arg.clone());
+            if FILE_FLAGS.contains(&arg.as_str()) {
+                if let Some(path) = args.next() {
+                    description.files.push(fs::read(path)?);
+                    description.args.push(path.clone());
+                }
+            }
+        }
+        Ok(description)
+    }
+
+    /// Saves the files in `dir`, keeping their names, and returns the command line with
+    /// their paths there.
+    pub fn unpack(&self, dir: &Path) -> io::Result<Vec<String>> {
+        let mut args = Vec::new();
+        let mut files = self.files.iter().enumerate();
+        let mut iter = self.args.iter();
+        while let Some(arg) = iter.next() {
+            args.push(arg.clone());
+            if FILE_FLAGS.contains(&arg.as_str()) {
+                let (path, (i, contents)) = match (iter.next(), files.next()) {
+                    (Some(path), Some(file)) => (path, file),
+                    _ => return Err(invalid_data("scene description is missing a file")),
+                };
+                // Numbered, in case two files have the same name. Loaders go by the extension.
+                let name = Path::new(path).file_name().ok_or_else(|| invalid_data("scene file has no name"))?;
+                let local = dir.join(format!("{}-{}", i, name.to_string_lossy()));
+                fs::write(&local, contents)?;
+                args.push(local.to_string_lossy().into_owned());
+            }
+        }
+        Ok(args)
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        write_u32(writer, self.args.len() as u32)?;
+        for arg in &self.args {
+            write_bytes(writer, arg.as_bytes())?;
+        }
+        write_u32(writer, self.files.len() as u32)?;
+        for file in &self.files {
+            write_bytes(writer, file)?;
+        }
+        Ok(())
+    }
+
+    fn read<R: Read>(reader: &mut R) -> io::Result<SceneDescription> {
+        let args = (0..read_u32(reader)?)
+            .map(|_| String::from_utf8(read_bytes(reader)?).map_err(|_| invalid_data("arguments must be UTF-8")))
+            .collect::<io::Result<_>>()?;
+        let files = (0..read_u32(reader)?).map(|_| read_bytes(reader)).collect::<io::Result<_>>()?;
+        Ok(SceneDescription{args, files})
+    }
+}
+
+fn write_bytes<W: Write>(writer: &mut W, bytes: &[u8]) -> io::Result<()> {
+    write_u64(writer, bytes.len() as u64)?;
+    writer.write_all(bytes)
+}
+
+fn read_bytes<R: Read>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let len = read_u64(reader)?;
+    let mut bytes = Vec::new();
+    // Read as it arrives rather than trust the length with an allocation up front.
+    reader.take(len).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 != len {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed mid-message"));
+    }
+    Ok(bytes)
+}
+
+/// Sums up a built scene, so that a worker which built a different one than the coordinator
+/// can be turned away: its number of lights and what a grid of rays from `camera` hits first.
+pub fn fingerprint(scene: &Scene, camera: &Camera) -> u64 {
+    let mut values = vec![scene.lights.len() as u64];
+    for i in 0..FINGERPRINT_RAYS * FINGERPRINT_RAYS {
+        let x = ((i % FINGERPRINT_RAYS) as f64 + 0.5) / FINGERPRINT_RAYS as f64;
+        let y = ((i / FINGERPRINT_RAYS) as f64 + 0.5) / FINGERPRINT_RAYS as f64;
+        match scene.world.hit_object(&camera.get_ray(x, y, 0.5), 0.001, f64::INFINITY) {
+            Some((object, rec)) => {
+                let material = scene.material_index(rec.material).map_or(0, |material| material as u64 + 1);
+                values.extend([object as u64 + 1, material, rec.t.to_bits()]);
+            }
+            None => values.push(0),
+        }
+    }
+    hash(&values)
+}
+
+/// Bands waiting to be handed out, shared by the coordinator's connections.
+struct Queue {
+    bands: Mutex<Vec<usize>>,
+    finished: AtomicBool,
+}
+
+/// Renders on workers that connect to `listener`, handing them out bands of rows of the
+/// image a few at a time, and merges the samples they send back into a render state. A
+/// worker that fails has its bands handed to the others. Stops early if `cancel` is
+/// cancelled, with the bands finished so far.
+///
+/// The protocol, after the magic `RAYSDIST` and a u32 version from both sides: the
+/// coordinator sends the scene description; the worker sends its renderer's settings, its
+/// scene's u64 fingerprint and how many bands it renders at a time. Then the coordinator sends a u32 count of bands and
+/// their u32 indices, and the worker sends back each band's index and its samples, until
+/// the coordinator sends a count of 0.
+pub fn coordinate(listener: TcpListener, description: SceneDescription, renderer: &Renderer, scene: &Scene, camera: &Camera, cancel: &CancellationToken) -> io::Result<(RenderState, RenderStatus)> {
+    let mut state = renderer.start();
+    let band_count = state.band_count();
+    let queue = Arc::new(Queue{bands: Mutex::new((0..band_count).rev().collect()), finished: AtomicBool::new(false)});
+    let description = Arc::new(description);
+    let settings = Arc::new(renderer.settings());
+    let fingerprint = fingerprint(scene, camera);
+    let (results, received) = mpsc::channel();
+    listener.set_nonblocking(true)?;
+    let mut remaining = band_count;
+    let status = loop {
+        if remaining == 0 {
+            break RenderStatus::Complete;
+        }
+        if cancel.is_cancelled() {
+            break RenderStatus::Cancelled;
+        }
+        match listener.accept() {
+            Ok((stream, address)) => {
+                eprintln!("Worker {} connected", address);
+                let (queue, description, settings, results) = (queue.clone(), description.clone(), settings.clone(), results.clone());
+                thread::spawn(move || {
+                    if let Err(e) = serve(stream, &description, (&settings, fingerprint), &queue, &results, WORKER_TIMEOUT) {
+                        eprintln!("Worker {} failed: {}", address, e);
+                    }
+                });
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+            Err(e) => return Err(e),
+        }
+        if let Ok((index, samples)) = received.recv_timeout(POLL_INTERVAL) {
+            state.read_band(index, &mut samples.as_slice())?;
+            remaining -= 1;
+        }
+    };
+    queue.finished.store(true, Ordering::Relaxed);
+    renderer.first_hits(scene, camera, &mut state);
+    Ok((state, status))
+}
+
+/// Hands bands out to one worker until there are none left, sending the samples it renders
+/// on to the coordinator. Gives up on a worker that goes quiet for longer than `timeout`.
+/// The worker's renderer settings and scene fingerprint must match `expected`.
+fn serve(stream: TcpStream, description: &SceneDescription, expected: (&str, u64), queue: &Queue, results: &Sender<(usize, Vec<u8>)>, timeout: Duration) -> io::Result<()> {
+    // Some platforms pass the listener's non-blocking mode on.
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+    writer.write_all(MAGIC)?;
+    write_u32(&mut writer, VERSION)?;
+    description.write(&mut writer)?;
+    writer.flush()?;
+    read_header(&mut reader)?;
+    let worker_settings = String::from_utf8_lossy(&read_bytes(&mut reader)?).into_owned();
+    let worker_fingerprint = read_u64(&mut reader)?;
+    let batch_size = read_u32(&mut reader)?.max(1) as usize;
+    let (settings, fingerprint) = expected;
+    let mismatch = if worker_settings != settings {
+        Some(format!("the worker's settings differ: {}", worker_settings))
+    } else if worker_fingerprint != fingerprint {
+        Some("the worker's scene differs".to_string())
+    } else {
+        None
+    };
+    if let Some(mismatch) = mismatch {
+        write_u32(&mut writer, 0)?;
+        writer.flush()?;
+        return Err(invalid_data(&mismatch));
+    }
+    while !queue.finished.load(Ordering::Relaxed) {
+        let batch: Vec<usize> = {
+            let mut bands = queue.bands.lock().unwrap();
+            let start = bands.len().saturating_sub(batch_size);
+            bands.split_off(start)
+        };
+        if batch.is_empty() {
+            // Others are rendering the last bands; wait in case one of them fails.
+            thread::sleep(POLL_INTERVAL);
+            continue;
+        }
+        if let Err(e) = render_batch(&mut reader, &mut writer, &batch, results) {
+            queue.bands.lock().unwrap().extend(batch);
+            return Err(e);
+        }
+    }
+    write_u32(&mut writer, 0)?;
+    writer.flush()
+}
+
+fn render_batch<R: Read, W: Write>(reader: &mut R, writer: &mut W, batch: &[usize], results: &Sender<(usize, Vec<u8>)>) -> io::Result<()> {
+    write_u32(writer, batch.len() as u32)?;
+    for &index in batch {
+        write_u32(writer, index as u32)?;
+    }
+    writer.flush()?;
+    let mut samples = Vec::new();
+    for _ in batch {
+        let index = read_u32(reader)? as usize;
+        if !batch.contains(&index) {
+            return Err(invalid_data("the worker sent a band it wasn't given"));
+        }
+        samples.push((index, read_bytes(reader)?));
+    }
+    // Only hand the samples over once the whole batch is in, so a band is never merged twice.
+    for result in samples {
+        let _ = results.send(result);
+    }
+    Ok(())
+}
+
+fn read_header<R: Read>(reader: &mut R) -> io::Result<()> {
+    let mut magic = [0u8; 8];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC || read_u32(reader)? != VERSION {
+        return Err(invalid_data("not a compatible raytracer on the other end"));
+    }
+    Ok(())
+}
+
+/// Connects to the coordinator at `address`, builds its scene with `setup` and renders
+/// the bands it hands out until it has none left. Returns the number of bands rendered.
+pub fn work<F>(address: &str, setup: F) -> io::Result<usize>
+    where F: FnOnce(&SceneDescription) -> io::Result<(Scene, Camera, Renderer)>
+{
+    let stream = TcpStream::connect(address)?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+    read_header(&mut reader)?;
+    let description = SceneDescription::read(&mut reader)?;
+    let (scene, camera, renderer) = setup(&description)?;
+    writer.write_all(MAGIC)?;
+    write_u32(&mut writer, VERSION)?;
+    write_bytes(&mut writer, renderer.settings().as_bytes())?;
+    write_u64(&mut writer, fingerprint(&scene, &camera))?;
+    write_u32(&mut writer, rayon::current_num_threads() as u32)?;
+    writer.flush()?;
+    let mut rendered = 0;
+    loop {
+        let count = read_u32(&mut reader)?;
+        if count == 0 {
+            return Ok(rendered);
+        }
+        let mut state = renderer.start();
+        let batch = (0..count).map(|_| read_u32(&mut reader).map(|index| index as usize)).collect::<io::Result<Vec<_>>>()?;
+        if batch.iter().any(|&index| index >= state.band_count()) {
+            return Err(invalid_data("the coordinator asked for a band the image doesn't have"));
+        }
+        renderer.render_bands(&scene, &camera, &mut state, &batch);
+        for &index in &batch {
+            let mut samples = Vec::new();
+            state.write_band(index, &mut samples)?;
+            write_u32(&mut writer, index as u32)?;
+            write_bytes(&mut writer, &samples)?;
+        }
+        writer.flush()?;
+        rendered += batch.len();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::film::Film;
+    use crate::hittable::HittableList;
+    use crate::material::{Dielectric, Lambertian};
+    use crate::sphere::Sphere;
+    use crate::vec3::Color;
+
+    fn setup(_: &SceneDescription) -> io::Result<(Scene, Camera, Renderer)> {
+        let scene = Scene::new(HittableList::new(vec![
+            Box::new(Sphere::new(0., -100.5, -1., 100., Box::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))))),
+            Box::new(Sphere::new(0., 0., -1., 0.5, Box::new(Dielectric::new(1.5)))),
+        ]), Vec::new());
+        Ok((scene, Camera::new(90., 2.), Renderer::new(16, 8, 10, 20)))
+    }
+
+    #[test]
+    fn test_workers_render_the_same_image() {
+        let (scene, camera, renderer) = setup(&SceneDescription{args: Vec::new(), files: Vec::new()}).unwrap();
+        let mut local = Film::new(16, 8);
+        renderer.draw(&scene, &camera, &mut local);
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let workers: Vec<_> = (0..2).map(|_| {
+            let address = address.clone();
+            thread::spawn(move || work(&address, setup).unwrap())
+        }).collect();
+        let description = SceneDescription{args: vec!["--samples".to_string(), "20".to_string()], files: Vec::new()};
+        let (state, status) = coordinate(listener, description, &renderer, &scene, &camera, &CancellationToken::new()).unwrap();
+        assert_eq!(status, RenderStatus::Complete);
+        let rendered: usize = workers.into_iter().map(|worker| worker.join().unwrap()).sum();
+        assert_eq!(rendered, state.band_count());
+
+        let mut film = Film::new(16, 8);
+        renderer.resolve(&state, &mut film);
+        assert_eq!(film.beauty, local.beauty);
+        assert_eq!(film.samples, local.samples);
+        assert_eq!(film.depth, local.depth);
+    }
+
+    #[test]
+    fn test_worker_with_another_scene_is_turned_away() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let coordinator = thread::spawn(move || {
+            let description = SceneDescription{args: Vec::new(), files: Vec::new()};
+            let (scene, camera, renderer) = setup(&description).unwrap();
+            let (state, status) = coordinate(listener, description, &renderer, &scene, &camera, &CancellationToken::new()).unwrap();
+            assert_eq!(status, RenderStatus::Complete);
+            state.band_count()
+        });
+        // As if the glass sphere's file had failed to load on this worker.
+        let rendered = work(&address, |description| {
+            let (_, camera, renderer) = setup(description)?;
+            let scene = Scene::new(HittableList::new(vec![
+                Box::new(Sphere::new(0., -100.5, -1., 100., Box::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))))),
+            ]), Vec::new());
+            Ok((scene, camera, renderer))
+        }).unwrap();
+        assert_eq!(rendered, 0);
+        let rendered = work(&address, setup).unwrap();
+        assert_eq!(rendered, coordinator.join().unwrap());
+    }
+
+    #[test]
+    fn test_hanging_worker_has_its_bands_requeued() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let worker = thread::spawn(move || {
+            let stream = TcpStream::connect(address).unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut writer = BufWriter::new(stream);
+            read_header(&mut reader).unwrap();
+            SceneDescription::read(&mut reader).unwrap();
+            writer.write_all(MAGIC).unwrap();
+            write_u32(&mut writer, VERSION).unwrap();
+            write_bytes(&mut writer, b"settings").unwrap();
+            write_u64(&mut writer, 7).unwrap();
+            write_u32(&mut writer, 2).unwrap();
+            writer.flush().unwrap();
+            // Take a batch, then never answer.
+            let count = read_u32(&mut reader).unwrap();
+            for _ in 0..count {
+                read_u32(&mut reader).unwrap();
+            }
+            thread::sleep(Duration::from_secs(1));
+        });
+        let (stream, _) = listener.accept().unwrap();
+        let queue = Queue{bands: Mutex::new(vec![2, 1, 0]), finished: AtomicBool::new(false)};
+        let (results, received) = mpsc::channel();
+        let description = SceneDescription{args: Vec::new(), files: Vec::new()};
+        let error = serve(stream, &description, ("settings", 7), &queue, &results, Duration::from_millis(100)).unwrap_err();
+        assert!(matches!(error.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut));
+        let mut bands = queue.bands.lock().unwrap().clone();
+        bands.sort_unstable();
+        assert_eq!(bands, vec![0, 1, 2]);
+        assert!(received.try_recv().is_err());
+        worker.join().unwrap();
+    }
+
+    #[test]
+    fn test_scene_description_carries_files() {
+        let dir = std::env::temp_dir().join(format!("raytracing-distributed-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mesh = dir.join("cube.ply");
+        fs::write(&mesh, b"ply contents").unwrap();
+        let args: Vec<String> = ["--worker", "host:1", "--mesh", mesh.to_str().unwrap(), "--samples", "4"].iter().map(|s| s.to_string()).collect();
+        let description = SceneDescription::from_args(&args).unwrap();
+        assert_eq!(description.files, vec![b"ply contents".to_vec()]);
+
+        let mut sent = Vec::new();
+        description.write(&mut sent).unwrap();
+        let received = SceneDescription::read(&mut sent.as_slice()).unwrap();
+        assert_eq!(received, description);
+
+        let unpacked_dir = dir.join("unpacked");
+        fs::create_dir_all(&unpacked_dir).unwrap();
+        let unpacked = received.unpack(&unpacked_dir).unwrap();
+        let local = unpacked_dir.join("0-cube.ply");
+        assert_eq!(unpacked, vec!["--mesh", local.to_str().unwrap(), "--samples", "4"]);
+        assert_eq!(fs::read(&local).unwrap(), b"ply contents");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,250 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+const MAGIC: [u8; 4] = [0x76, 0x2f, 0x31, 0x01];
+// Version 2, single-part scanline image, short names.
+const VERSION: u32 = 2;
+
+/// The samples of one channel, row by row, with the pixel type they're stored as.
+pub enum Samples {
+    Uint(Vec<u32>),
+    Half(Vec<f32>),
+    Float(Vec<f32>),
+}
+
+impl Samples {
+    fn pixel_type(&self) -> i32 {
+        match self {
+            Samples::Uint(_) => 0,
+            Samples::Half(_) => 1,
+            Samples::Float(_) => 2,
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Samples::Uint(s) => s.len(),
+            Samples::Half(s) | Samples::Float(s) => s.len(),
+        }
+    }
+
+    fn write_row<W: Write>(&self, writer: &mut W, start: usize, width: usize) -> io::Result<()> {
+        match self {
+            Samples::Uint(s) => s[start..start + width].iter().try_for_each(|v| writer.write_all(&v.to_le_bytes())),
+            Samples::Half(s) => s[start..start + width].iter().try_for_each(|v| writer.write_all(&f32_to_f16(*v).to_le_bytes())),
+            Samples::Float(s) => s[start..start + width].iter().try_for_each(|v| writer.write_all(&v.to_le_bytes())),
+        }
+    }
+
+    fn bytes_per_sample(&self) -> usize {
+        if let Samples::Half(_) = self { 2 } else { 4 }
+    }
+}
+
+pub struct Channel {
+    /// Layered channels are named `layer.channel`, e.g. `N.X`.
+    pub name: String,
+    pub samples: Samples,
+}
+
+/// Header metadata values.
+pub enum Attribute {
+    Int(i32),
+    Float(f32),
+    Text(String),
+    V3f([f32; 3]),
+    /// A 4x4 matrix, row by row, transforming row vectors (the OpenEXR convention).
+    M44f([f32; 16]),
+}
+
+impl Attribute {
+    fn type_name(&self) -> &'static str {
+        match self {
+            Attribute::Int(_) => "int",
+            Attribute::Float(_) => "float",
+            Attribute::Text(_) => "string",
+            Attribute::V3f(_) => "v3f",
+            Attribute::M44f(_) => "m44f",
+        }
+    }
+
+    fn value(&self) -> Vec<u8> {
+        match self {
+            Attribute::Int(v) => v.to_le_bytes().to_vec(),
+            Attribute::Float(v) => v.to_le_bytes().to_vec(),
+            Attribute::Text(s) => s.as_bytes().to_vec(),
+            Attribute::V3f(v) => v.iter().flat_map(|x| x.to_le_bytes()).collect(),
+            Attribute::M44f(m) => m.iter().flat_map(|x| x.to_le_bytes()).collect(),
+        }
+    }
+}
+
+/// An uncompressed, single-part scanline OpenEXR image.
+pub struct ExrImage {
+    pub width: usize,
+    pub height: usize,
+    pub channels: Vec<Channel>,
+    pub attributes: Vec<(String, Attribute)>,
+}
+
+fn write_attribute<W: Write>(writer: &mut W, name: &str, type_name: &str, value: &[u8]) -> io::Result<()> {
+    writer.write_all(name.as_bytes())?;
+    writer.write_all(&[0])?;
+    writer.write_all(type_name.as_bytes())?;
+    writer.write_all(&[0])?;
+    writer.write_all(&(value.len() as i32).to_le_bytes())?;
+    writer.write_all(value)
+}
+
+fn box2i(width: usize, height: usize) -> Vec<u8> {
+    [0, 0, width as i32 - 1, height as i32 - 1].iter().flat_map(|v| v.to_le_bytes()).collect()
+}
+
+impl ExrImage {
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer)?;
+        writer.flush()
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        if self.channels.iter().any(|c| c.samples.len() != self.width * self.height) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "EXR channel size doesn't match the image"));
+        }
+        if self.channels.iter().map(|c| c.name.as_str()).chain(self.attributes.iter().map(|(n, _)| n.as_str())).any(|n| n.len() > 31) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "EXR names are limited to 31 characters"));
+        }
+        // Channels are stored sorted by name.
+        let mut channels: Vec<&Channel> = self.channels.iter().collect();
+        channels.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let mut header = Vec::new();
+        header.extend_from_slice(&MAGIC);
+        header.extend_from_slice(&VERSION.to_le_bytes());
+        let mut chlist = Vec::new();
+        for channel in channels.iter() {
+            chlist.extend_from_slice(channel.name.as_bytes());
+            chlist.push(0);
+            chlist.extend_from_slice(&channel.samples.pixel_type().to_le_bytes());
+            // pLinear and three reserved bytes, then x and y sampling.
+            chlist.extend_from_slice(&[0, 0, 0, 0]);
+            chlist.extend_from_slice(&1i32.to_le_bytes());
+            chlist.extend_from_slice(&1i32.to_le_bytes());
+        }
+        chlist.push(0);
+        write_attribute(&mut header, "channels", "chlist", &chlist)?;
+        write_attribute(&mut header, "compression", "compression", &[0])?;
+        write_attribute(&mut header, "dataWindow", "box2i", &box2i(self.width, self.height))?;
+        write_attribute(&mut header, "displayWindow", "box2i", &box2i(self.width, self.height))?;
+        write_attribute(&mut header, "lineOrder", "lineOrder", &[0])?;
+        write_attribute(&mut header, "pixelAspectRatio", "float", &1f32.to_le_bytes())?;
+        write_attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8])?;
+        write_attribute(&mut header, "screenWindowWidth", "float", &1f32.to_le_bytes())?;
+        for (name, attribute) in self.attributes.iter() {
+            write_attribute(&mut header, name, attribute.type_name(), &attribute.value())?;
+        }
+        header.push(0);
+        writer.write_all(&header)?;
+
+        // Offset table, then one scanline per block: its y, its size and each channel's row.
+        let row_size: usize = channels.iter().map(|c| c.samples.bytes_per_sample() * self.width).sum();
+        let block_size = 8 + row_size;
+        let first_block = header.len() + 8 * self.height;
+        for y in 0..self.height {
+            writer.write_all(&((first_block + y * block_size) as u64).to_le_bytes())?;
+        }
+        for y in 0..self.height {
+            writer.write_all(&(y as i32).to_le_bytes())?;
+            writer.write_all(&(row_size as i32).to_le_bytes())?;
+            for channel in channels.iter() {
+                channel.samples.write_row(writer, y * self.width, self.width)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Converts to IEEE 754 half precision, rounding to nearest even.
+pub fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+    if exponent == 0xff {
+        // Infinity stays infinity and NaN stays NaN.
+        return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
+    let half_exponent = exponent - 127 + 15;
+    if half_exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+    let round = |value: u32, shift: u32| {
+        let truncated = value >> shift;
+        let remainder = value & ((1 << shift) - 1);
+        let halfway = 1 << (shift - 1);
+        if remainder > halfway || (remainder == halfway && truncated & 1 == 1) { truncated + 1 } else { truncated }
+    };
+    if half_exponent <= 0 {
+        // Subnormal, or too small and flushed to zero.
+        if half_exponent < -10 {
+            return sign;
+        }
+        return sign | round(mantissa | 0x80_0000, (14 - half_exponent) as u32) as u16;
+    }
+    // Rounding up may carry into the exponent, which is still correct.
+    sign | round(((half_exponent as u32) << 23) | mantissa, 13) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+
+    #[test]
+    fn test_f32_to_f16() {
+        assert_eq!(f32_to_f16(0.), 0);
+        assert_eq!(f32_to_f16(1.), 0x3c00);
+        assert_eq!(f32_to_f16(0.5), 0x3800);
+        assert_eq!(f32_to_f16(-2.), 0xc000);
+        assert_eq!(f32_to_f16(65504.), 0x7bff);
+        assert_eq!(f32_to_f16(1e6), 0x7c00);
+        assert_eq!(f32_to_f16(f32::INFINITY), 0x7c00);
+        assert_eq!(f32_to_f16(f32::NAN) & 0x7e00, 0x7e00);
+        assert_eq!(f32_to_f16(2f32.powi(-24)), 0x0001);
+        assert_eq!(f32_to_f16(2f32.powi(-30)), 0);
+        // 1 + 2^-11 is halfway between two halves and rounds to the even one.
+        assert_eq!(f32_to_f16(1. + 2f32.powi(-11)), 0x3c00);
+        assert_eq!(f32_to_f16(1. + 3. * 2f32.powi(-11)), 0x3c02);
+    }
+
+    #[test]
+    fn test_write() {
+        let image = ExrImage{
+            width: 2,
+            height: 2,
+            channels: vec![
+                Channel{name: "Z".to_string(), samples: Samples::Float(vec![1., 2., 3., 4.])},
+                Channel{name: "R".to_string(), samples: Samples::Half(vec![1., 1., 1., 1.])},
+            ],
+            attributes: vec![("samples".to_string(), Attribute::Int(8))],
+        };
+        let mut bytes = Vec::new();
+        image.write(&mut bytes).unwrap();
+        assert_eq!(&bytes[..8], &[0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0]);
+        let text = String::from_utf8_lossy(&bytes);
+        assert!(text.find("R\0").unwrap() < text.find("Z\0").unwrap());
+        assert!(text.contains("samples\0int\0"));
+
+        // The second offset points at the block for row 1, which starts with its y and size.
+        let header_end = bytes.len() - 2 * 8 - 2 * (8 + 2 * 2 + 2 * 4);
+        let offset = u64::from_le_bytes(bytes[header_end + 8..header_end + 16].try_into().unwrap()) as usize;
+        assert_eq!(&bytes[offset..offset + 8], &[1, 0, 0, 0, 12, 0, 0, 0]);
+        // Half R first, then float Z.
+        assert_eq!(&bytes[offset + 8..offset + 12], &[0x00, 0x3c, 0x00, 0x3c]);
+        assert_eq!(&bytes[offset + 12..offset + 16], &3f32.to_le_bytes());
+
+        let mismatched = ExrImage{width: 3, height: 1, channels: vec![Channel{name: "R".to_string(), samples: Samples::Uint(vec![1])}], attributes: Vec::new()};
+        assert!(mismatched.write(&mut Vec::new()).is_err());
+    }
+}
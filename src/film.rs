@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::ops::Range;
+
+use crate::checkpoint::{read_f64, read_vec3, write_f64, write_vec3};
+
+use crate::filter::Filter;
+use crate::vec3::{Color, Vec3};
+
+/// What the renderer found for one pixel, before material IDs are numbered.
+#[derive(Debug, Copy, Clone)]
+pub struct FilmPixel {
+    pub color: Color,
+    pub depth: f64,
+    pub normal: Vec3,
+    pub albedo: Color,
+    /// Identifies the material instance that was hit, if any (its index in the scene).
+    pub material: Option<usize>,
+    pub object: Option<usize>,
+    /// Samples the renderer spent on the pixel.
+    pub samples: u32,
+}
+
+impl Default for FilmPixel {
+    fn default() -> FilmPixel {
+        FilmPixel{
+            color: Color::zeros(),
+            depth: f64::INFINITY,
+            normal: Vec3::zeros(),
+            albedo: Color::zeros(),
+            material: None,
+            object: None,
+            samples: 0,
+        }
+    }
+}
+
+/// The images produced by a render: the beauty pass plus auxiliary buffers (AOVs) taken
+/// from the first hit of the ray through each pixel's center. Buffers are stored row by row.
+pub struct Film {
+    pub width: usize,
+    pub height: usize,
+    /// Linear HDR radiance.
+    pub beauty: Vec<Color>,
+    /// Distance from the camera to the first hit, infinite for the background.
+    pub depth: Vec<f64>,
+    /// World-space normal at the first hit, facing the camera. Zero for the background.
+    pub normal: Vec<Vec3>,
+    pub albedo: Vec<Color>,
+    /// Materials numbered from 1 in the order they first appear in the image, 0 for the background.
+    pub material_id: Vec<u32>,
+    /// One more than the index of the top-level object that was hit, 0 for the background.
+    pub object_id: Vec<u32>,
+    /// Samples spent on each pixel, which vary with adaptive sampling.
+    pub samples: Vec<u32>,
+}
+
+impl Film {
+    pub fn new(width: usize, height: usize) -> Film {
+        let n = width * height;
+        Film{
+            width,
+            height,
+            beauty: vec![Color::zeros(); n],
+            depth: vec![f64::INFINITY; n],
+            normal: vec![Vec3::zeros(); n],
+            albedo: vec![Color::zeros(); n],
+            material_id: vec![0; n],
+            object_id: vec![0; n],
+            samples: vec![0; n],
+        }
+    }
+
+    /// Replaces the film's contents with freshly rendered pixels. Material IDs are assigned
+    /// here, in image order, so they don't depend on which thread got to a material first.
+    pub fn store(&mut self, pixels: &[FilmPixel]) {
+        assert_eq!(pixels.len(), self.width * self.height);
+        let mut material_ids = HashMap::new();
+        for (i, pixel) in pixels.iter().enumerate() {
+            self.beauty[i] = pixel.color;
+            self.depth[i] = pixel.depth;
+            self.normal[i] = pixel.normal;
+            self.albedo[i] = pixel.albedo;
+            self.material_id[i] = match pixel.material {
+                Some(material) => {
+                    let next = material_ids.len() as u32 + 1;
+                    *material_ids.entry(material).or_insert(next)
+                }
+                None => 0,
+            };
+            self.object_id[i] = pixel.object.map_or(0, |object| object as u32 + 1);
+            self.samples[i] = pixel.samples;
+        }
+    }
+
+    /// The auxiliary buffers by name, each with its number of channels and interleaved values.
+    pub fn aovs(&self) -> Vec<(&'static str, usize, Vec<f32>)> {
+        let colors = |buffer: &[Vec3]| buffer.iter().flat_map(|c| vec![c.x as f32, c.y as f32, c.z as f32]).collect();
+        vec![
+            ("depth", 1, self.depth.iter().map(|&d| d as f32).collect()),
+            ("normal", 3, colors(&self.normal)),
+            ("albedo", 3, colors(&self.albedo)),
+            ("material_id", 1, self.material_id.iter().map(|&id| id as f32).collect()),
+            ("object_id", 1, self.object_id.iter().map(|&id| id as f32).collect()),
+            ("samples", 1, self.samples.iter().map(|&n| n as f32).collect()),
+        ]
+    }
+}
+
+/// Filtered samples for a band of rows, plus the rows above and below it that the filter
+/// reaches. Bands are rendered in parallel, then their tiles are merged in order, which
+/// keeps the sums the same whichever thread rendered which band.
+pub struct SplatTile {
+    width: usize,
+    first_row: usize,
+    rows: usize,
+    sums: Vec<Color>,
+    weights: Vec<f64>,
+}
+
+impl SplatTile {
+    pub fn new(width: usize, height: usize, rows: Range<usize>, radius: f64) -> SplatTile {
+        let reach = radius.ceil() as usize;
+        let first_row = rows.start.saturating_sub(reach);
+        let rows = (rows.end + reach).min(height) - first_row;
+        SplatTile{width, first_row, rows, sums: vec![Color::zeros(); width * rows], weights: vec![0.; width * rows]}
+    }
+
+    /// Adds a sample at image position (`x`, `y`), in pixels, to every pixel whose center
+    /// is within the filter's radius.
+    pub fn add(&mut self, x: f64, y: f64, color: &Color, filter: &Filter) {
+        let range = |p: f64, start: usize, end: usize| {
+            let low = ((p - 0.5 - filter.radius).ceil().max(start as f64)) as usize;
+            let high = ((p - 0.5 + filter.radius).floor() + 1.).clamp(low as f64, end as f64) as usize;
+            low..high
+        };
+        for row in range(y, self.first_row, self.first_row + self.rows) {
+            for column in range(x, 0, self.width) {
+                let weight = filter.evaluate(x - (column as f64 + 0.5), y - (row as f64 + 0.5));
+                if weight != 0. {
+                    let i = (row - self.first_row) * self.width + column;
+                    self.sums[i] += weight * color;
+                    self.weights[i] += weight;
+                }
+            }
+        }
+    }
+
+    /// Writes the sums and weights of the samples splatted so far.
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        for (sum, weight) in self.sums.iter().zip(self.weights.iter()) {
+            write_vec3(writer, sum)?;
+            write_f64(writer, *weight)?;
+        }
+        Ok(())
+    }
+
+    /// Reads back the samples written by `write` into a tile of the same size.
+    pub fn read<R: Read>(&mut self, reader: &mut R) -> io::Result<()> {
+        for (sum, weight) in self.sums.iter_mut().zip(self.weights.iter_mut()) {
+            *sum = read_vec3(reader)?;
+            *weight = read_f64(reader)?;
+        }
+        Ok(())
+    }
+
+    /// Sums the tiles of a `width` by `height` image and normalizes by the filter weights.
+    pub fn merge<'a, I: IntoIterator<Item = &'a SplatTile>>(tiles: I, width: usize, height: usize) -> Vec<Color> {
+        let mut sums = vec![Color::zeros(); width * height];
+        let mut weights = vec![0.; width * height];
+        for tile in tiles {
+            let offset = tile.first_row * width;
+            for (i, (sum, weight)) in tile.sums.iter().zip(tile.weights.iter()).enumerate() {
+                sums[offset + i] += *sum;
+                weights[offset + i] += weight;
+            }
+        }
+        sums.iter().zip(weights).map(|(sum, weight)| if weight != 0. { sum / weight } else { Color::zeros() }).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_store_numbers_materials_in_image_order() {
+        let mut film = Film::new(3, 1);
+        let hit = |material, object| FilmPixel{material: Some(material), object: Some(object), depth: 1., ..FilmPixel::default()};
+        film.store(&[hit(0x2000, 4), FilmPixel::default(), hit(0x1000, 0)]);
+        assert_eq!(film.material_id, vec![1, 0, 2]);
+        assert_eq!(film.object_id, vec![5, 0, 1]);
+        assert_eq!(film.depth[1], f64::INFINITY);
+
+        let aovs = film.aovs();
+        let (name, channels, values) = &aovs[1];
+        assert_eq!((*name, *channels, values.len()), ("normal", 3, 9));
+    }
+
+    #[test]
+    fn test_splats_reach_neighbouring_bands() {
+        use crate::filter::FilterKind;
+        let white = Color::new(1., 1., 1.);
+        let box_filter = Filter::default();
+        let tent = Filter::new(FilterKind::Tent, 1.);
+        // Two bands of a 3x4 image; the tent reaches one row into the other band.
+        let mut top = SplatTile::new(3, 4, 0..2, tent.radius);
+        let mut bottom = SplatTile::new(3, 4, 2..4, tent.radius);
+        top.add(1.5, 2., &(2. * white), &tent);
+        bottom.add(0.5, 3.5, &(3. * white), &box_filter);
+        let image = SplatTile::merge(&[top, bottom], 3, 4);
+        // The tent sample sits on the border between rows 1 and 2, so it lands in both.
+        assert_eq!(image[4], 2. * white);
+        assert_eq!(image[7], 2. * white);
+        assert_eq!(image[9], 3. * white);
+        assert_eq!(image[0], Color::zeros());
+        assert_eq!(image[3], Color::zeros());
+    }
+}
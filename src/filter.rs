@@ -0,0 +1,140 @@
+use std::f64::consts::PI;
+use std::fmt;
+use std::str::FromStr;
+
+/// The shape of a pixel reconstruction filter.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FilterKind {
+    /// Every sample within the radius counts the same. With radius 0.5, each pixel
+    /// averages the samples over its own area.
+    Box,
+    /// Weights falling linearly to zero at the radius.
+    Tent,
+    /// A Gaussian with a standard deviation of a third of the radius, shifted down to
+    /// reach zero at the radius.
+    Gaussian,
+    /// The Mitchell-Netravali cubic with B = C = 1/3, whose small negative lobes sharpen.
+    Mitchell,
+    /// A sinc windowed by a wider sinc that reaches zero at the radius.
+    Lanczos,
+}
+
+impl FilterKind {
+    pub fn default_radius(&self) -> f64 {
+        match self {
+            FilterKind::Box => 0.5,
+            FilterKind::Tent => 1.,
+            FilterKind::Gaussian => 1.5,
+            FilterKind::Mitchell => 2.,
+            FilterKind::Lanczos => 3.,
+        }
+    }
+}
+
+impl FromStr for FilterKind {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<FilterKind, ()> {
+        match s {
+            "box" => Ok(FilterKind::Box),
+            "tent" => Ok(FilterKind::Tent),
+            "gaussian" => Ok(FilterKind::Gaussian),
+            "mitchell" => Ok(FilterKind::Mitchell),
+            "lanczos" => Ok(FilterKind::Lanczos),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for FilterKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            FilterKind::Box => "box",
+            FilterKind::Tent => "tent",
+            FilterKind::Gaussian => "gaussian",
+            FilterKind::Mitchell => "mitchell",
+            FilterKind::Lanczos => "lanczos",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A separable reconstruction filter that weights a sample by its offset, in pixels, from
+/// a pixel's center. Samples are splatted into every pixel within `radius` of them.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Filter {
+    pub kind: FilterKind,
+    pub radius: f64,
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-5 { 1. } else { (PI * x).sin() / (PI * x) }
+}
+
+impl Filter {
+    pub fn new(kind: FilterKind, radius: f64) -> Filter {
+        Filter{kind, radius}
+    }
+
+    pub fn evaluate(&self, dx: f64, dy: f64) -> f64 {
+        self.evaluate_1d(dx) * self.evaluate_1d(dy)
+    }
+
+    fn evaluate_1d(&self, x: f64) -> f64 {
+        let r = self.radius;
+        match self.kind {
+            // Half open, so a sample on the border between two pixels only lands in one.
+            FilterKind::Box => if -r <= x && x < r { 1. } else { 0. },
+            FilterKind::Tent => (1. - x.abs() / r).max(0.),
+            FilterKind::Gaussian => {
+                let sigma = r / 3.;
+                let gaussian = |x: f64| (-x * x / (2. * sigma * sigma)).exp();
+                (gaussian(x) - gaussian(r)).max(0.)
+            }
+            FilterKind::Mitchell => {
+                // The cubic is defined over [-2, 2].
+                let x = (2. * x / r).abs();
+                let (b, c) = (1. / 3., 1. / 3.);
+                let value = if x < 1. {
+                    (12. - 9. * b - 6. * c) * x.powi(3) + (-18. + 12. * b + 6. * c) * x * x + (6. - 2. * b)
+                } else if x < 2. {
+                    (-b - 6. * c) * x.powi(3) + (6. * b + 30. * c) * x * x + (-12. * b - 48. * c) * x + (8. * b + 24. * c)
+                } else {
+                    0.
+                };
+                value / 6.
+            }
+            FilterKind::Lanczos => if x.abs() < r { sinc(x) * sinc(x / r) } else { 0. },
+        }
+    }
+}
+
+impl Default for Filter {
+    fn default() -> Filter {
+        Filter::new(FilterKind::Box, FilterKind::Box.default_radius())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter_shapes() {
+        let filter = |kind: FilterKind| Filter::new(kind, kind.default_radius());
+        // Box splats into exactly one pixel.
+        assert_eq!(filter(FilterKind::Box).evaluate(-0.5, 0.2), 1.);
+        assert_eq!(filter(FilterKind::Box).evaluate(0.5, 0.2), 0.);
+        assert_eq!(filter(FilterKind::Tent).evaluate(0.5, 0.), 0.5);
+        for kind in [FilterKind::Tent, FilterKind::Gaussian, FilterKind::Mitchell, FilterKind::Lanczos].iter() {
+            let f = filter(*kind);
+            assert!(f.evaluate(0., 0.) > f.evaluate(0.3, 0.1), "{} peaks at the center", kind);
+            assert_eq!(f.evaluate(f.radius, 0.), 0., "{} reaches zero at its radius", kind);
+            assert_eq!(f.evaluate(0.3, 0.), f.evaluate(-0.3, 0.), "{} is symmetric", kind);
+        }
+        // Mitchell and Lanczos have negative lobes; Lanczos crosses zero at whole pixels.
+        assert!(filter(FilterKind::Mitchell).evaluate(1.5, 0.) < 0.);
+        assert!(filter(FilterKind::Lanczos).evaluate(1.5, 0.) < 0.);
+        assert!(filter(FilterKind::Lanczos).evaluate(1., 0.).abs() < 1e-12);
+    }
+}
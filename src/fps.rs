@@ -1,3 +1,7 @@
+use std::cell::Cell;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::{Duration, Instant};
 use std::vec::Vec;
 
@@ -12,7 +16,7 @@ pub fn timer() -> Timer {
         samples: Vec::new(),
     }
 }
-        
+
 
 impl Timer {
     pub fn start(&mut self) {
@@ -23,11 +27,24 @@ impl Timer {
         self.samples.push(Instant::now() - self.curr_start);
     }
 
+    /// The frame time below which `p` percent of the frames fall, by the nearest rank.
+    pub fn percentile(&self, p: f64) -> Duration {
+        let mut sorted = self.samples.clone();
+        sorted.sort();
+        let rank = (p / 100. * sorted.len() as f64).ceil() as usize;
+        sorted.get(rank.max(1) - 1).copied().unwrap_or_default()
+    }
+
+    pub fn mean(&self) -> Duration {
+        self.samples.iter().sum::<Duration>() / (self.samples.len().max(1) as u32)
+    }
+
     pub fn print_stats(&self) {
         println!("FPS Stats");
         println!("\tFrames: {}", self.samples.len());
-        let mean = self.samples.iter().sum::<Duration>().as_millis() / (self.samples.len() as u128);
-        println!("\tMean: {} ms", mean);
+        println!("\tMean: {} ms", self.mean().as_millis());
+        println!("\tMin: {} ms, max: {} ms", self.percentile(0.).as_millis(), self.percentile(100.).as_millis());
+        println!("\tp50: {} ms, p95: {} ms, p99: {} ms", self.percentile(50.).as_millis(), self.percentile(95.).as_millis(), self.percentile(99.).as_millis());
     }
 
     #[allow(dead_code)]
@@ -35,3 +52,231 @@ impl Timer {
         self.samples.clear();
     }
 }
+
+/// The events counted while rendering.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Counter {
+    /// Rays from the camera, including the ones for the auxiliary buffers.
+    PrimaryRays,
+    /// Rays scattered at a hit.
+    SecondaryRays,
+    /// Rays from a hit towards a light, to check it's visible.
+    ShadowRays,
+    /// Rays tested against a top-level object or a primitive in a BVH leaf.
+    IntersectionTests,
+    BvhNodesVisited,
+    /// Paths traced from the camera, one per pixel sample.
+    Paths,
+    /// Rays along those paths, camera rays included.
+    PathSegments,
+}
+
+const COUNTERS: usize = 7;
+
+/// Counts per thread, written only by their own thread, which others can read.
+struct ThreadCounters {
+    name: String,
+    counts: [AtomicU64; COUNTERS],
+    busy_nanos: AtomicU64,
+}
+
+impl ThreadCounters {
+    fn add(value: &AtomicU64, n: u64) {
+        // Only this thread writes, so a plain load and store is enough.
+        value.store(value.load(Ordering::Relaxed) + n, Ordering::Relaxed);
+    }
+}
+
+static THREADS: Mutex<Vec<Arc<ThreadCounters>>> = Mutex::new(Vec::new());
+
+thread_local! {
+    // Counted but not yet published in `LOCAL`, so counting costs next to nothing.
+    static PENDING: [Cell<u64>; COUNTERS] = const { [const { Cell::new(0) }; COUNTERS] };
+    static LOCAL: Arc<ThreadCounters> = {
+        let mut threads = THREADS.lock().unwrap();
+        let index = threads.len();
+        let name = std::thread::current().name().map_or_else(|| format!("thread {}", index), str::to_string);
+        let counters = Arc::new(ThreadCounters{name, counts: Default::default(), busy_nanos: AtomicU64::new(0)});
+        threads.push(counters.clone());
+        counters
+    };
+}
+
+/// Counts one `counter` event on this thread.
+pub fn count(counter: Counter) {
+    count_many(counter, 1);
+}
+
+/// Counts `n` `counter` events on this thread at once.
+pub fn count_many(counter: Counter, n: u64) {
+    PENDING.with(|pending| {
+        let count = &pending[counter as usize];
+        count.set(count.get() + n);
+    });
+}
+
+/// Publishes this thread's counts so far to snapshots, and adds to the time it has spent
+/// rendering.
+pub fn flush(busy: Duration) {
+    LOCAL.with(|local| {
+        PENDING.with(|pending| {
+            for (value, count) in local.counts.iter().zip(pending.iter()) {
+                ThreadCounters::add(value, count.replace(0));
+            }
+        });
+        ThreadCounters::add(&local.busy_nanos, busy.as_nanos() as u64);
+    });
+}
+
+/// Rays of any kind this thread has traced, published or not.
+pub fn thread_rays() -> u64 {
+    LOCAL.with(|local| {
+        PENDING.with(|pending| {
+            [Counter::PrimaryRays, Counter::SecondaryRays, Counter::ShadowRays].iter()
+                .map(|&counter| local.counts[counter as usize].load(Ordering::Relaxed) + pending[counter as usize].get())
+                .sum()
+        })
+    })
+}
+
+/// The counts of every thread at one point in time.
+#[derive(Debug, Clone, Default)]
+pub struct Snapshot {
+    // In the order the threads first counted something: name, counts and busy time.
+    threads: Vec<(String, [u64; COUNTERS], Duration)>,
+}
+
+impl Snapshot {
+    pub fn take() -> Snapshot {
+        let threads = THREADS.lock().unwrap();
+        Snapshot{threads: threads.iter().map(|thread| {
+            let mut counts = [0; COUNTERS];
+            for (count, value) in counts.iter_mut().zip(thread.counts.iter()) {
+                *count = value.load(Ordering::Relaxed);
+            }
+            (thread.name.clone(), counts, Duration::from_nanos(thread.busy_nanos.load(Ordering::Relaxed)))
+        }).collect()}
+    }
+
+    /// What was counted between `earlier` and this snapshot.
+    pub fn since(&self, earlier: &Snapshot) -> Snapshot {
+        Snapshot{threads: self.threads.iter().enumerate().map(|(i, (name, counts, busy))| {
+            match earlier.threads.get(i) {
+                Some((_, before, busy_before)) => {
+                    let mut delta = *counts;
+                    for (count, before) in delta.iter_mut().zip(before.iter()) {
+                        *count -= before;
+                    }
+                    (name.clone(), delta, *busy - *busy_before)
+                }
+                None => (name.clone(), *counts, *busy),
+            }
+        }).collect()}
+    }
+
+    /// The total of `counter` over all threads.
+    pub fn get(&self, counter: Counter) -> u64 {
+        self.threads.iter().map(|(_, counts, _)| counts[counter as usize]).sum()
+    }
+
+    pub fn rays(&self) -> u64 {
+        self.get(Counter::PrimaryRays) + self.get(Counter::SecondaryRays) + self.get(Counter::ShadowRays)
+    }
+
+    fn per_ray(&self, counter: Counter) -> f64 {
+        self.get(counter) as f64 / self.rays().max(1) as f64
+    }
+
+    fn average_path_length(&self) -> f64 {
+        self.get(Counter::PathSegments) as f64 / self.get(Counter::Paths).max(1) as f64
+    }
+
+    /// Threads that did any rendering, with their busy time.
+    fn busy_threads(&self) -> impl Iterator<Item = (&str, Duration)> {
+        self.threads.iter().filter(|(_, _, busy)| *busy > Duration::from_secs(0)).map(|(name, _, busy)| (name.as_str(), *busy))
+    }
+}
+
+/// The statistics of a render: what the threads did and how long the frames took.
+pub struct Statistics<'a> {
+    pub counters: &'a Snapshot,
+    pub frames: &'a Timer,
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1e3
+}
+
+impl Statistics<'_> {
+    pub fn print(&self) {
+        let c = self.counters;
+        self.frames.print_stats();
+        println!("Ray Stats");
+        println!("\tPrimary: {}, secondary: {}, shadow: {}", c.get(Counter::PrimaryRays), c.get(Counter::SecondaryRays), c.get(Counter::ShadowRays));
+        println!("\tIntersection tests per ray: {:.2}", c.per_ray(Counter::IntersectionTests));
+        println!("\tBVH nodes visited per ray: {:.2}", c.per_ray(Counter::BvhNodesVisited));
+        println!("\tAverage path length: {:.2}", c.average_path_length());
+        println!("Thread Stats");
+        for (name, busy) in c.busy_threads() {
+            println!("\t{}: busy {:.0} ms", name, millis(busy));
+        }
+    }
+
+    /// The statistics as a JSON object, for dashboards to pick up.
+    pub fn to_json(&self) -> String {
+        let (c, frames) = (self.counters, self.frames);
+        let mut json = String::from("{\n");
+        let _ = writeln!(json, "  \"frames\": {{\"count\": {}, \"mean_ms\": {:.3}, \"min_ms\": {:.3}, \"max_ms\": {:.3}, \"p50_ms\": {:.3}, \"p95_ms\": {:.3}, \"p99_ms\": {:.3}}},",
+            frames.samples.len(), millis(frames.mean()), millis(frames.percentile(0.)), millis(frames.percentile(100.)),
+            millis(frames.percentile(50.)), millis(frames.percentile(95.)), millis(frames.percentile(99.)));
+        let _ = writeln!(json, "  \"rays\": {{\"primary\": {}, \"secondary\": {}, \"shadow\": {}, \"total\": {}}},",
+            c.get(Counter::PrimaryRays), c.get(Counter::SecondaryRays), c.get(Counter::ShadowRays), c.rays());
+        let _ = writeln!(json, "  \"intersection_tests\": {},", c.get(Counter::IntersectionTests));
+        let _ = writeln!(json, "  \"intersection_tests_per_ray\": {:.3},", c.per_ray(Counter::IntersectionTests));
+        let _ = writeln!(json, "  \"bvh_nodes_visited\": {},", c.get(Counter::BvhNodesVisited));
+        let _ = writeln!(json, "  \"bvh_nodes_per_ray\": {:.3},", c.per_ray(Counter::BvhNodesVisited));
+        let _ = writeln!(json, "  \"paths\": {},", c.get(Counter::Paths));
+        let _ = writeln!(json, "  \"average_path_length\": {:.3},", c.average_path_length());
+        let threads: Vec<String> = c.busy_threads()
+            .map(|(name, busy)| format!("{{\"name\": \"{}\", \"busy_ms\": {:.3}}}", name.replace('\\', "\\\\").replace('"', "\\\""), millis(busy)))
+            .collect();
+        let _ = writeln!(json, "  \"threads\": [{}]", threads.join(", "));
+        json.push_str("}\n");
+        json
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentiles() {
+        let mut frames = timer();
+        frames.samples = (1..=100).rev().map(Duration::from_millis).collect();
+        assert_eq!(frames.percentile(0.), Duration::from_millis(1));
+        assert_eq!(frames.percentile(50.), Duration::from_millis(50));
+        assert_eq!(frames.percentile(95.), Duration::from_millis(95));
+        assert_eq!(frames.percentile(100.), Duration::from_millis(100));
+        assert_eq!(frames.mean(), Duration::from_micros(50_500));
+    }
+
+    #[test]
+    fn test_counters_per_thread() {
+        let before = Snapshot::take();
+        std::thread::spawn(|| {
+            count(Counter::PrimaryRays);
+            count(Counter::ShadowRays);
+            count(Counter::BvhNodesVisited);
+            flush(Duration::from_millis(3));
+        }).join().unwrap();
+        let counted = Snapshot::take().since(&before);
+        // Other tests may trace rays at the same time, but never visit fewer nodes.
+        assert!(counted.rays() >= 2);
+        assert!(counted.get(Counter::BvhNodesVisited) >= 1);
+        assert!(counted.busy_threads().any(|(_, busy)| busy == Duration::from_millis(3)));
+        let json = Statistics{counters: &counted, frames: &timer()}.to_json();
+        assert!(json.contains("\"bvh_nodes_visited\": "));
+        assert!(json.contains("\"busy_ms\": 3.000"));
+    }
+}
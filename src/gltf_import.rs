@@ -0,0 +1,277 @@
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use gltf::image::Format;
+use gltf::khr_lights_punctual::Kind;
+use gltf::mesh::Mode;
+
+use crate::camera::Camera;
+use crate::light::{DirectionalLight, Light, PointLight, SpotLight};
+use crate::matrix::{self, Matrix, IDENTITY, transform_normal, transform_point, transform_vector};
+use crate::mesh::{Mesh, TriangleMesh};
+use crate::pbr::PbrMaterial;
+use crate::scene::Lights;
+use crate::scenes::Objects;
+use crate::spectrum::srgb_to_linear;
+use crate::texture::ImageTexture;
+use crate::vec3::{Color, Point, Vec3};
+
+/// Everything imported from a glTF file: the geometry (flattened into world space), the
+/// punctual lights and the first camera, if there is one.
+pub struct GltfScene {
+    pub objects: Objects,
+    pub lights: Lights,
+    pub camera: Option<Camera>,
+}
+
+/// Loads the default scene (or the first one) of a `.gltf` or `.glb` file. Cameras are
+/// given the renderer's `aspect_ratio` rather than their own.
+pub fn load<P: AsRef<Path>>(path: P, aspect_ratio: f64) -> io::Result<GltfScene> {
+    let (document, buffers, images) = gltf::import(path).map_err(|e| match e {
+        gltf::Error::Io(e) => e,
+        e => io::Error::new(io::ErrorKind::InvalidData, e),
+    })?;
+    convert(&document, &buffers, &images, aspect_ratio)
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+struct Importer<'a> {
+    buffers: &'a [gltf::buffer::Data],
+    images: &'a [gltf::image::Data],
+    aspect_ratio: f64,
+    materials: HashMap<Option<usize>, PbrMaterial>,
+    // Keyed by image index and whether the image holds sRGB-encoded colors.
+    textures: HashMap<(usize, bool), Arc<ImageTexture>>,
+    scene: GltfScene,
+}
+
+fn convert(document: &gltf::Document, buffers: &[gltf::buffer::Data], images: &[gltf::image::Data], aspect_ratio: f64) -> io::Result<GltfScene> {
+    let mut importer = Importer{
+        buffers,
+        images,
+        aspect_ratio,
+        materials: HashMap::new(),
+        textures: HashMap::new(),
+        scene: GltfScene{objects: Vec::new(), lights: Vec::new(), camera: None},
+    };
+    if let Some(scene) = document.default_scene().or_else(|| document.scenes().next()) {
+        for node in scene.nodes() {
+            importer.visit(&node, &IDENTITY)?;
+        }
+    }
+    Ok(importer.scene)
+}
+
+impl<'a> Importer<'a> {
+    fn visit(&mut self, node: &gltf::Node, parent: &Matrix) -> io::Result<()> {
+        let local = node.transform().matrix();
+        let m = matrix::mul(parent, &local.map(|column| column.map(|v| v as f64)));
+
+        if let Some(mesh) = node.mesh() {
+            for primitive in mesh.primitives() {
+                self.add_primitive(&primitive, &m)?;
+            }
+        }
+        if let Some(camera) = node.camera() {
+            if let (None, gltf::camera::Projection::Perspective(perspective)) = (&self.scene.camera, camera.projection()) {
+                // glTF cameras look down their local -z axis with +y up.
+                let origin = transform_point(&m, &Point::zeros());
+                let forward = transform_vector(&m, &Vec3::new(0., 0., -1.));
+                let up = transform_vector(&m, &Vec3::new(0., 1., 0.));
+                let fov = (perspective.yfov() as f64).to_degrees();
+                self.scene.camera = Some(Camera::look_at(origin, origin + forward, up, fov, self.aspect_ratio));
+            }
+        }
+        if let Some(light) = node.light() {
+            let [r, g, b] = light.color();
+            let intensity = Color::new(r as f64, g as f64, b as f64) * light.intensity() as f64;
+            let position = transform_point(&m, &Point::zeros());
+            let direction = transform_vector(&m, &Vec3::new(0., 0., -1.)).unit_vec();
+            let light: Box<dyn Light + Send + Sync> = match light.kind() {
+                Kind::Directional => Box::new(DirectionalLight{direction, irradiance: intensity}),
+                Kind::Point => Box::new(PointLight{position, intensity}),
+                Kind::Spot{inner_cone_angle, outer_cone_angle} => Box::new(SpotLight{
+                    position,
+                    direction,
+                    intensity,
+                    inner_cone_angle: inner_cone_angle as f64,
+                    outer_cone_angle: outer_cone_angle as f64,
+                }),
+            };
+            self.scene.lights.push(light);
+        }
+
+        for child in node.children() {
+            self.visit(&child, &m)?;
+        }
+        Ok(())
+    }
+
+    fn add_primitive(&mut self, primitive: &gltf::Primitive, m: &Matrix) -> io::Result<()> {
+        if primitive.mode() != Mode::Triangles {
+            return Ok(());
+        }
+        let reader = primitive.reader(|buffer| Some(&self.buffers[buffer.index()]));
+        let positions: Vec<Point> = match reader.read_positions() {
+            Some(positions) => positions.map(|p| transform_point(m, &to_vec3(p))).collect(),
+            None => return Ok(()),
+        };
+        let normals: Option<Vec<Vec3>> = reader.read_normals()
+            .map(|normals| normals.map(|n| transform_normal(m, &to_vec3(n))).collect());
+        let colors: Option<Vec<Color>> = reader.read_colors(0)
+            .map(|colors| colors.into_rgb_f32().map(to_vec3).collect());
+        let uvs: Option<Vec<(f64, f64)>> = reader.read_tex_coords(0)
+            .map(|uvs| uvs.into_f32().map(|[u, v]| (u as f64, v as f64)).collect());
+        let vertex_count = positions.len();
+        if [normals.as_ref().map(Vec::len), colors.as_ref().map(Vec::len), uvs.as_ref().map(Vec::len)].iter().flatten().any(|&n| n != vertex_count) {
+            return Err(invalid_data("a primitive's attributes have different vertex counts"));
+        }
+        let indices: Vec<u32> = match reader.read_indices() {
+            Some(indices) => indices.into_u32().collect(),
+            None => (0..vertex_count as u32).collect(),
+        };
+        if indices.iter().any(|&i| i as usize >= vertex_count) {
+            return Err(invalid_data("a primitive's vertex index is out of range"));
+        }
+        let triangles = indices.chunks_exact(3).map(|t| [t[0], t[1], t[2]]).collect();
+
+        let mesh = TriangleMesh{positions, normals, colors, uvs, triangles};
+        let material = self.material(&primitive.material());
+        self.scene.objects.push(Box::new(Mesh::new(mesh, Box::new(material))));
+        Ok(())
+    }
+
+    fn material(&mut self, material: &gltf::Material) -> PbrMaterial {
+        if let Some(cached) = self.materials.get(&material.index()) {
+            return cached.clone();
+        }
+        let pbr = material.pbr_metallic_roughness();
+        let [r, g, b, _] = pbr.base_color_factor();
+        let [er, eg, eb] = material.emissive_factor();
+        let mut converted = PbrMaterial::new(
+            Color::new(r as f64, g as f64, b as f64), pbr.metallic_factor() as f64, pbr.roughness_factor() as f64);
+        converted.base_color_texture = pbr.base_color_texture().map(|info| self.texture(&info, true));
+        converted.metallic_roughness_texture = pbr.metallic_roughness_texture().map(|info| self.texture(&info, false));
+        converted.emissive = Color::new(er as f64, eg as f64, eb as f64);
+        converted.emissive_texture = material.emissive_texture().map(|info| self.texture(&info, true));
+        self.materials.insert(material.index(), converted.clone());
+        converted
+    }
+
+    fn texture(&mut self, info: &gltf::texture::Info, srgb: bool) -> Arc<ImageTexture> {
+        let index = info.texture().source().index();
+        let images = self.images;
+        self.textures.entry((index, srgb))
+            .or_insert_with(|| Arc::new(convert_image(&images[index], srgb)))
+            .clone()
+    }
+}
+
+fn convert_image(image: &gltf::image::Data, srgb: bool) -> ImageTexture {
+    let (channels, bytes_per_channel) = match image.format {
+        Format::R8 => (1, 1),
+        Format::R8G8 => (2, 1),
+        Format::R8G8B8 => (3, 1),
+        Format::R8G8B8A8 => (4, 1),
+        Format::R16 => (1, 2),
+        Format::R16G16 => (2, 2),
+        Format::R16G16B16 => (3, 2),
+        Format::R16G16B16A16 => (4, 2),
+        Format::R32G32B32FLOAT => (3, 4),
+        Format::R32G32B32A32FLOAT => (4, 4),
+    };
+    let channel = |bytes: &[u8]| -> f64 {
+        let value = match bytes_per_channel {
+            1 => bytes[0] as f64 / 255.,
+            2 => u16::from_le_bytes([bytes[0], bytes[1]]) as f64 / 65535.,
+            _ => return f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+        };
+        if srgb { srgb_to_linear(value) } else { value }
+    };
+    let pixels = image.pixels.chunks_exact(channels * bytes_per_channel)
+        .map(|pixel| {
+            let c: Vec<f64> = pixel.chunks_exact(bytes_per_channel).map(channel).collect();
+            match channels {
+                1 => Color::new(c[0], c[0], c[0]),
+                2 => Color::new(c[0], c[1], 0.),
+                _ => Color::new(c[0], c[1], c[2]),
+            }
+        })
+        .collect();
+    ImageTexture::new(image.width as usize, image.height as usize, pixels)
+}
+
+fn to_vec3([x, y, z]: [f32; 3]) -> Vec3 {
+    Vec3::new(x as f64, y as f64, z as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ray::Ray;
+
+    // One triangle in the xy plane, scaled by 2 and moved to z = -2, plus a camera and a light.
+    const TRIANGLE: &str = r#"{
+        "asset": {"version": "2.0"},
+        "extensionsUsed": ["KHR_lights_punctual"],
+        "extensions": {"KHR_lights_punctual": {"lights": [{"type": "point", "color": [1, 0.5, 0.5], "intensity": 2}]}},
+        "scene": 0,
+        "scenes": [{"nodes": [0, 1, 2]}],
+        "nodes": [
+            {"mesh": 0, "translation": [0, 0, -2], "scale": [2, 2, 2]},
+            {"camera": 0, "translation": [0, 0, 1]},
+            {"translation": [0, 3, 0], "extensions": {"KHR_lights_punctual": {"light": 0}}}
+        ],
+        "cameras": [{"type": "perspective", "perspective": {"yfov": 1.5707963, "znear": 0.1}}],
+        "materials": [{"pbrMetallicRoughness": {"baseColorFactor": [1, 0, 0, 1], "metallicFactor": 0}}],
+        "meshes": [{"primitives": [{"attributes": {"POSITION": 0}, "material": 0}]}],
+        "accessors": [{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                       "min": [0, 0, 0], "max": [1, 1, 0]}],
+        "bufferViews": [{"buffer": 0, "byteLength": 36}],
+        "buffers": [{"byteLength": 36, "uri": "data:application/octet-stream;base64,AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAA"}]
+    }"#;
+
+    #[test]
+    fn test_convert() {
+        let (document, buffers, images) = gltf::import_slice(TRIANGLE.as_bytes()).unwrap();
+        let scene = convert(&document, &buffers, &images, 1.).unwrap();
+        assert_eq!(scene.objects.len(), 1);
+        assert_eq!(scene.lights.len(), 1);
+
+        let r = Ray::new(Point::new(0.5, 0.5, 0.), Vec3::new(0., 0., -1.), 0.);
+        let rec = scene.objects[0].hit(&r, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 2.).abs() < 1e-6);
+        let outside = Ray::new(Point::new(1.5, 1.5, 0.), Vec3::new(0., 0., -1.), 0.);
+        assert!(scene.objects[0].hit(&outside, 0.001, f64::INFINITY).is_none());
+
+        let (_, distance, radiance) = scene.lights[0].illuminate(&Point::zeros()).unwrap();
+        assert!((distance - 3.).abs() < 1e-6);
+        assert!((radiance - Color::new(2., 1., 1.) / 9.).length() < 1e-6);
+
+        let center = scene.camera.unwrap().get_ray(0.5, 0.5, 0.);
+        assert!((center.origin - Point::new(0., 0., 1.)).length() < 1e-6);
+        assert!((center.dir.unit_vec() - Vec3::new(0., 0., -1.)).length() < 1e-6);
+    }
+
+    #[test]
+    fn test_malformed_primitives() {
+        // Indices 0x3f800000, 0 and 0 from the second position, and a normal for only one vertex.
+        let bad_index = TRIANGLE
+            .replace(r#""attributes": {"POSITION": 0}"#, r#""attributes": {"POSITION": 0}, "indices": 1"#)
+            .replace(r#""max": [1, 1, 0]}]"#, r#""max": [1, 1, 0]}, {"bufferView": 1, "componentType": 5125, "count": 3, "type": "SCALAR"}]"#)
+            .replace(r#""bufferViews": [{"buffer": 0, "byteLength": 36}]"#, r#""bufferViews": [{"buffer": 0, "byteLength": 36}, {"buffer": 0, "byteOffset": 12, "byteLength": 12}]"#);
+        let short_normals = TRIANGLE
+            .replace(r#""attributes": {"POSITION": 0}"#, r#""attributes": {"POSITION": 0, "NORMAL": 1}"#)
+            .replace(r#""max": [1, 1, 0]}]"#, r#""max": [1, 1, 0]}, {"bufferView": 0, "componentType": 5126, "count": 1, "type": "VEC3"}]"#);
+        for json in [bad_index, short_normals] {
+            let (document, buffers, images) = gltf::import_slice(json.as_bytes()).unwrap();
+            let error = convert(&document, &buffers, &images, 1.).err().unwrap();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+}
@@ -0,0 +1,310 @@
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::triangle::intersect_triangle;
+use crate::vec3::{Point, Vec3};
+
+// The most samples a height map may have, so a corrupt header can't ask for an absurd allocation.
+const MAX_SAMPLES: usize = 1 << 28;
+
+/// A regular grid of heights in [0, 1], `nx` samples wide and `nz` samples deep.
+pub struct HeightMap {
+    pub nx: usize,
+    pub nz: usize,
+    pub heights: Vec<f32>,
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// Splits the header of a PGM file into whitespace separated tokens, skipping comments.
+/// Consumes exactly one whitespace byte after the last token, as required by the format.
+fn pgm_header_tokens<R: Read>(reader: &mut R, count: usize) -> io::Result<Vec<String>> {
+    let mut tokens = Vec::new();
+    let mut token = String::new();
+    let mut in_comment = false;
+    let mut byte = [0u8; 1];
+    while tokens.len() < count {
+        reader.read_exact(&mut byte)?;
+        let c = byte[0] as char;
+        if in_comment {
+            in_comment = c != '\n';
+        } else if c == '#' {
+            in_comment = true;
+        } else if c.is_ascii_whitespace() {
+            if !token.is_empty() {
+                tokens.push(std::mem::take(&mut token));
+            }
+        } else {
+            token.push(c);
+        }
+    }
+    Ok(tokens)
+}
+
+impl HeightMap {
+    pub fn load_pgm<P: AsRef<Path>>(path: P) -> io::Result<HeightMap> {
+        HeightMap::read_pgm(&mut BufReader::new(File::open(path)?))
+    }
+
+    /// Reads a grayscale image in either ASCII (P2) or binary (P5) PGM format.
+    pub fn read_pgm<R: Read>(reader: &mut R) -> io::Result<HeightMap> {
+        let header = pgm_header_tokens(reader, 4)?;
+        let parse = |s: &str| s.parse::<usize>().map_err(|_| invalid_data("invalid PGM header"));
+        let (nx, nz, max_value) = (parse(&header[1])?, parse(&header[2])?, parse(&header[3])?);
+        if nx < 2 || nz < 2 || max_value == 0 || max_value > 65535 {
+            return Err(invalid_data("unsupported PGM dimensions or depth"));
+        }
+        let len = nx.checked_mul(nz).filter(|&len| len <= MAX_SAMPLES)
+            .ok_or_else(|| invalid_data("PGM image is too large"))?;
+        let raw: Vec<usize> = match header[0].as_str() {
+            "P2" => {
+                let mut text = String::new();
+                reader.read_to_string(&mut text)?;
+                text.split_whitespace().take(len).map(parse).collect::<io::Result<_>>()?
+            }
+            "P5" => {
+                let bytes_per_sample = if max_value < 256 { 1 } else { 2 };
+                let size = len.checked_mul(bytes_per_sample).ok_or_else(|| invalid_data("PGM image is too large"))?;
+                let mut bytes = vec![0u8; size];
+                reader.read_exact(&mut bytes)?;
+                if bytes_per_sample == 1 {
+                    bytes.iter().map(|&b| b as usize).collect()
+                } else {
+                    bytes.chunks_exact(2).map(|b| u16::from_be_bytes([b[0], b[1]]) as usize).collect()
+                }
+            }
+            _ => return Err(invalid_data("not a PGM file")),
+        };
+        if raw.len() != len {
+            return Err(invalid_data("truncated PGM data"));
+        }
+        let heights = raw.iter().map(|&v| (v.min(max_value) as f64 / max_value as f64) as f32).collect();
+        Ok(HeightMap{nx, nz, heights})
+    }
+
+    /// Generates fractal value noise (fBm) with the given number of octaves.
+    pub fn fbm(nx: usize, nz: usize, seed: u32, octaves: usize) -> HeightMap {
+        let mut heights = Vec::with_capacity(nx * nz);
+        let base_frequency = 4. / nx.max(nz) as f64;
+        for j in 0..nz {
+            for i in 0..nx {
+                let (mut value, mut amplitude, mut frequency, mut total) = (0., 0.5, base_frequency, 0.);
+                for octave in 0..octaves {
+                    let octave_seed = seed.wrapping_add(octave as u32 * 7919);
+                    value += amplitude * value_noise(i as f64 * frequency, j as f64 * frequency, octave_seed);
+                    total += amplitude;
+                    amplitude *= 0.5;
+                    frequency *= 2.;
+                }
+                heights.push((value / total) as f32);
+            }
+        }
+        HeightMap{nx, nz, heights}
+    }
+
+    fn height(&self, i: usize, j: usize) -> f64 {
+        self.heights[j * self.nx + i] as f64
+    }
+}
+
+fn lattice(x: i64, z: i64, seed: u32) -> f64 {
+    let mut h = (x as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15)
+        ^ (z as u64).wrapping_mul(0xc2b2_ae3d_27d4_eb4f)
+        ^ (seed as u64).wrapping_mul(0x1656_67b1_9e37_79f9);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    h ^= h >> 33;
+    (h >> 11) as f64 / (1u64 << 53) as f64
+}
+
+fn value_noise(x: f64, z: f64, seed: u32) -> f64 {
+    let (x0, z0) = (x.floor(), z.floor());
+    let smooth = |t: f64| t * t * (3. - 2. * t);
+    let (fx, fz) = (smooth(x - x0), smooth(z - z0));
+    let (ix, iz) = (x0 as i64, z0 as i64);
+    let top = lattice(ix, iz, seed) + fx * (lattice(ix + 1, iz, seed) - lattice(ix, iz, seed));
+    let bottom = lattice(ix, iz + 1, seed) + fx * (lattice(ix + 1, iz + 1, seed) - lattice(ix, iz + 1, seed));
+    top + fz * (bottom - top)
+}
+
+/// Terrain built from a height map stretched over `size` (x and z extents, and the height
+/// of a sample equal to 1) starting at corner `min`. Rays walk the grid cells they cross
+/// (2D DDA) and only test the two triangles of each visited cell.
+pub struct Heightfield {
+    map: HeightMap,
+    min: Point,
+    size: Vec3,
+    min_height: f64,
+    max_height: f64,
+    pub material: Box<dyn Material + Send + Sync>,
+}
+
+impl Heightfield {
+    pub fn new(map: HeightMap, min: Point, size: Vec3, material: Box<dyn Material + Send + Sync>) -> Heightfield {
+        let min_height = map.heights.iter().cloned().fold(f32::INFINITY, f32::min) as f64;
+        let max_height = map.heights.iter().cloned().fold(f32::NEG_INFINITY, f32::max) as f64;
+        Heightfield{map, min, size, min_height, max_height, material}
+    }
+
+    fn cell_size(&self) -> (f64, f64) {
+        (self.size.x / (self.map.nx - 1) as f64, self.size.z / (self.map.nz - 1) as f64)
+    }
+
+    fn vertex(&self, i: usize, j: usize) -> Point {
+        let (dx, dz) = self.cell_size();
+        Point::new(
+            self.min.x + i as f64 * dx,
+            self.min.y + self.map.height(i, j) * self.size.y,
+            self.min.z + j as f64 * dz,
+        )
+    }
+
+    /// Parametric interval of `r` inside the terrain's bounding box, clipped to [t_min, t_max].
+    fn clip(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64)> {
+        let lo = Point::new(self.min.x, self.min.y + self.min_height * self.size.y, self.min.z);
+        let hi = Point::new(self.min.x + self.size.x, self.min.y + self.max_height * self.size.y, self.min.z + self.size.z);
+        let (mut t0, mut t1) = (t_min, t_max);
+        for axis in 0..3 {
+            let inv_d = 1. / r.dir[axis];
+            let mut near = (lo[axis] - r.origin[axis]) * inv_d;
+            let mut far = (hi[axis] - r.origin[axis]) * inv_d;
+            if inv_d < 0. {
+                std::mem::swap(&mut near, &mut far);
+            }
+            t0 = if near > t0 { near } else { t0 };
+            t1 = if far < t1 { far } else { t1 };
+            if t1 < t0 {
+                return None;
+            }
+        }
+        Some((t0, t1))
+    }
+
+    fn hit_cell(&self, r: &Ray, i: usize, j: usize, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let v00 = self.vertex(i, j);
+        let v10 = self.vertex(i + 1, j);
+        let v01 = self.vertex(i, j + 1);
+        let v11 = self.vertex(i + 1, j + 1);
+        let mut closest: Option<(f64, Vec3)> = None;
+        for (a, b, c) in [(v00, v01, v11), (v00, v11, v10)].iter() {
+            let t_max = closest.map_or(t_max, |(t, _)| t);
+            if let Some((t, _, _)) = intersect_triangle(r, a, b, c, t_min, t_max) {
+                closest = Some((t, (b - a).cross(&(c - a)).unit_vec()));
+            }
+        }
+        let (t, outward_normal) = closest?;
+        let mut rec = HitRecord{p: r.at(t), normal: outward_normal, t, front_face: false, material: self.material.as_ref(), u: 0., v: 0., vertex_color: None};
+        rec.set_face_normal(r, &outward_normal);
+        Some(rec)
+    }
+}
+
+impl Hittable for Heightfield {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let (t0, t1) = self.clip(r, t_min, t_max)?;
+        let (dx, dz) = self.cell_size();
+        let (cells_x, cells_z) = (self.map.nx - 1, self.map.nz - 1);
+        let entry = r.at(t0);
+        let cell = |x: f64, size: f64, cells: usize| (((x / size).floor().max(0.)) as usize).min(cells - 1);
+        let mut i = cell(entry.x - self.min.x, dx, cells_x);
+        let mut j = cell(entry.z - self.min.z, dz, cells_z);
+
+        // Distance (in t) between cell boundaries, and to the next boundary, along x and z.
+        let axis = |dir: f64, origin: f64, start: f64, size: f64, index: usize| {
+            if dir > 0. {
+                (size / dir, (start + (index + 1) as f64 * size - origin) / dir)
+            } else if dir < 0. {
+                (-size / dir, (start + index as f64 * size - origin) / dir)
+            } else {
+                (f64::INFINITY, f64::INFINITY)
+            }
+        };
+        let (delta_x, mut next_x) = axis(r.dir.x, r.origin.x, self.min.x, dx, i);
+        let (delta_z, mut next_z) = axis(r.dir.z, r.origin.z, self.min.z, dz, j);
+
+        loop {
+            if let Some(rec) = self.hit_cell(r, i, j, t_min, t_max) {
+                return Some(rec);
+            }
+            if next_x < next_z {
+                if next_x > t1 || (r.dir.x > 0. && i + 1 >= cells_x) || (r.dir.x < 0. && i == 0) {
+                    return None;
+                }
+                i = if r.dir.x > 0. { i + 1 } else { i - 1 };
+                next_x += delta_x;
+            } else {
+                if next_z > t1 || (r.dir.z > 0. && j + 1 >= cells_z) || (r.dir.z < 0. && j == 0) {
+                    return None;
+                }
+                j = if r.dir.z > 0. { j + 1 } else { j - 1 };
+                next_z += delta_z;
+            }
+        }
+    }
+
+    fn materials<'a>(&'a self, materials: &mut Vec<&'a dyn Material>) {
+        materials.push(self.material.as_ref());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::vec3::Color;
+
+    fn flat(height: f32) -> HeightMap {
+        HeightMap{nx: 3, nz: 3, heights: vec![height; 9]}
+    }
+
+    #[test]
+    fn test_read_pgm() {
+        let ascii = b"P2\n# a comment\n2 2\n255\n0 255\n51 102\n";
+        let map = HeightMap::read_pgm(&mut &ascii[..]).unwrap();
+        assert_eq!((map.nx, map.nz), (2, 2));
+        assert_eq!(map.heights, vec![0., 1., 0.2, 0.4]);
+
+        let mut binary = b"P5 2 2 255\n".to_vec();
+        binary.extend_from_slice(&[0, 255, 51, 102]);
+        let map = HeightMap::read_pgm(&mut binary.as_slice()).unwrap();
+        assert_eq!(map.heights, vec![0., 1., 0.2, 0.4]);
+
+        assert!(HeightMap::read_pgm(&mut &b"P5 2 2 255\n\x00"[..]).is_err());
+        for header in [&b"P5 18446744073709551615 2 255\n"[..], &b"P5 65536 65536 65535\n"[..]] {
+            assert_eq!(HeightMap::read_pgm(&mut &header[..]).err().unwrap().kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn test_fbm_range() {
+        let map = HeightMap::fbm(16, 8, 7, 4);
+        assert_eq!(map.heights.len(), 16 * 8);
+        assert!(map.heights.iter().all(|&h| (0. ..=1.).contains(&h)));
+        assert_eq!(map.heights, HeightMap::fbm(16, 8, 7, 4).heights);
+    }
+
+    #[test]
+    fn test_hit_flat_terrain() {
+        let material = Box::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let terrain = Heightfield::new(flat(0.5), Point::new(-1., 0., -1.), Vec3::new(2., 2., 2.), material);
+
+        let down = Ray::new(Point::new(0.3, 5., 0.2), Vec3::new(0., -1., 0.), 0.);
+        let rec = terrain.hit(&down, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 4.).abs() < 1e-9);
+        assert!((rec.normal - Vec3::new(0., 1., 0.)).length() < 1e-9);
+
+        // A grazing ray has to walk through several cells before it reaches the surface.
+        let grazing = Ray::new(Point::new(-1., 1.2, -0.9), Vec3::new(0.5, -0.1, 0.5), 0.);
+        let rec = terrain.hit(&grazing, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.p.y - 1.).abs() < 1e-9);
+
+        let above = Ray::new(Point::new(-2., 1.5, 0.), Vec3::new(1., 0., 0.), 0.);
+        assert!(terrain.hit(&above, 0.001, f64::INFINITY).is_none());
+    }
+}
@@ -1,6 +1,8 @@
+use crate::fps::{self, Counter};
 use crate::ray::Ray;
 use crate::vec3::Point;
 use crate::vec3::Vec3;
+use crate::vec3::Color;
 use crate::material::Material;
 
 pub struct HitRecord<'a> {
@@ -9,16 +11,22 @@ pub struct HitRecord<'a> {
    pub t: f64,
    pub front_face: bool,
    pub material: &'a dyn Material,
+   pub u: f64,
+   pub v: f64,
+   pub vertex_color: Option<Color>,
 }
 
 pub trait Hittable {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord>;
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>>;
+
+    /// Adds every material the object's hits can have to `materials`, always in the same order.
+    fn materials<'a>(&'a self, materials: &mut Vec<&'a dyn Material>);
 }
 
 impl<'a> HitRecord<'a> {
     pub fn set_face_normal(&mut self, r: &Ray, outward_normal: &Vec3) {
-        self.front_face = r.dir.dot(&outward_normal) < 0.;
-        self.normal = if self.front_face { outward_normal.clone() } else { -outward_normal.clone() };
+        self.front_face = r.dir.dot(outward_normal) < 0.;
+        self.normal = if self.front_face { *outward_normal } else { -*outward_normal };
     }
 }
 
@@ -30,18 +38,30 @@ impl HittableList {
     pub fn new(objects: Vec<Box<dyn Hittable + Send  +Sync>>) -> HittableList {
         HittableList{objects}
     }
-}
 
-impl Hittable for HittableList {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        let mut rec: Option<HitRecord> = None;
+    /// Like `hit`, but also returns the index of the object that was hit.
+    pub fn hit_object(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<(usize, HitRecord<'_>)> {
+        let mut rec: Option<(usize, HitRecord)> = None;
         let mut closest_so_far = t_max;
-        for object in self.objects.iter() {
+        fps::count_many(Counter::IntersectionTests, self.objects.len() as u64);
+        for (index, object) in self.objects.iter().enumerate() {
             if let Some(new_hit) = object.hit(r, t_min, closest_so_far) {
                 closest_so_far = new_hit.t;
-                rec = Some(new_hit);
+                rec = Some((index, new_hit));
             }
         }
-        return rec;
+        rec
+    }
+}
+
+impl Hittable for HittableList {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        self.hit_object(r, t_min, t_max).map(|(_, rec)| rec)
+    }
+
+    fn materials<'a>(&'a self, materials: &mut Vec<&'a dyn Material>) {
+        for object in &self.objects {
+            object.materials(materials);
+        }
     }
 }
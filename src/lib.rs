@@ -0,0 +1,46 @@
+#[macro_use] extern crate auto_ops;
+
+pub mod bvh;
+pub mod camera;
+pub mod capi;
+pub mod checkpoint;
+pub mod config;
+pub mod denoise;
+pub mod distributed;
+pub mod exr;
+pub mod film;
+pub mod filter;
+pub mod fps;
+pub mod gltf_import;
+pub mod heightfield;
+pub mod hittable;
+pub mod light;
+pub mod material;
+pub mod matrix;
+pub mod mesh;
+pub mod moving_sphere;
+pub mod output;
+pub mod pbr;
+pub mod pbrt;
+pub mod ply;
+#[cfg(feature = "python")]
+mod python;
+pub mod progress_bar;
+pub mod ray;
+pub mod render_thread;
+pub mod renderer;
+pub mod sampler;
+pub mod scene;
+pub mod scene_builder;
+pub mod scenes;
+pub mod sdf;
+pub mod server;
+pub mod spectrum;
+pub mod stl;
+pub mod texture;
+pub mod tonemap;
+pub mod vec3;
+pub mod sphere;
+pub mod transform;
+pub mod triangle;
+pub mod volume;
@@ -0,0 +1,88 @@
+use crate::vec3::{Color, Point, Vec3};
+
+/// A light that can't be hit by rays (a point or direction in space), so it is only
+/// sampled directly at each shading point.
+pub trait Light {
+    /// Returns the unit direction from `p` towards the light, the distance to the light
+    /// and the radiance arriving at `p` from it, if the light reaches `p` at all.
+    fn illuminate(&self, p: &Point) -> Option<(Vec3, f64, Color)>;
+}
+
+pub struct PointLight {
+    pub position: Point,
+    pub intensity: Color,
+}
+
+impl Light for PointLight {
+    fn illuminate(&self, p: &Point) -> Option<(Vec3, f64, Color)> {
+        let to_light = self.position - p;
+        let distance = to_light.length();
+        Some((to_light / distance, distance, self.intensity / (distance * distance)))
+    }
+}
+
+/// A point light restricted to a cone around `direction`, with a smooth falloff between
+/// the inner and outer cone angles (in radians).
+pub struct SpotLight {
+    pub position: Point,
+    pub direction: Vec3,
+    pub intensity: Color,
+    pub inner_cone_angle: f64,
+    pub outer_cone_angle: f64,
+}
+
+impl Light for SpotLight {
+    fn illuminate(&self, p: &Point) -> Option<(Vec3, f64, Color)> {
+        let to_light = self.position - p;
+        let distance = to_light.length();
+        let wi = to_light / distance;
+        let cos_theta = (-wi).dot(&self.direction.unit_vec());
+        let (cos_inner, cos_outer) = (self.inner_cone_angle.cos(), self.outer_cone_angle.cos());
+        if cos_theta <= cos_outer {
+            return None;
+        }
+        let falloff = if cos_theta >= cos_inner { 1. } else { (cos_theta - cos_outer) / (cos_inner - cos_outer) };
+        Some((wi, distance, self.intensity * (falloff * falloff / (distance * distance))))
+    }
+}
+
+/// A light infinitely far away, shining along `direction`.
+pub struct DirectionalLight {
+    pub direction: Vec3,
+    pub irradiance: Color,
+}
+
+impl Light for DirectionalLight {
+    fn illuminate(&self, _p: &Point) -> Option<(Vec3, f64, Color)> {
+        Some((-self.direction.unit_vec(), f64::INFINITY, self.irradiance))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_point_light_falls_off() {
+        let light = PointLight{position: Point::new(0., 2., 0.), intensity: Color::new(4., 4., 4.)};
+        let (wi, distance, radiance) = light.illuminate(&Point::zeros()).unwrap();
+        assert_eq!(wi, Vec3::new(0., 1., 0.));
+        assert_eq!(distance, 2.);
+        assert_eq!(radiance, Color::new(1., 1., 1.));
+    }
+
+    #[test]
+    fn test_spot_light_cone() {
+        let light = SpotLight{
+            position: Point::new(0., 1., 0.),
+            direction: Vec3::new(0., -1., 0.),
+            intensity: Color::new(1., 1., 1.),
+            inner_cone_angle: 0.2,
+            outer_cone_angle: 0.4,
+        };
+        assert_eq!(light.illuminate(&Point::zeros()).unwrap().2, Color::new(1., 1., 1.));
+        let (_, _, edge) = light.illuminate(&Point::new(0.3, 0., 0.)).unwrap();
+        assert!(edge.x > 0. && edge.x < 1.);
+        assert!(light.illuminate(&Point::new(1., 0., 0.)).is_none());
+    }
+}
@@ -1,11 +1,15 @@
-#[macro_use] extern crate auto_ops;
 //extern crate raw_window_handle;
 extern crate pixels;
 extern crate winit;
-extern crate rand;
 extern crate rayon;
 
 
+use std::net::TcpListener;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
 use pixels::{Pixels, SurfaceTexture};
 use winit::dpi::LogicalSize;
 use winit::event::{Event, VirtualKeyCode};
@@ -13,44 +17,138 @@ use winit::event_loop::{ControlFlow, EventLoop};
 use winit::window::{Window, WindowBuilder};
 use winit_input_helper::WinitInputHelper;
 
-mod camera;
-mod fps;
-mod hittable;
-mod material;
-mod ray;
-mod renderer;
-mod vec3;
-mod sphere;
-
-use camera::Camera;
-use hittable::Hittable;
-use hittable::HittableList;
-use material::{Dielectric, Lambertian, Metal};
-use renderer::Renderer;
-use sphere::Sphere;
-use vec3::Vec3;
-use vec3::Color;
+use raytracing::{checkpoint, distributed, fps, gltf_import, matrix, output, pbrt, scenes, server};
+use raytracing::camera::Camera;
+use raytracing::config::Config;
+use raytracing::denoise::Denoiser;
+use raytracing::distributed::SceneDescription;
+use raytracing::exr::Attribute;
+use raytracing::film::Film;
+use raytracing::filter::Filter;
+use raytracing::heightfield::{HeightMap, Heightfield};
+use raytracing::hittable::HittableList;
+use raytracing::material::{Lambertian, TexturedLambertian};
+use raytracing::mesh::{Mesh, TriangleMesh};
+use raytracing::progress_bar::ProgressBar;
+use raytracing::render_thread::{RenderJob, RenderThread};
+use raytracing::renderer::{AdaptiveSampling, CancellationToken, ProgressCallback, RenderLimits, RenderState, RenderStatus, Renderer};
+use raytracing::scene::Scene;
+use raytracing::server::JobRequest;
+use raytracing::texture::VertexColor;
+use raytracing::tonemap::ToneMapper;
+use raytracing::vec3::{Color, Point, Vec3};
+use raytracing::volume::{HeterogeneousVolume, VoxelGrid};
 
 const ASPECT_RATIO: f64 = 16. / 9.;
-const VERTICAL_FOV_DEG: f64 = 40.;
+const VERTICAL_FOV_DEG: f64 = 90.;
 const WIDTH: u32 = 800;
 const HEIGHT: u32 = (WIDTH as f64 / ASPECT_RATIO) as u32;
-const SAMPLES_PER_PIXEL: usize = 100;
+// Keep showing previews until the camera has stood still this long.
+const REFINE_DELAY: Duration = Duration::from_millis(500);
 const MAX_RAY_DEPTH: usize = 50;
 
-fn construct_world() -> HittableList {
-    let material_ground = Box::new(Lambertian::new(Color::new(0.8, 0.8, 0.0)));
-    let material_center = Box::new(Dielectric{ir: -0.4});
-    let material_left = Box::new(Metal::new(Color::new(0.8, 0.8, 0.8), 0.3));
-    let material_right = Box::new(Metal::new(Color::new(0.8, 0.6, 0.2), 1.0));
+/// Builds the scene, plus the camera from the glTF or pbrt file if one was loaded and it has one.
+/// Files that fail to load are left out, with a message for each.
+fn construct_scene(config: &Config) -> (Scene, Option<Camera>, Vec<String>) {
+    let mut objects = scenes::by_name(&config.scene).expect("scene names are validated by Config");
+    let mut lights = Vec::new();
+    let mut camera = None;
+    let mut failures = Vec::new();
+
+    if let Some(path) = &config.gltf {
+        match gltf_import::load(path, ASPECT_RATIO) {
+            Ok(gltf) => {
+                objects = gltf.objects;
+                lights = gltf.lights;
+                camera = gltf.camera;
+            }
+            Err(e) => failures.push(format!("Failed to load glTF file {}: {}", path, e)),
+        }
+    }
+
+    if let Some(path) = &config.pbrt {
+        match pbrt::load(path, ASPECT_RATIO) {
+            Ok(pbrt) => {
+                for unsupported in pbrt.unsupported.iter() {
+                    eprintln!("Ignoring unsupported {} in {}", unsupported, path);
+                }
+                objects = pbrt.objects;
+                lights = pbrt.lights;
+                camera = pbrt.camera;
+            }
+            Err(e) => failures.push(format!("Failed to load pbrt file {}: {}", path, e)),
+        }
+    }
+
+    if let Some(path) = &config.volume {
+        match VoxelGrid::load(path) {
+            Ok(grid) => objects.push(Box::new(HeterogeneousVolume::new(
+                grid, Point::new(-1.5, -0.5, -2.5), Point::new(1.5, 1.5, -0.5),
+                config.volume_density, 0.8, config.volume_emission))),
+            Err(e) => failures.push(format!("Failed to load volume {}: {}", path, e)),
+        }
+    }
+
+    for path in config.meshes.iter() {
+        match TriangleMesh::load(path) {
+            Ok(mut mesh) => {
+                mesh.fit_to(Point::new(0., 0., -1.), 1.);
+                let material = Box::new(TexturedLambertian::new(Box::new(VertexColor{fallback: Color::new(0.7, 0.7, 0.7)})));
+                objects.push(Box::new(Mesh::new(mesh, material)));
+            }
+            Err(e) => failures.push(format!("Failed to load mesh {}: {}", path, e)),
+        }
+    }
+
+    if let Some(path) = &config.heightmap {
+        match HeightMap::load_pgm(path) {
+            Ok(map) => objects.push(Box::new(Heightfield::new(
+                map, Point::new(-20., -2., -40.), Vec3::new(40., config.heightmap_scale, 40.),
+                Box::new(Lambertian::new(Color::new(0.4, 0.5, 0.3)))))),
+            Err(e) => failures.push(format!("Failed to load height map {}: {}", path, e)),
+        }
+    }
+
+    (Scene::new(HittableList::new(objects), lights), camera, failures)
+}
+
+/// Creates the world, camera and renderer that `config` describes, and says which of its
+/// files failed to load.
+fn set_up(config: &Config) -> (Scene, Camera, Renderer, Vec<String>) {
+    let (scene, gltf_camera, failures) = construct_scene(config);
+    let mut camera = gltf_camera.unwrap_or_else(|| Camera::new(VERTICAL_FOV_DEG, ASPECT_RATIO));
+    camera.set_shutter(config.shutter_open, config.shutter_close);
+    let mut renderer = Renderer::new(WIDTH, HEIGHT, MAX_RAY_DEPTH, config.samples);
+    renderer.set_seed(config.seed);
+    renderer.set_sampler(config.sampler);
+    renderer.set_filter(Filter::new(config.filter, config.filter_radius.unwrap_or_else(|| config.filter.default_radius())));
+    renderer.set_adaptive(config.adaptive.map(|target_error| AdaptiveSampling{min_samples: config.min_samples, target_error}));
+    renderer.set_spectral(config.spectral);
+    if config.time_limit.is_some() {
+        // Keep adding passes until the deadline.
+        renderer.set_samples_per_pixel(u32::MAX as usize);
+    }
+    (scene, camera, renderer, failures)
+}
 
-    let mut objects: Vec<Box<dyn Hittable + Send + Sync>> = Vec::new();
-    objects.push(Box::new(Sphere::new( 0.0, -100.5, -1., 100.0, material_ground)));
-    objects.push(Box::new(Sphere::new( 0.0,    0.0, -1.,   0.5, material_center)));
-    objects.push(Box::new(Sphere::new(-1.0,    0.0, -1.0,   0.5, material_left)));
-    objects.push(Box::new(Sphere::new( 1.0,    0.0, -1.0,   0.5, material_right)));
+/// Like `set_up`, but fails if any of the files failed to load rather than render without them.
+fn set_up_all(config: &Config) -> std::io::Result<(Scene, Camera, Renderer)> {
+    match set_up(config) {
+        (scene, camera, renderer, failures) if failures.is_empty() => Ok((scene, camera, renderer)),
+        (_, _, _, failures) => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, failures.join("; "))),
+    }
+}
 
-    HittableList::new(objects)
+/// Sets up the scene a coordinator sent, with its files saved to a temporary directory.
+fn set_up_worker(description: &SceneDescription) -> std::io::Result<(Scene, Camera, Renderer)> {
+    let dir = std::env::temp_dir().join(format!("raytracing-worker-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let args = description.unpack(&dir)?;
+    let config = Config::parse(args.into_iter()).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    // Rendering without a file would send back bands of another scene.
+    let set_up = set_up_all(&config);
+    let _ = std::fs::remove_dir_all(&dir);
+    set_up
 }
 
 fn initialize_window(width: u32, height: u32) -> (EventLoop<()>, WinitInputHelper, Window) {
@@ -66,7 +164,8 @@ fn initialize_window(width: u32, height: u32) -> (EventLoop<()>, WinitInputHelpe
     (event_loop, input, window)
 }
 
-fn handle_navigation(input: &WinitInputHelper, camera: &mut Camera, window: &Window) {
+/// Moves the camera with WASD. Returns whether it moved.
+fn handle_navigation(input: &WinitInputHelper, camera: &mut Camera, window: &Window) -> bool {
     let mut camera_movement = Vec3::zeros();
     if input.key_pressed(VirtualKeyCode::W) {
         camera_movement.z = -0.1;
@@ -83,14 +182,244 @@ fn handle_navigation(input: &WinitInputHelper, camera: &mut Camera, window: &Win
     if camera_movement != Vec3::zeros() {
        camera.shift(&camera_movement);
        window.request_redraw();
+       return true;
+    }
+    false
+}
+
+/// Adjusts the tone mapping from the keyboard: T cycles the tone curve, [ and ] change the
+/// exposure by half a stop and G toggles dithering. Returns whether anything changed.
+fn handle_tone_mapping(input: &WinitInputHelper, tone_mapper: &mut ToneMapper) -> bool {
+    let before = *tone_mapper;
+    if input.key_pressed(VirtualKeyCode::T) {
+        tone_mapper.curve = tone_mapper.curve.next();
+    }
+    if input.key_pressed(VirtualKeyCode::LBracket) {
+        tone_mapper.exposure -= 0.5;
+    }
+    if input.key_pressed(VirtualKeyCode::RBracket) {
+        tone_mapper.exposure += 0.5;
+    }
+    if input.key_pressed(VirtualKeyCode::G) {
+        tone_mapper.dither = !tone_mapper.dither;
+    }
+    *tone_mapper != before
+}
+
+/// The scene file, or the name of the built-in scene, that was rendered.
+fn scene_name(config: &Config) -> &str {
+    config.pbrt.as_ref().or(config.gltf.as_ref()).unwrap_or(&config.scene)
+}
+
+/// Header metadata describing how an EXR render was made.
+fn exr_metadata(config: &Config, camera: &Camera, samples: u32, render_time: Duration) -> Vec<(String, Attribute)> {
+    let to_world = camera.camera_to_world();
+    let to_camera = matrix::inverse(&to_world).expect("camera bases are orthonormal");
+    // Column-major column-vector matrices have the same layout as OpenEXR's row-vector ones.
+    let mut world_to_camera = [0.; 16];
+    for (value, &v) in world_to_camera.iter_mut().zip(to_camera.iter().flatten()) {
+        *value = v as f32;
     }
+    vec![
+        ("samplesPerPixel".to_string(), Attribute::Int(samples as i32)),
+        ("renderTime".to_string(), Attribute::Float(render_time.as_secs_f32())),
+        ("worldToCamera".to_string(), Attribute::M44f(world_to_camera)),
+        ("cameraPosition".to_string(), Attribute::V3f([to_world[3][0] as f32, to_world[3][1] as f32, to_world[3][2] as f32])),
+        ("verticalFov".to_string(), Attribute::Float(camera.vertical_fov() as f32)),
+        ("scene".to_string(), Attribute::Text(scene_name(config).to_string())),
+    ]
+}
+
+/// Renders in this process until the render is complete, out of time or cancelled. The
+/// render in progress is checkpointed as it goes if asked for, and can start from a checkpoint.
+fn render_locally(config: &Config, renderer: &Renderer, scene: &Scene, camera: &Camera, cancel: &CancellationToken) -> std::io::Result<(RenderState, RenderStatus)> {
+    let mut state = match &config.checkpoint {
+        Some(checkpoint_path) if config.resume => checkpoint::load(checkpoint_path, renderer, scene_name(config))
+            .map_err(|e| std::io::Error::new(e.kind(), format!("can't resume from {}: {}", checkpoint_path, e)))?,
+        _ => renderer.start(),
+    };
+    let deadline = config.time_limit.map(|seconds| Instant::now() + Duration::from_secs_f64(seconds));
+    let status = loop {
+        let next_checkpoint = config.checkpoint.as_ref().map(|_| Instant::now() + Duration::from_secs_f64(config.checkpoint_interval));
+        let limits = RenderLimits{deadline, cancel: Some(cancel.clone())};
+        // Finish the pass under way before the next checkpoint, however short the interval,
+        // so every checkpoint gets further than the last.
+        let status = match renderer.render_pass(scene, camera, &mut state, &limits) {
+            Some(status) => status,
+            None => renderer.render(scene, camera, &mut state, &RenderLimits{deadline: deadline.into_iter().chain(next_checkpoint).min(), ..limits}),
+        };
+        if let Some(checkpoint_path) = &config.checkpoint {
+            if let Err(e) = checkpoint::save(checkpoint_path, renderer, &state, scene_name(config)) {
+                eprintln!("Failed to save checkpoint {}: {}", checkpoint_path, e);
+            }
+        }
+        // Keep going if it was only a checkpoint that was due.
+        if status != RenderStatus::TimedOut || deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            break status;
+        }
+    };
+    Ok((state, status))
+}
+
+/// Renders on the workers that connect to `address`.
+fn render_on_workers(address: &str, renderer: &Renderer, scene: &Scene, camera: &Camera, cancel: &CancellationToken) -> std::io::Result<(RenderState, RenderStatus)> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let description = SceneDescription::from_args(&args)?;
+    let listener = TcpListener::bind(address)?;
+    eprintln!("Waiting for workers on {}", listener.local_addr()?);
+    distributed::coordinate(listener, description, renderer, scene, camera, cancel)
+}
+
+/// Renders a single frame and saves it, plus the auxiliary buffers if asked for. EXR
+/// output keeps the linear image and stores the buffers as layers of the same file. With
+/// adaptive sampling, a heatmap of the samples spent per pixel is saved too. A render that
+/// runs out of time or is cancelled saves the image it has so far.
+fn render_to_file(config: &Config, path: &str, renderer: &Renderer, scene: &Scene, camera: &Camera, tone_mapper: &ToneMapper, cancel: CancellationToken) -> std::io::Result<()> {
+    let start = Instant::now();
+    let (state, status) = match &config.coordinator {
+        Some(address) => render_on_workers(address, renderer, scene, camera, &cancel)?,
+        None => render_locally(config, renderer, scene, camera, &cancel)?,
+    };
+    if status == RenderStatus::Cancelled {
+        eprintln!("Render cancelled, saving the image so far");
+    }
+    let mut film = Film::new(WIDTH as usize, HEIGHT as usize);
+    renderer.resolve(&state, &mut film);
+    let max_samples = film.samples.iter().copied().max().unwrap_or(0);
+    let beauty = if config.denoise { Denoiser::default().denoise(&film) } else { film.beauty.clone() };
+    if config.adaptive.is_some() {
+        let heatmap = output::heatmap(&film.samples, max_samples);
+        output::write_png(output::aov_path(Path::new(path), "samples", "png"), film.width, film.height, &heatmap)?;
+    }
+    if Path::new(path).extension().is_some_and(|e| e.eq_ignore_ascii_case("exr")) {
+        let metadata = exr_metadata(config, camera, max_samples, start.elapsed());
+        return output::write_exr(path, &film, &beauty, config.half, config.aovs, metadata);
+    }
+    let mut frame = vec![0; film.width * film.height * 4];
+    tone_mapper.apply(&beauty, &mut frame);
+    output::write_png(path, film.width, film.height, &frame)?;
+    if config.aovs {
+        for (name, channels, data) in film.aovs() {
+            output::write_pfm(output::aov_path(Path::new(path), name, "pfm"), film.width, film.height, channels, &data)?;
+        }
+    }
+    Ok(())
+}
+
+/// Renders a job for the HTTP service, in a temporary directory of its own that holds its
+/// scene and image until the image is read back.
+fn render_job(request: &JobRequest, progress: ProgressCallback, cancel: CancellationToken) -> std::io::Result<Vec<u8>> {
+    static JOBS: AtomicU64 = AtomicU64::new(0);
+    let job = JOBS.fetch_add(1, Ordering::Relaxed);
+    let dir = std::env::temp_dir().join(format!("raytracing-server-{}-{}", std::process::id(), job));
+    std::fs::create_dir_all(&dir)?;
+    let result = (|| {
+        let mut args = request.args.clone();
+        if let Some(pbrt) = &request.pbrt {
+            let scene_path = dir.join("scene.pbrt");
+            std::fs::write(&scene_path, pbrt)?;
+            // Fail the job rather than render the default scene in its place, or read
+            // meshes from elsewhere on the server.
+            pbrt::load_confined(&scene_path, ASPECT_RATIO)?;
+            args.extend(["--pbrt".to_string(), scene_path.to_string_lossy().into_owned()]);
+        }
+        let output_path = dir.join(if request.exr { "render.exr" } else { "render.png" });
+        let output_path = output_path.to_string_lossy().into_owned();
+        args.extend(["--output".to_string(), output_path.clone()]);
+        let config = Config::parse(args.into_iter()).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+        let (scene, camera, mut renderer) = set_up_all(&config)?;
+        renderer.set_progress(Some(progress));
+        let tone_mapper = ToneMapper::new(config.exposure, config.tone_curve, config.white_balance, config.dither);
+        render_to_file(&config, &output_path, &renderer, &scene, &camera, &tone_mapper, cancel)?;
+        std::fs::read(&output_path)
+    })();
+    let _ = std::fs::remove_dir_all(&dir);
+    result
 }
 
 fn main() -> Result<(), pixels::Error> {
+    let config = match Config::from_args() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+
+    if let Some(address) = &config.worker {
+        match distributed::work(address, set_up_worker) {
+            Ok(bands) => println!("Rendered {} bands for {}", bands, address),
+            Err(e) => {
+                eprintln!("Failed to render for {}: {}", address, e);
+                std::process::exit(1);
+            }
+        }
+        return Ok(());
+    }
+
+    if let Some(address) = &config.serve {
+        let result = TcpListener::bind(address).and_then(|listener| {
+            eprintln!("Serving renders on http://{}", listener.local_addr()?);
+            server::serve(listener, Box::new(render_job))
+        });
+        if let Err(e) = result {
+            eprintln!("Failed to serve renders on {}: {}", address, e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
     // Create world, camera, and renderer
-    let world = construct_world();
-    let mut camera = Camera::new(VERTICAL_FOV_DEG, ASPECT_RATIO);
-    let renderer = Renderer::new(WIDTH, HEIGHT, MAX_RAY_DEPTH, SAMPLES_PER_PIXEL);
+    let (scene, mut camera, mut renderer, failures) = set_up(&config);
+    for failure in failures {
+        eprintln!("{}", failure);
+    }
+    let mut tone_mapper = ToneMapper::new(config.exposure, config.tone_curve, config.white_balance, config.dither);
+
+    if let Some(path) = &config.output {
+        // Ctrl-C stops the render early and saves what it has so far.
+        let cancel = CancellationToken::new();
+        let handler_token = cancel.clone();
+        if let Err(e) = ctrlc::set_handler(move || handler_token.cancel()) {
+            eprintln!("Failed to handle Ctrl-C: {}", e);
+        }
+        let progress_bar = Arc::new(ProgressBar::new(config.time_limit.map(Duration::from_secs_f64)));
+        let bar = progress_bar.clone();
+        renderer.set_progress(Some(Arc::new(move |progress| bar.update(progress))));
+        let before = fps::Snapshot::take();
+        let mut timer = fps::timer();
+        let result = render_to_file(&config, path, &renderer, &scene, &camera, &tone_mapper, cancel);
+        timer.stop();
+        progress_bar.finish();
+        if let Err(e) = result {
+            eprintln!("Failed to render {}: {}", path, e);
+            std::process::exit(1);
+        }
+        let counters = fps::Snapshot::take().since(&before);
+        let statistics = fps::Statistics{counters: &counters, frames: &timer};
+        statistics.print();
+        if let Some(stats_path) = &config.stats {
+            if let Err(e) = std::fs::write(stats_path, statistics.to_json()) {
+                eprintln!("Failed to write {}: {}", stats_path, e);
+                std::process::exit(1);
+            }
+        }
+        return Ok(());
+    }
+
+    let mut film = renderer.new_film();
+    let denoiser = Denoiser::default();
+    let mut denoise = config.denoise;
+    // The image on screen before tone mapping: the film's beauty, denoised for previews.
+    let mut display = film.beauty.clone();
+    // Only camera changes need a new render; tone mapping changes reuse the last image.
+    // While the camera moves, render quick previews, then refine once it stops.
+    let mut next_render = Some(config.preview_samples);
+    // Whether the render thread is still working on the last render submitted.
+    let mut rendering = false;
+    let mut last_move = Instant::now();
+    let mut refined = false;
+    let mut sampler = config.sampler;
     let mut timer = fps::timer();
     
     // Set up window and event loop
@@ -101,14 +430,32 @@ fn main() -> Result<(), pixels::Error> {
     let surface_texture = SurfaceTexture::new(window_size.width, window_size.height, &window);
     let mut pixels = Pixels::new(WIDTH, HEIGHT, surface_texture)?;
 
+    // Render on another thread, which wakes the event loop whenever it has a new frame.
+    let proxy = event_loop.create_proxy();
+    let mut render_thread = RenderThread::spawn(renderer, Arc::new(scene), move || {
+        let _ = proxy.send_event(());
+    });
+
     // Event Loop
     event_loop.run(move |event, _, control_flow| {
+        // Show the render thread's latest frame
+        if let Event::UserEvent(()) = event {
+            if let Some(frame) = render_thread.latest_frame() {
+                if frame.complete {
+                    rendering = false;
+                    refined = frame.samples >= config.samples;
+                    timer.stop();
+                    timer.print_stats();
+                }
+                film = frame.film;
+                display = frame.display;
+                window.request_redraw();
+            }
+        }
+
         // Draw the current frame
         if let Event::RedrawRequested(_) = event {
-            timer.start();
-            renderer.draw(&world, &camera, pixels.get_frame());
-            timer.stop();
-            timer.print_stats();
+            tone_mapper.apply(&display, pixels.get_frame());
             if pixels.render().map_err(|e| println!("pixels.render() failed: {}", e)).is_err() {
                 *control_flow = ControlFlow::Exit;
                 return;
@@ -118,7 +465,36 @@ fn main() -> Result<(), pixels::Error> {
         // Handle input events
         if input.update(&event) {
             // Navigation events
-            handle_navigation(&input, &mut camera, &window);
+            if handle_navigation(&input, &mut camera, &window) {
+                next_render = Some(config.preview_samples);
+                last_move = Instant::now();
+            } else if !refined && !rendering && next_render.is_none() && last_move.elapsed() > REFINE_DELAY {
+                next_render = Some(config.samples);
+            }
+
+            // N toggles denoising of refined frames
+            if input.key_pressed(VirtualKeyCode::N) {
+                denoise = !denoise;
+                println!("Denoising {}", if denoise { "on" } else { "off" });
+                if refined {
+                    display = if denoise { denoiser.denoise(&film) } else { film.beauty.clone() };
+                }
+                window.request_redraw();
+            }
+
+            // M cycles through the samplers
+            if input.key_pressed(VirtualKeyCode::M) {
+                sampler = sampler.next();
+                println!("Sampler: {}", sampler);
+                next_render = Some(config.preview_samples);
+                last_move = Instant::now();
+            }
+
+            // Tone mapping events
+            if handle_tone_mapping(&input, &mut tone_mapper) {
+                println!("Tone mapping: {}", tone_mapper);
+                window.request_redraw();
+            }
 
             // Close events
             if input.key_pressed(VirtualKeyCode::Escape) || input.quit() {
@@ -129,7 +505,16 @@ fn main() -> Result<(), pixels::Error> {
             // Resize the window
             if let Some(size) = input.window_resized() {
                 pixels.resize(size.width, size.height);
+                window.request_redraw();
             }
         }
+
+        // Start a new render, replacing any stale one in progress
+        if let Some(samples) = next_render.take() {
+            timer.start();
+            refined = false;
+            rendering = true;
+            render_thread.submit(RenderJob{camera, samples, sampler, denoise: denoise || samples < config.samples});
+        }
     });
 }
@@ -1,10 +1,41 @@
 use crate::hittable::HitRecord;
 use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::texture::Texture;
 use crate::vec3::{Color, Vec3, reflect, refract};
-use rand::Rng;
 
 pub trait Material {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)>;
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<(Ray, Color)>;
+
+    fn emitted(&self, _rec: &HitRecord) -> Color {
+        Color::zeros()
+    }
+
+    /// The BSDF times the cosine term for light arriving from unit direction `wi`, used to
+    /// sample lights directly. Purely specular materials can't be lit this way and return black.
+    fn eval(&self, _r_in: &Ray, _rec: &HitRecord, _wi: &Vec3) -> Color {
+        Color::zeros()
+    }
+
+    /// The surface color at the hit, for the albedo AOV. Defaults to white, as for glass.
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        Color::new(1., 1., 1.)
+    }
+
+    /// Whether the directions `scatter` picks depend on the ray's wavelength, so a spectral
+    /// path through this material can only carry its hero wavelength.
+    fn is_dispersive(&self) -> bool {
+        false
+    }
+}
+
+fn lambert(albedo: Color, rec: &HitRecord, wi: &Vec3) -> Color {
+    let cos_theta = rec.normal.dot(wi);
+    if cos_theta > 0. {
+        albedo * (cos_theta / std::f64::consts::PI)
+    } else {
+        Color::zeros()
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -22,15 +53,53 @@ impl Lambertian {
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, _r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)> {
-        let mut scatter_direction = rec.normal + Vec3::random_unit_vector();
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<(Ray, Color)> {
+        let mut scatter_direction = rec.normal + Vec3::random_unit_vector(sampler);
         if scatter_direction.near_zero() {
             scatter_direction = rec.normal;
         }
-        let scattered = Ray::new(rec.p, scatter_direction);
+        let scattered = Ray::new(rec.p, scatter_direction, r_in.time);
         let attenuation = self.albedo;
         Some((scattered, attenuation))
     }
+
+    fn eval(&self, _r_in: &Ray, rec: &HitRecord, wi: &Vec3) -> Color {
+        lambert(self.albedo, rec, wi)
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        self.albedo
+    }
+}
+
+/// A diffuse material whose albedo is looked up from a texture at each hit.
+pub struct TexturedLambertian {
+    pub albedo: Box<dyn Texture + Send + Sync>,
+}
+
+impl TexturedLambertian {
+    pub fn new(albedo: Box<dyn Texture + Send + Sync>) -> TexturedLambertian {
+        TexturedLambertian{albedo}
+    }
+}
+
+impl Material for TexturedLambertian {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<(Ray, Color)> {
+        let mut scatter_direction = rec.normal + Vec3::random_unit_vector(sampler);
+        if scatter_direction.near_zero() {
+            scatter_direction = rec.normal;
+        }
+        let scattered = Ray::new(rec.p, scatter_direction, r_in.time);
+        Some((scattered, self.albedo.value(rec)))
+    }
+
+    fn eval(&self, _r_in: &Ray, rec: &HitRecord, wi: &Vec3) -> Color {
+        lambert(self.albedo.value(rec), rec, wi)
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.albedo.value(rec)
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -49,9 +118,9 @@ impl Metal {
 }
 
 impl Material for Metal {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)> {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<(Ray, Color)> {
         let reflected: Vec3 = reflect(&r_in.dir.unit_vec(), &rec.normal);
-        let scattered = Ray::new(rec.p, reflected + self.fuzz*Vec3::random_in_unit_sphere());
+        let scattered = Ray::new(rec.p, reflected + self.fuzz*Vec3::random_in_unit_sphere(sampler), r_in.time);
         let attenuation = self.albedo;
         if scattered.dir.dot(&rec.normal) > 0. {
             Some((scattered, attenuation))
@@ -59,10 +128,61 @@ impl Material for Metal {
             None
         }
     }
+
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        self.albedo
+    }
+}
+
+/// How a dielectric's index of refraction varies with wavelength.
+#[derive(Debug, Copy, Clone)]
+pub enum Dispersion {
+    /// n(λ) = a + b / λ², with λ in micrometres.
+    Cauchy{a: f64, b: f64},
+    /// n²(λ) = 1 + Σ bᵢ λ² / (λ² - cᵢ), with λ in micrometres.
+    Sellmeier{b: [f64; 3], c: [f64; 3]},
+}
+
+impl Dispersion {
+    /// Cauchy fit of Schott BK7 crown glass, which disperses weakly.
+    pub const CROWN: Dispersion = Dispersion::Cauchy{a: 1.5046, b: 0.00420};
+
+    /// Schott SF11 dense flint glass, which disperses strongly.
+    pub const DENSE_FLINT: Dispersion = Dispersion::Sellmeier{
+        b: [1.73759695, 0.313747346, 1.89878101],
+        c: [0.013188707, 0.0623068142, 155.23629],
+    };
+
+    /// Index of refraction at wavelength `lambda` (in nm).
+    pub fn ior(&self, lambda: f64) -> f64 {
+        let l = lambda / 1000.;
+        let l2 = l * l;
+        match self {
+            Dispersion::Cauchy{a, b} => a + b / l2,
+            Dispersion::Sellmeier{b, c} => {
+                (1. + (0..3).map(|i| b[i] * l2 / (l2 - c[i])).sum::<f64>()).sqrt()
+            }
+        }
+    }
 }
 
+/// Wavelength of the sodium D line (in nm), where glasses' nominal indices are measured.
+const SODIUM_D: f64 = 589.3;
+
 pub struct Dielectric {
     pub ir: f64,
+    /// When set, spectral rays refract with the index at their wavelength instead of `ir`.
+    pub dispersion: Option<Dispersion>,
+}
+
+impl Dielectric {
+    pub fn new(ir: f64) -> Dielectric {
+        Dielectric{ir, dispersion: None}
+    }
+
+    pub fn dispersive(dispersion: Dispersion) -> Dielectric {
+        Dielectric{ir: dispersion.ior(SODIUM_D), dispersion: Some(dispersion)}
+    }
 }
 
 unsafe impl Send for Dielectric {}
@@ -76,23 +196,46 @@ fn reflectance(cosine: f64, ref_idx: f64) -> f64 {
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)> {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<(Ray, Color)> {
         let attenuation = Color::new(1., 1., 1.);
-        let refraction_ratio = if rec.front_face { 1./self.ir } else { self.ir };
+        let ir = match (&self.dispersion, r_in.wavelength) {
+            (Some(dispersion), Some(lambda)) => dispersion.ior(lambda),
+            _ => self.ir,
+        };
+        let refraction_ratio = if rec.front_face { 1./ir } else { ir };
         let unit_direction = r_in.dir.unit_vec();
 
         let cos_theta = (-unit_direction).dot(&rec.normal).min(1.);
         let sin_theta = (1. - cos_theta*cos_theta).sqrt();
 
         let cannot_refract = refraction_ratio * sin_theta > 1.0;
-        let mut rng  = rand::thread_rng();
-        let direction = if cannot_refract || reflectance(cos_theta, refraction_ratio) > rng.gen_range(0.0, 1.0) {
+        let direction = if cannot_refract || reflectance(cos_theta, refraction_ratio) > sampler.get_1d() {
             reflect(&unit_direction, &rec.normal)
         } else {
             refract(&unit_direction, &rec.normal, refraction_ratio)
         };
 
-        let scattered = Ray::new(rec.p, direction);
+        let scattered = Ray::new(rec.p, direction, r_in.time);
         Some((scattered, attenuation))
     }
+
+    fn is_dispersive(&self) -> bool {
+        self.dispersion.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dispersion_is_normal() {
+        // Glass bends blue light more than red, and Cauchy and Sellmeier fits of BK7 agree.
+        let sellmeier = Dispersion::Sellmeier{b: [1.03961212, 0.231792344, 1.01046945], c: [0.00600069867, 0.0200179144, 103.560653]};
+        for dispersion in [sellmeier, Dispersion::CROWN].iter() {
+            assert!(dispersion.ior(450.) > dispersion.ior(650.));
+            assert!((dispersion.ior(SODIUM_D) - 1.517).abs() < 0.002);
+        }
+        assert!((Dielectric::dispersive(Dispersion::DENSE_FLINT).ir - 1.785).abs() < 0.002);
+    }
 }
@@ -0,0 +1,119 @@
+use crate::vec3::{Point, Vec3};
+
+/// A 4x4 affine transform, stored column-major (`m[column][row]`) as glTF and pbrt write them.
+pub type Matrix = [[f64; 4]; 4];
+
+pub const IDENTITY: Matrix = [[1., 0., 0., 0.], [0., 1., 0., 0.], [0., 0., 1., 0.], [0., 0., 0., 1.]];
+
+/// The product `a * b`, which applies `b` first.
+pub fn mul(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut m = [[0.; 4]; 4];
+    for (col, column) in m.iter_mut().enumerate() {
+        for (row, value) in column.iter_mut().enumerate() {
+            *value = (0..4).map(|k| a[k][row] * b[col][k]).sum();
+        }
+    }
+    m
+}
+
+pub fn translate(delta: &Vec3) -> Matrix {
+    let mut m = IDENTITY;
+    m[3] = [delta.x, delta.y, delta.z, 1.];
+    m
+}
+
+pub fn scale(factors: &Vec3) -> Matrix {
+    let mut m = IDENTITY;
+    m[0][0] = factors.x;
+    m[1][1] = factors.y;
+    m[2][2] = factors.z;
+    m
+}
+
+/// Rotation by `degrees` around `axis`, counter-clockwise when looking down the axis.
+pub fn rotate(degrees: f64, axis: &Vec3) -> Matrix {
+    let a = axis.unit_vec();
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let mut m = IDENTITY;
+    m[0] = [a.x * a.x + (1. - a.x * a.x) * cos, a.x * a.y * (1. - cos) + a.z * sin, a.x * a.z * (1. - cos) - a.y * sin, 0.];
+    m[1] = [a.x * a.y * (1. - cos) - a.z * sin, a.y * a.y + (1. - a.y * a.y) * cos, a.y * a.z * (1. - cos) + a.x * sin, 0.];
+    m[2] = [a.x * a.z * (1. - cos) + a.y * sin, a.y * a.z * (1. - cos) - a.x * sin, a.z * a.z + (1. - a.z * a.z) * cos, 0.];
+    m
+}
+
+/// Inverse of an affine transform, or `None` if it is singular.
+pub fn inverse(m: &Matrix) -> Option<Matrix> {
+    let c0 = Vec3::new(m[0][0], m[0][1], m[0][2]);
+    let c1 = Vec3::new(m[1][0], m[1][1], m[1][2]);
+    let c2 = Vec3::new(m[2][0], m[2][1], m[2][2]);
+    let det = c0.dot(&c1.cross(&c2));
+    if det.abs() < 1e-12 {
+        return None;
+    }
+    // The rows of the inverse of a 3x3 matrix are the cross products of its columns over the determinant.
+    let rows = [c1.cross(&c2) / det, c2.cross(&c0) / det, c0.cross(&c1) / det];
+    let mut inv = IDENTITY;
+    for (row, r) in rows.iter().enumerate() {
+        inv[0][row] = r.x;
+        inv[1][row] = r.y;
+        inv[2][row] = r.z;
+    }
+    let t = transform_vector(&inv, &Vec3::new(m[3][0], m[3][1], m[3][2]));
+    inv[3] = [-t.x, -t.y, -t.z, 1.];
+    Some(inv)
+}
+
+pub fn transform_point(m: &Matrix, p: &Point) -> Point {
+    transform_vector(m, p) + Vec3::new(m[3][0], m[3][1], m[3][2])
+}
+
+pub fn transform_vector(m: &Matrix, v: &Vec3) -> Vec3 {
+    Vec3::new(
+        m[0][0] * v.x + m[1][0] * v.y + m[2][0] * v.z,
+        m[0][1] * v.x + m[1][1] * v.y + m[2][1] * v.z,
+        m[0][2] * v.x + m[1][2] * v.y + m[2][2] * v.z,
+    )
+}
+
+/// Transforms a normal by the inverse transpose of the upper 3x3 of `m`, which is its
+/// cofactor matrix up to a scale that normalizing removes.
+pub fn transform_normal(m: &Matrix, n: &Vec3) -> Vec3 {
+    let c0 = Vec3::new(m[0][0], m[0][1], m[0][2]);
+    let c1 = Vec3::new(m[1][0], m[1][1], m[1][2]);
+    let c2 = Vec3::new(m[2][0], m[2][1], m[2][2]);
+    let cofactor = n.x * c1.cross(&c2) + n.y * c2.cross(&c0) + n.z * c0.cross(&c1);
+    // A mirroring transform flips the cofactors too.
+    let sign = if c0.dot(&c1.cross(&c2)) < 0. { -1. } else { 1. };
+    (sign * cofactor).unit_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transform_normal_non_uniform_scale() {
+        let m = scale(&Vec3::new(2., 1., 1.));
+        // The plane x + y = 0 has normal (1, 1, 0); stretching x tilts it towards y.
+        let n = transform_normal(&m, &Vec3::new(1., 1., 0.).unit_vec());
+        assert!((n - Vec3::new(1., 2., 0.).unit_vec()).length() < 1e-9);
+    }
+
+    #[test]
+    fn test_inverse() {
+        let m = mul(&translate(&Vec3::new(1., 2., 3.)), &mul(&rotate(30., &Vec3::new(0., 1., 1.)), &scale(&Vec3::new(2., 3., 4.))));
+        let product = mul(&m, &inverse(&m).unwrap());
+        for (col, column) in product.iter().enumerate() {
+            for (row, value) in column.iter().enumerate() {
+                assert!((value - IDENTITY[col][row]).abs() < 1e-9);
+            }
+        }
+        assert!(inverse(&scale(&Vec3::new(1., 0., 1.))).is_none());
+    }
+
+    #[test]
+    fn test_rotate() {
+        let p = transform_point(&rotate(90., &Vec3::new(0., 0., 1.)), &Point::new(1., 0., 0.));
+        assert!((p - Point::new(0., 1., 0.)).length() < 1e-9);
+    }
+}
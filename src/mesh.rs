@@ -0,0 +1,154 @@
+use std::io;
+use std::path::Path;
+
+use crate::bvh::{Aabb, Bvh};
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::triangle::intersect_triangle;
+use crate::vec3::{Color, Point, Vec3};
+use crate::{ply, stl};
+
+/// Indexed triangle geometry with optional per-vertex normals, colors and texture coordinates.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TriangleMesh {
+    pub positions: Vec<Point>,
+    pub normals: Option<Vec<Vec3>>,
+    pub colors: Option<Vec<Color>>,
+    pub uvs: Option<Vec<(f64, f64)>>,
+    pub triangles: Vec<[u32; 3]>,
+}
+
+impl TriangleMesh {
+    /// Loads a mesh, picking the format from the file extension (`.ply` or `.stl`).
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<TriangleMesh> {
+        let path = path.as_ref();
+        let extension = path.extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("ply") => ply::load(path),
+            Some("stl") => stl::load(path),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "unsupported mesh format")),
+        }
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        Aabb::from_points(&self.positions)
+    }
+
+    /// Uniformly scales and moves the mesh so its bounding box is centered on `center`
+    /// and its largest side is `size` long.
+    pub fn fit_to(&mut self, center: Point, size: f64) {
+        let bounds = self.bounds();
+        let extent = bounds.extent();
+        let largest = extent.x.max(extent.y).max(extent.z);
+        let scale = if largest > 0. { size / largest } else { 1. };
+        let old_center = bounds.centroid();
+        for p in self.positions.iter_mut() {
+            *p = center + (*p - old_center) * scale;
+        }
+    }
+}
+
+/// A triangle mesh with a BVH over its triangles.
+pub struct Mesh {
+    mesh: TriangleMesh,
+    bvh: Bvh,
+    pub material: Box<dyn Material + Send + Sync>,
+}
+
+impl Mesh {
+    pub fn new(mesh: TriangleMesh, material: Box<dyn Material + Send + Sync>) -> Mesh {
+        let bounds: Vec<Aabb> = mesh.triangles.iter()
+            .map(|tri| Aabb::from_points(&[
+                mesh.positions[tri[0] as usize],
+                mesh.positions[tri[1] as usize],
+                mesh.positions[tri[2] as usize],
+            ]))
+            .collect();
+        let bvh = Bvh::build(&bounds);
+        Mesh{mesh, bvh, material}
+    }
+
+    fn vertices(&self, triangle: usize) -> (usize, usize, usize) {
+        let [a, b, c] = self.mesh.triangles[triangle];
+        (a as usize, b as usize, c as usize)
+    }
+}
+
+impl Hittable for Mesh {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let positions = &self.mesh.positions;
+        let mut closest: Option<(usize, f64, f64, f64)> = None;
+        self.bvh.traverse(r, t_min, t_max, |triangle, t_max| {
+            let (a, b, c) = self.vertices(triangle);
+            let (t, b1, b2) = intersect_triangle(r, &positions[a], &positions[b], &positions[c], t_min, t_max)?;
+            closest = Some((triangle, t, b1, b2));
+            Some(t)
+        });
+        let (triangle, t, b1, b2) = closest?;
+
+        // Interpolate vertex attributes with the barycentric coordinates of the hit.
+        let (a, b, c) = self.vertices(triangle);
+        let b0 = 1. - b1 - b2;
+        let outward_normal = match &self.mesh.normals {
+            Some(normals) => (b0 * normals[a] + b1 * normals[b] + b2 * normals[c]).unit_vec(),
+            None => (positions[b] - positions[a]).cross(&(positions[c] - positions[a])).unit_vec(),
+        };
+        let vertex_color = self.mesh.colors.as_ref().map(|colors| b0 * colors[a] + b1 * colors[b] + b2 * colors[c]);
+        let (u, v) = match &self.mesh.uvs {
+            Some(uvs) => (
+                b0 * uvs[a].0 + b1 * uvs[b].0 + b2 * uvs[c].0,
+                b0 * uvs[a].1 + b1 * uvs[b].1 + b2 * uvs[c].1,
+            ),
+            None => (b1, b2),
+        };
+        let mut rec = HitRecord{p: r.at(t), normal: outward_normal, t, front_face: false, material: self.material.as_ref(), u, v, vertex_color};
+        rec.set_face_normal(r, &outward_normal);
+        Some(rec)
+    }
+
+    fn materials<'a>(&'a self, materials: &mut Vec<&'a dyn Material>) {
+        materials.push(self.material.as_ref());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+
+    fn quad() -> TriangleMesh {
+        TriangleMesh{
+            positions: vec![Point::new(-1., -1., 0.), Point::new(1., -1., 0.), Point::new(1., 1., 0.), Point::new(-1., 1., 0.)],
+            normals: None,
+            colors: Some(vec![Color::new(1., 0., 0.), Color::new(1., 0., 0.), Color::new(0., 0., 1.), Color::new(0., 0., 1.)]),
+            uvs: Some(vec![(0., 0.), (1., 0.), (1., 1.), (0., 1.)]),
+            triangles: vec![[0, 1, 2], [0, 2, 3]],
+        }
+    }
+
+    #[test]
+    fn test_hit() {
+        let mesh = Mesh::new(quad(), Box::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))));
+        let r = Ray::new(Point::new(0.5, 0., 2.), Vec3::new(0., 0., -1.), 0.);
+        let rec = mesh.hit(&r, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 2.).abs() < 1e-9);
+        assert!(rec.front_face);
+        assert!((rec.normal - Vec3::new(0., 0., 1.)).length() < 1e-9);
+        let color = rec.vertex_color.unwrap();
+        assert!((color - Color::new(0.5, 0., 0.5)).length() < 1e-9);
+        assert!((rec.u - 0.75).abs() < 1e-9 && (rec.v - 0.5).abs() < 1e-9);
+
+        let miss = Ray::new(Point::new(2., 0., 2.), Vec3::new(0., 0., -1.), 0.);
+        assert!(mesh.hit(&miss, 0.001, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn test_fit_to() {
+        let mut mesh = quad();
+        mesh.fit_to(Point::new(0., 0., -1.), 1.);
+        let bounds = mesh.bounds();
+        assert_eq!(bounds.min, Point::new(-0.5, -0.5, -1.));
+        assert_eq!(bounds.max, Point::new(0.5, 0.5, -1.));
+    }
+}
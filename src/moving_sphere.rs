@@ -0,0 +1,78 @@
+use crate::hittable::{Hittable, HitRecord};
+use crate::ray::Ray;
+use crate::material::Material;
+use crate::vec3::{Point, Vec3};
+
+/// A sphere whose center moves linearly from `center0` at `time0` to `center1` at `time1`.
+pub struct MovingSphere {
+    pub center0: Point,
+    pub center1: Point,
+    pub time0: f64,
+    pub time1: f64,
+    pub radius: f64,
+    pub material: Box<dyn Material + Send + Sync>
+}
+
+impl MovingSphere {
+    pub fn new(center0: Point, center1: Point, time0: f64, time1: f64, radius: f64, material: Box<dyn Material + Send + Sync>) -> MovingSphere {
+        MovingSphere{center0, center1, time0, time1, radius, material}
+    }
+
+    pub fn center(&self, time: f64) -> Point {
+        if self.time1 == self.time0 {
+            return self.center0;
+        }
+        self.center0 + ((time - self.time0) / (self.time1 - self.time0)) * (self.center1 - self.center0)
+    }
+}
+
+impl Hittable for MovingSphere {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let center = self.center(r.time);
+        let oc: Vec3 = r.origin - center;
+        let a = r.dir.length_squared();
+        let half_b = oc.dot(&r.dir);
+        let c = oc.length_squared() - self.radius*self.radius;
+        let discriminant = half_b*half_b - a*c;
+        if discriminant < 0. {
+            return None;
+        }
+        let sqrt_d = discriminant.sqrt();
+
+        let mut root = (-half_b - sqrt_d) / a;
+        if root < t_min || root > t_max {
+            root = (-half_b + sqrt_d) / a;
+            if root < t_min || root > t_max {
+                return None;
+            }
+        }
+        let p = r.at(root);
+        let outward_normal = (p - center) / self.radius;
+        let mut rec = HitRecord{p, normal: outward_normal, t: root, front_face: false, material: self.material.as_ref(), u: 0., v: 0., vertex_color: None};
+        rec.set_face_normal(r, &outward_normal);
+        Some(rec)
+    }
+
+    fn materials<'a>(&'a self, materials: &mut Vec<&'a dyn Material>) {
+        materials.push(self.material.as_ref());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::vec3::Color;
+
+    #[test]
+    fn test_hit_follows_time() {
+        let material = Box::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let sphere = MovingSphere::new(Point::new(0., 0., -2.), Point::new(2., 0., -2.), 0., 1., 0.5, material);
+        assert_eq!(sphere.center(0.5), Point::new(1., 0., -2.));
+
+        let r = Ray::new(Point::zeros(), Vec3::new(0., 0., -1.), 0.);
+        assert!(sphere.hit(&r, 0.001, f64::INFINITY).is_some());
+        let r = Ray::new(Point::zeros(), Vec3::new(0., 0., -1.), 1.);
+        assert!(sphere.hit(&r, 0.001, f64::INFINITY).is_none());
+    }
+}
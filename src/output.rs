@@ -0,0 +1,127 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::exr::{Attribute, Channel, ExrImage, Samples};
+use crate::film::Film;
+use crate::vec3::{Color, Vec3};
+
+/// Writes an 8-bit RGBA image as a PNG.
+pub fn write_png<P: AsRef<Path>>(path: P, width: usize, height: usize, rgba: &[u8]) -> io::Result<()> {
+    image::save_buffer(path, rgba, width as u32, height as u32, image::ExtendedColorType::Rgba8)
+        .map_err(io::Error::other)
+}
+
+/// Writes a 1- or 3-channel float image as a Portable Float Map.
+pub fn write_pfm<P: AsRef<Path>>(path: P, width: usize, height: usize, channels: usize, data: &[f32]) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_pfm_to(&mut writer, width, height, channels, data)?;
+    writer.flush()
+}
+
+/// PFM stores rows bottom to top; a negative scale marks little-endian floats.
+fn write_pfm_to<W: Write>(writer: &mut W, width: usize, height: usize, channels: usize, data: &[f32]) -> io::Result<()> {
+    let magic = match channels {
+        1 => "Pf",
+        3 => "PF",
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "PFM images have 1 or 3 channels")),
+    };
+    assert_eq!(data.len(), width * height * channels);
+    write!(writer, "{}\n{} {}\n-1.0\n", magic, width, height)?;
+    for row in data.chunks_exact(width * channels).rev() {
+        for value in row {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes the linear `beauty` as an OpenEXR image, with the film's auxiliary buffers as
+/// extra layers if `aovs` is set: `Z` depth, `N.X/Y/Z` normals, `albedo.R/G/B` and the
+/// `materialID`, `objectID` and `samples` buffers. Colors and normals are halves if `half` is set;
+/// depth is always a float and the IDs are unsigned integers.
+pub fn write_exr<P: AsRef<Path>>(path: P, film: &Film, beauty: &[Color], half: bool, aovs: bool, attributes: Vec<(String, Attribute)>) -> io::Result<()> {
+    exr_image(film, beauty, half, aovs, attributes).save(path)
+}
+
+fn exr_image(film: &Film, beauty: &[Color], half: bool, aovs: bool, attributes: Vec<(String, Attribute)>) -> ExrImage {
+    let color_samples = |values: Vec<f32>| if half { Samples::Half(values) } else { Samples::Float(values) };
+    let components = |layer: &str, names: [&str; 3], buffer: &[Vec3]| -> Vec<Channel> {
+        let prefix = if layer.is_empty() { String::new() } else { format!("{}.", layer) };
+        let component = |v: &Vec3, i| (match i { 0 => v.x, 1 => v.y, _ => v.z }) as f32;
+        names.iter().enumerate().map(|(i, name)| Channel{
+            name: format!("{}{}", prefix, name),
+            samples: color_samples(buffer.iter().map(|v| component(v, i)).collect()),
+        }).collect()
+    };
+    let mut channels = components("", ["R", "G", "B"], beauty);
+    if aovs {
+        channels.push(Channel{name: "Z".to_string(), samples: Samples::Float(film.depth.iter().map(|&d| d as f32).collect())});
+        channels.extend(components("N", ["X", "Y", "Z"], &film.normal));
+        channels.extend(components("albedo", ["R", "G", "B"], &film.albedo));
+        channels.push(Channel{name: "materialID".to_string(), samples: Samples::Uint(film.material_id.clone())});
+        channels.push(Channel{name: "objectID".to_string(), samples: Samples::Uint(film.object_id.clone())});
+        channels.push(Channel{name: "samples".to_string(), samples: Samples::Uint(film.samples.clone())});
+    }
+    ExrImage{width: film.width, height: film.height, channels, attributes}
+}
+
+/// Colors the number of samples spent on each pixel from black through red and yellow to
+/// white at `max`, as RGBA8.
+pub fn heatmap(samples: &[u32], max: u32) -> Vec<u8> {
+    let mut rgba = Vec::with_capacity(samples.len() * 4);
+    for &n in samples {
+        let t = 3. * n as f64 / max.max(1) as f64;
+        let channel = |offset: f64| ((t - offset).clamp(0., 1.) * 255.).round() as u8;
+        rgba.extend_from_slice(&[channel(0.), channel(1.), channel(2.), 0xff]);
+    }
+    rgba
+}
+
+/// The path an auxiliary image is written to next to `output`, e.g. `render.depth.pfm`
+/// for `render.png`.
+pub fn aov_path(output: &Path, name: &str, extension: &str) -> PathBuf {
+    let stem = output.file_stem().and_then(|s| s.to_str()).unwrap_or("render");
+    output.with_file_name(format!("{}.{}.{}", stem, name, extension))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_pfm() {
+        let mut bytes = Vec::new();
+        write_pfm_to(&mut bytes, 2, 2, 1, &[1., 2., 3., 4.]).unwrap();
+        let header = b"Pf\n2 2\n-1.0\n";
+        assert_eq!(&bytes[..header.len()], header);
+        let values: Vec<f32> = bytes[header.len()..].chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+        assert_eq!(values, vec![3., 4., 1., 2.]);
+        assert!(write_pfm_to(&mut Vec::new(), 1, 1, 2, &[0., 0.]).is_err());
+    }
+
+    #[test]
+    fn test_exr_image() {
+        let film = Film::new(2, 1);
+        let beauty = vec![Color::new(1., 0.5, 0.25); 2];
+        let image = exr_image(&film, &beauty, true, true, Vec::new());
+        let names: Vec<&str> = image.channels.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, vec!["R", "G", "B", "Z", "N.X", "N.Y", "N.Z", "albedo.R", "albedo.G", "albedo.B", "materialID", "objectID", "samples"]);
+        assert!(matches!(&image.channels[1].samples, Samples::Half(g) if g == &vec![0.5, 0.5]));
+        assert!(matches!(&image.channels[3].samples, Samples::Float(_)));
+        assert!(matches!(&image.channels[11].samples, Samples::Uint(_)));
+        assert_eq!(exr_image(&film, &beauty, false, false, Vec::new()).channels.len(), 3);
+    }
+
+    #[test]
+    fn test_heatmap() {
+        assert_eq!(heatmap(&[0, 2, 4, 6], 6), vec![0, 0, 0, 255, 255, 0, 0, 255, 255, 255, 0, 255, 255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn test_aov_path() {
+        assert_eq!(aov_path(Path::new("out/render.png"), "depth", "pfm"), PathBuf::from("out/render.depth.pfm"));
+    }
+}
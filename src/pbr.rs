@@ -0,0 +1,232 @@
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use crate::hittable::HitRecord;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::texture::ImageTexture;
+use crate::vec3::{Color, Vec3, luminance, reflect};
+
+/// The glTF metallic-roughness material: a diffuse base layer blended with a GGX microfacet
+/// specular layer, where metals tint their reflections by the base color and have no diffuse.
+#[derive(Clone)]
+pub struct PbrMaterial {
+    pub base_color: Color,
+    pub base_color_texture: Option<Arc<ImageTexture>>,
+    pub metallic: f64,
+    pub roughness: f64,
+    /// Metalness in the blue channel and roughness in the green channel.
+    pub metallic_roughness_texture: Option<Arc<ImageTexture>>,
+    pub emissive: Color,
+    pub emissive_texture: Option<Arc<ImageTexture>>,
+}
+
+/// The material parameters at a single hit, with textures and vertex colors applied.
+struct Surface {
+    base_color: Color,
+    metallic: f64,
+    alpha: f64,
+}
+
+impl PbrMaterial {
+    pub fn new(base_color: Color, metallic: f64, roughness: f64) -> PbrMaterial {
+        PbrMaterial{
+            base_color,
+            base_color_texture: None,
+            metallic,
+            roughness,
+            metallic_roughness_texture: None,
+            emissive: Color::zeros(),
+            emissive_texture: None,
+        }
+    }
+
+    fn surface(&self, rec: &HitRecord) -> Surface {
+        let mut base_color = self.base_color;
+        if let Some(texture) = &self.base_color_texture {
+            base_color = base_color * texture.sample(rec.u, rec.v);
+        }
+        if let Some(color) = rec.vertex_color {
+            base_color = base_color * color;
+        }
+        let (mut metallic, mut roughness) = (self.metallic, self.roughness);
+        if let Some(texture) = &self.metallic_roughness_texture {
+            let texel = texture.sample(rec.u, rec.v);
+            metallic *= texel.z;
+            roughness *= texel.y;
+        }
+        // Perfectly smooth GGX is a delta distribution, which the sampling below can't represent.
+        let alpha = (roughness * roughness).max(1e-3);
+        Surface{base_color, metallic: metallic.clamp(0., 1.), alpha}
+    }
+
+    /// Probability of sampling the specular lobe rather than the diffuse one.
+    fn specular_probability(surface: &Surface, f0: &Color, cos_o: f64) -> f64 {
+        let specular = luminance(&schlick(f0, cos_o));
+        let diffuse = (1. - surface.metallic) * luminance(&surface.base_color) * (1. - specular);
+        if specular + diffuse > 0. { (specular / (specular + diffuse)).clamp(0.1, 1.) } else { 1. }
+    }
+
+    fn pdf(surface: &Surface, p_specular: f64, n: &Vec3, wo: &Vec3, wi: &Vec3) -> f64 {
+        let h = (wo + wi).unit_vec();
+        let cos_h = n.dot(&h);
+        let specular = ggx_d(cos_h, surface.alpha) * cos_h / (4. * wo.dot(&h).max(1e-6));
+        let diffuse = n.dot(wi).max(0.) / PI;
+        p_specular * specular + (1. - p_specular) * diffuse
+    }
+
+    fn bsdf_cos(surface: &Surface, f0: &Color, n: &Vec3, wo: &Vec3, wi: &Vec3) -> Color {
+        let cos_o = n.dot(wo).max(1e-6);
+        let cos_i = n.dot(wi);
+        if cos_i <= 0. {
+            return Color::zeros();
+        }
+        let h = (wo + wi).unit_vec();
+        let fresnel = schlick(f0, wo.dot(&h).max(0.));
+        let white = Color::new(1., 1., 1.);
+        let diffuse = (1. - surface.metallic) * surface.base_color * (white - fresnel) / PI;
+        let g = smith_g1(cos_o, surface.alpha) * smith_g1(cos_i, surface.alpha);
+        let specular = fresnel * (ggx_d(n.dot(&h), surface.alpha) * g / (4. * cos_o * cos_i));
+        (diffuse + specular) * cos_i
+    }
+
+    fn f0(surface: &Surface) -> Color {
+        let dielectric = Color::new(0.04, 0.04, 0.04);
+        (1. - surface.metallic) * dielectric + surface.metallic * surface.base_color
+    }
+}
+
+impl Material for PbrMaterial {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<(Ray, Color)> {
+        let surface = self.surface(rec);
+        let f0 = PbrMaterial::f0(&surface);
+        let n = rec.normal;
+        let wo = -r_in.dir.unit_vec();
+        let p_specular = PbrMaterial::specular_probability(&surface, &f0, n.dot(&wo).max(0.));
+
+        let wi = if sampler.get_1d() < p_specular {
+            let (u1, u2) = sampler.get_2d();
+            let h = sample_ggx(&n, surface.alpha, u1, u2);
+            reflect(&-wo, &h)
+        } else {
+            let direction = n + Vec3::random_unit_vector(sampler);
+            if direction.near_zero() { n } else { direction.unit_vec() }
+        };
+        if n.dot(&wi) <= 0. {
+            return None;
+        }
+
+        // Weight by the pdf of the whole mixture rather than the chosen lobe, which keeps
+        // the estimate well-behaved when the lobes overlap.
+        let pdf = PbrMaterial::pdf(&surface, p_specular, &n, &wo, &wi);
+        if pdf <= 0. {
+            return None;
+        }
+        let attenuation = PbrMaterial::bsdf_cos(&surface, &f0, &n, &wo, &wi) / pdf;
+        Some((Ray::new(rec.p, wi, r_in.time), attenuation))
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        match &self.emissive_texture {
+            Some(texture) => self.emissive * texture.sample(rec.u, rec.v),
+            None => self.emissive,
+        }
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.surface(rec).base_color
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, wi: &Vec3) -> Color {
+        let surface = self.surface(rec);
+        let f0 = PbrMaterial::f0(&surface);
+        PbrMaterial::bsdf_cos(&surface, &f0, &rec.normal, &-r_in.dir.unit_vec(), wi)
+    }
+}
+
+fn schlick(f0: &Color, cos_theta: f64) -> Color {
+    let white = Color::new(1., 1., 1.);
+    f0 + (white - f0) * (1. - cos_theta).powi(5)
+}
+
+/// GGX normal distribution function.
+fn ggx_d(cos_h: f64, alpha: f64) -> f64 {
+    if cos_h <= 0. {
+        return 0.;
+    }
+    let a2 = alpha * alpha;
+    let d = cos_h * cos_h * (a2 - 1.) + 1.;
+    a2 / (PI * d * d)
+}
+
+/// Smith masking term for GGX in one direction.
+fn smith_g1(cos_theta: f64, alpha: f64) -> f64 {
+    let a2 = alpha * alpha;
+    2. * cos_theta / (cos_theta + (a2 + (1. - a2) * cos_theta * cos_theta).sqrt())
+}
+
+/// Samples a microfacet normal around `n` proportionally to D(h) cos(theta_h).
+fn sample_ggx(n: &Vec3, alpha: f64, u1: f64, u2: f64) -> Vec3 {
+    let tan2_theta = alpha * alpha * u1 / (1. - u1);
+    let cos_theta = 1. / (1. + tan2_theta).sqrt();
+    let sin_theta = (1. - cos_theta * cos_theta).max(0.).sqrt();
+    let phi = 2. * PI * u2;
+
+    let helper = if n.x.abs() > 0.9 { Vec3::new(0., 1., 0.) } else { Vec3::new(1., 0., 0.) };
+    let tangent = n.cross(&helper).unit_vec();
+    let bitangent = n.cross(&tangent);
+    (sin_theta * phi.cos()) * tangent + (sin_theta * phi.sin()) * bitangent + cos_theta * n
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::IndependentSampler;
+    use crate::vec3::Point;
+
+    fn record(material: &PbrMaterial) -> HitRecord<'_> {
+        HitRecord{
+            p: Point::zeros(), normal: Vec3::new(0., 0., 1.), t: 1., front_face: true,
+            material, u: 0., v: 0., vertex_color: None,
+        }
+    }
+
+    #[test]
+    fn test_smooth_metal_reflects_mirror_like() {
+        let material = PbrMaterial::new(Color::new(1., 1., 1.), 1., 0.05);
+        let rec = record(&material);
+        let r_in = Ray::new(Point::new(-1., 0., 1.), Vec3::new(1., 0., -1.), 0.);
+        let mirror = Vec3::new(1., 0., 1.).unit_vec();
+        let mut sampler = IndependentSampler::new(0);
+        for _ in 0..100 {
+            if let Some((scattered, attenuation)) = material.scatter(&r_in, &rec, &mut sampler) {
+                assert!(scattered.dir.unit_vec().dot(&mirror) > 0.95);
+                assert!(attenuation.x > 0.5 && attenuation.x < 1.5);
+            }
+        }
+    }
+
+    #[test]
+    fn test_white_furnace_conserves_energy() {
+        let r_in = Ray::new(Point::new(0., 0., 1.), Vec3::new(0., 0., -1.), 0.);
+        for &(metallic, roughness) in [(0., 1.), (1., 0.5), (0.5, 0.3)].iter() {
+            let material = PbrMaterial::new(Color::new(1., 1., 1.), metallic, roughness);
+            let rec = record(&material);
+            let n = 20000;
+            let mut sampler = IndependentSampler::new(0);
+            let total: f64 = (0..n).filter_map(|_| material.scatter(&r_in, &rec, &mut sampler)).map(|(_, a)| a.y).sum();
+            let albedo = total / n as f64;
+            assert!(albedo > 0.7 && albedo < 1.05, "albedo {} for {:?}", albedo, (metallic, roughness));
+        }
+    }
+
+    #[test]
+    fn test_eval_below_surface_is_black() {
+        let material = PbrMaterial::new(Color::new(0.5, 0.5, 0.5), 0., 0.5);
+        let rec = record(&material);
+        let r_in = Ray::new(Point::new(0., 0., 1.), Vec3::new(0., 0., -1.), 0.);
+        assert_eq!(material.eval(&r_in, &rec, &Vec3::new(0., 0., -1.)), Color::zeros());
+        assert!(material.eval(&r_in, &rec, &Vec3::new(0., 0., 1.)).x > 0.);
+    }
+}
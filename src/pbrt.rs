@@ -0,0 +1,610 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use crate::camera::Camera;
+use crate::light::{DirectionalLight, Light, PointLight, SpotLight};
+use crate::material::{Dielectric, Lambertian, Material, Metal};
+use crate::matrix::{self, Matrix, IDENTITY, transform_normal, transform_point, transform_vector};
+use crate::mesh::{Mesh, TriangleMesh};
+use crate::ply;
+use crate::scene::Lights;
+use crate::scenes::Objects;
+use crate::spectrum::blackbody;
+use crate::sphere::Sphere;
+use crate::vec3::{Color, Point, Vec3};
+
+/// Everything imported from a pbrt-v3 scene file. `unsupported` lists the directives and
+/// types that were skipped (or approximated), so callers can tell the user.
+pub struct PbrtScene {
+    pub objects: Objects,
+    pub lights: Lights,
+    pub camera: Option<Camera>,
+    pub unsupported: Vec<String>,
+}
+
+/// Loads a pbrt-v3 scene file. Relative paths inside it (e.g. PLY meshes) are resolved
+/// against the file's directory. The camera is given the renderer's `aspect_ratio`.
+pub fn load<P: AsRef<Path>>(path: P, aspect_ratio: f64) -> io::Result<PbrtScene> {
+    let path = path.as_ref();
+    let text = fs::read_to_string(path)?;
+    parse(&text, path.parent().unwrap_or_else(|| Path::new(".")), aspect_ratio)
+}
+
+/// Like `load`, but refuses files outside the scene file's directory, for scene files
+/// from someone else.
+pub fn load_confined<P: AsRef<Path>>(path: P, aspect_ratio: f64) -> io::Result<PbrtScene> {
+    let path = path.as_ref();
+    let text = fs::read_to_string(path)?;
+    parse_scene(&text, path.parent().unwrap_or_else(|| Path::new(".")), true, aspect_ratio)
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Identifier(String),
+    Str(String),
+    Number(f64),
+    Open,
+    Close,
+}
+
+fn tokenize(text: &str) -> io::Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '#' => {
+                while chars.next().is_some_and(|c| c != '\n') {}
+            }
+            '[' | ']' => {
+                chars.next();
+                tokens.push(if c == '[' { Token::Open } else { Token::Close });
+            }
+            '"' => {
+                chars.next();
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => s.push(c),
+                        None => return Err(invalid_data("unterminated string in pbrt file")),
+                    }
+                }
+                tokens.push(Token::Str(s));
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || "[]\"#".contains(c) {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                // Booleans may be left unquoted, and are values rather than directives.
+                tokens.push(match word.parse() {
+                    Ok(number) => Token::Number(number),
+                    Err(_) if word == "true" || word == "false" => Token::Str(word),
+                    Err(_) => Token::Identifier(word),
+                });
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// A parameter such as `"rgb Kd" [0.5 0.5 0.5]`. Numeric values are kept apart from string
+/// and boolean values; which one is used depends on the parameter's type.
+struct Param {
+    ty: String,
+    name: String,
+    numbers: Vec<f64>,
+    strings: Vec<String>,
+}
+
+struct Params(Vec<Param>);
+
+impl Params {
+    fn parse(tokens: &[Token]) -> io::Result<Params> {
+        let mut params = Vec::new();
+        let mut i = 0;
+        while i < tokens.len() {
+            let declaration = match &tokens[i] {
+                Token::Str(s) => s,
+                other => return Err(invalid_data(&format!("expected a parameter declaration, found {:?}", other))),
+            };
+            let mut words = declaration.split_whitespace();
+            let (ty, name) = match (words.next(), words.next()) {
+                (Some(ty), Some(name)) => (ty.to_string(), name.to_string()),
+                _ => return Err(invalid_data(&format!("bad parameter declaration \"{}\"", declaration))),
+            };
+            i += 1;
+            let values = match tokens.get(i) {
+                Some(Token::Open) => {
+                    let end = tokens[i..].iter().position(|t| *t == Token::Close)
+                        .ok_or_else(|| invalid_data("unterminated parameter list"))?;
+                    let values = &tokens[i + 1..i + end];
+                    i += end + 1;
+                    values
+                }
+                Some(_) => {
+                    i += 1;
+                    &tokens[i - 1..i]
+                }
+                None => return Err(invalid_data(&format!("missing value for parameter \"{}\"", declaration))),
+            };
+            let mut param = Param{ty, name, numbers: Vec::new(), strings: Vec::new()};
+            for value in values {
+                match value {
+                    Token::Number(n) => param.numbers.push(*n),
+                    Token::Str(s) => param.strings.push(s.clone()),
+                    other => return Err(invalid_data(&format!("unexpected parameter value {:?}", other))),
+                }
+            }
+            params.push(param);
+        }
+        Ok(Params(params))
+    }
+
+    fn get(&self, name: &str) -> Option<&Param> {
+        self.0.iter().find(|p| p.name == name)
+    }
+
+    fn numbers(&self, name: &str) -> Option<&[f64]> {
+        self.get(name).map(|p| p.numbers.as_slice()).filter(|n| !n.is_empty())
+    }
+
+    fn float(&self, name: &str, default: f64) -> f64 {
+        self.numbers(name).map_or(default, |n| n[0])
+    }
+
+    fn string(&self, name: &str) -> Option<&str> {
+        self.get(name).and_then(|p| p.strings.first()).map(|s| s.as_str())
+    }
+
+    fn point(&self, name: &str, default: Point) -> Point {
+        match self.numbers(name) {
+            Some(&[x, y, z, ..]) => Point::new(x, y, z),
+            _ => default,
+        }
+    }
+
+    /// A spectrum given as RGB or as a blackbody temperature (with an optional scale).
+    /// Textures and sampled spectra aren't supported and fall back to `default`.
+    fn color(&self, name: &str, default: Color) -> Color {
+        let param = match self.get(name) {
+            Some(param) => param,
+            None => return default,
+        };
+        match (param.ty.as_str(), param.numbers.as_slice()) {
+            ("rgb", &[r, g, b, ..]) | ("color", &[r, g, b, ..]) => Color::new(r, g, b),
+            ("blackbody", &[temperature]) => blackbody(temperature),
+            ("blackbody", &[temperature, scale, ..]) => blackbody(temperature) * scale,
+            (_, &[v]) => Color::new(v, v, v),
+            _ => default,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum MaterialDesc {
+    Matte(Color),
+    Metal(Color, f64),
+    Glass(f64),
+}
+
+impl MaterialDesc {
+    fn new(ty: &str, params: &Params, unsupported: &mut Vec<String>) -> MaterialDesc {
+        match ty {
+            "matte" => MaterialDesc::Matte(params.color("Kd", Color::new(0.5, 0.5, 0.5))),
+            "metal" => {
+                // Reflectance at normal incidence from the complex index of refraction,
+                // defaulting to copper like pbrt does.
+                let eta = params.color("eta", Color::new(0.2004, 0.9240, 1.1022));
+                let k = params.color("k", Color::new(3.9129, 2.4528, 2.1421));
+                let f0 = |eta: f64, k: f64| ((eta - 1.).powi(2) + k * k) / ((eta + 1.).powi(2) + k * k);
+                let reflectance = Color::new(f0(eta.x, k.x), f0(eta.y, k.y), f0(eta.z, k.z));
+                let roughness = params.float("roughness", params.float("uroughness", 0.01));
+                MaterialDesc::Metal(reflectance, roughness.clamp(0., 1.))
+            }
+            "glass" => MaterialDesc::Glass(params.float("index", params.float("eta", 1.5))),
+            _ => {
+                unsupported.push(format!("Material \"{}\" (rendered as matte)", ty));
+                MaterialDesc::Matte(params.color("Kd", Color::new(0.5, 0.5, 0.5)))
+            }
+        }
+    }
+
+    fn build(&self) -> Box<dyn Material + Send + Sync> {
+        match self {
+            MaterialDesc::Matte(albedo) => Box::new(Lambertian::new(*albedo)),
+            MaterialDesc::Metal(albedo, fuzz) => Box::new(Metal::new(*albedo, *fuzz)),
+            MaterialDesc::Glass(ir) => Box::new(Dielectric::new(*ir)),
+        }
+    }
+}
+
+/// The state saved and restored by AttributeBegin/AttributeEnd.
+#[derive(Clone)]
+struct GraphicsState {
+    ctm: Matrix,
+    material: MaterialDesc,
+}
+
+struct Parser<'a> {
+    base_dir: &'a Path,
+    // Whether files must be inside `base_dir`.
+    confined: bool,
+    state: GraphicsState,
+    stack: Vec<GraphicsState>,
+    named_materials: HashMap<String, MaterialDesc>,
+    // The camera-to-world transform and field of view, turned into a `Camera` once the
+    // film resolution is known.
+    camera: Option<(Matrix, f64)>,
+    resolution: (f64, f64),
+    scene: PbrtScene,
+}
+
+/// Parses the text of a pbrt-v3 scene file. See `load`.
+pub fn parse(text: &str, base_dir: &Path, aspect_ratio: f64) -> io::Result<PbrtScene> {
+    parse_scene(text, base_dir, false, aspect_ratio)
+}
+
+fn parse_scene(text: &str, base_dir: &Path, confined: bool, aspect_ratio: f64) -> io::Result<PbrtScene> {
+    let tokens = tokenize(text)?;
+    let mut parser = Parser{
+        base_dir,
+        confined,
+        state: GraphicsState{ctm: IDENTITY, material: MaterialDesc::Matte(Color::new(0.5, 0.5, 0.5))},
+        stack: Vec::new(),
+        named_materials: HashMap::new(),
+        camera: None,
+        resolution: (640., 480.),
+        scene: PbrtScene{objects: Vec::new(), lights: Vec::new(), camera: None, unsupported: Vec::new()},
+    };
+
+    let mut i = 0;
+    while i < tokens.len() {
+        let directive = match &tokens[i] {
+            Token::Identifier(directive) => directive,
+            other => return Err(invalid_data(&format!("expected a directive, found {:?}", other))),
+        };
+        let end = tokens[i + 1..].iter().position(|t| matches!(t, Token::Identifier(_)))
+            .map_or(tokens.len(), |n| i + 1 + n);
+        parser.directive(directive, &tokens[i + 1..end])?;
+        i = end;
+    }
+
+    if let Some((camera_to_world, fov)) = parser.camera {
+        // pbrt's field of view spans the shorter side of the film.
+        let (width, height) = parser.resolution;
+        let vertical_fov = if width >= height {
+            fov
+        } else {
+            2. * ((fov / 2.).to_radians().tan() * height / width).atan().to_degrees()
+        };
+        // Take the image axes straight from the camera transform: pbrt is left-handed, so
+        // deriving them from a look-at direction would mirror the image.
+        let origin = transform_point(&camera_to_world, &Point::zeros());
+        let u = transform_vector(&camera_to_world, &Vec3::new(1., 0., 0.)).unit_vec();
+        let v = transform_vector(&camera_to_world, &Vec3::new(0., 1., 0.)).unit_vec();
+        let w = -transform_vector(&camera_to_world, &Vec3::new(0., 0., 1.)).unit_vec();
+        parser.scene.camera = Some(Camera::with_basis(origin, u, v, w, vertical_fov, aspect_ratio));
+    }
+    Ok(parser.scene)
+}
+
+/// Splits a directive's arguments into its leading type or name string and its parameters.
+fn typed_params(directive: &str, args: &[Token]) -> io::Result<(String, Params)> {
+    match args.first() {
+        Some(Token::Str(ty)) => Ok((ty.clone(), Params::parse(&args[1..])?)),
+        _ => Err(invalid_data(&format!("{} is missing its type", directive))),
+    }
+}
+
+fn numbers(directive: &str, args: &[Token], count: usize) -> io::Result<Vec<f64>> {
+    let values: Vec<f64> = args.iter()
+        .filter_map(|t| if let Token::Number(n) = t { Some(*n) } else { None })
+        .collect();
+    if values.len() != count {
+        return Err(invalid_data(&format!("{} expects {} numbers, found {}", directive, count, values.len())));
+    }
+    Ok(values)
+}
+
+impl<'a> Parser<'a> {
+    /// The path of a file the scene refers to.
+    fn file(&self, filename: &str) -> io::Result<PathBuf> {
+        let path = Path::new(filename);
+        if self.confined && !path.components().all(|c| matches!(c, Component::Normal(_) | Component::CurDir)) {
+            return Err(invalid_data(&format!("{} is outside the scene's directory", filename)));
+        }
+        Ok(self.base_dir.join(path))
+    }
+
+    fn directive(&mut self, directive: &str, args: &[Token]) -> io::Result<()> {
+        match directive {
+            "LookAt" => {
+                let n = numbers(directive, args, 9)?;
+                let (eye, look, up) = (Point::new(n[0], n[1], n[2]), Point::new(n[3], n[4], n[5]), Vec3::new(n[6], n[7], n[8]));
+                self.concat(&look_at(&eye, &look, &up)?);
+            }
+            "Translate" => {
+                let n = numbers(directive, args, 3)?;
+                self.concat(&matrix::translate(&Vec3::new(n[0], n[1], n[2])));
+            }
+            "Scale" => {
+                let n = numbers(directive, args, 3)?;
+                self.concat(&matrix::scale(&Vec3::new(n[0], n[1], n[2])));
+            }
+            "Rotate" => {
+                let n = numbers(directive, args, 4)?;
+                self.concat(&matrix::rotate(n[0], &Vec3::new(n[1], n[2], n[3])));
+            }
+            "Transform" | "ConcatTransform" => {
+                let n = numbers(directive, args, 16)?;
+                let mut m = IDENTITY;
+                for (i, value) in n.iter().enumerate() {
+                    m[i / 4][i % 4] = *value;
+                }
+                if directive == "Transform" {
+                    self.state.ctm = m;
+                } else {
+                    self.concat(&m);
+                }
+            }
+            "Identity" => self.state.ctm = IDENTITY,
+            "WorldBegin" => self.state.ctm = IDENTITY,
+            "WorldEnd" => {}
+            "AttributeBegin" | "TransformBegin" => self.stack.push(self.state.clone()),
+            "AttributeEnd" | "TransformEnd" => {
+                let saved = self.stack.pop().ok_or_else(|| invalid_data(&format!("{} without a matching begin", directive)))?;
+                if directive == "AttributeEnd" {
+                    self.state = saved;
+                } else {
+                    self.state.ctm = saved.ctm;
+                }
+            }
+            "Camera" => {
+                let (ty, params) = typed_params(directive, args)?;
+                if ty != "perspective" {
+                    self.scene.unsupported.push(format!("Camera \"{}\"", ty));
+                    return Ok(());
+                }
+                let camera_to_world = matrix::inverse(&self.state.ctm).ok_or_else(|| invalid_data("camera transform is singular"))?;
+                self.camera = Some((camera_to_world, params.float("fov", 90.)));
+            }
+            "Film" => {
+                let (_, params) = typed_params(directive, args)?;
+                self.resolution = (params.float("xresolution", 640.), params.float("yresolution", 480.));
+            }
+            "Material" => {
+                let (ty, params) = typed_params(directive, args)?;
+                self.state.material = MaterialDesc::new(&ty, &params, &mut self.scene.unsupported);
+            }
+            "MakeNamedMaterial" => {
+                let (name, params) = typed_params(directive, args)?;
+                let ty = params.string("type").unwrap_or("matte").to_string();
+                let material = MaterialDesc::new(&ty, &params, &mut self.scene.unsupported);
+                self.named_materials.insert(name, material);
+            }
+            "NamedMaterial" => {
+                let (name, _) = typed_params(directive, args)?;
+                self.state.material = self.named_materials.get(&name).cloned()
+                    .ok_or_else(|| invalid_data(&format!("unknown named material \"{}\"", name)))?;
+            }
+            "Shape" => {
+                let (ty, params) = typed_params(directive, args)?;
+                self.shape(&ty, &params)?;
+            }
+            "LightSource" => {
+                let (ty, params) = typed_params(directive, args)?;
+                self.light(&ty, &params);
+            }
+            // Rendering settings that only affect how pbrt itself renders.
+            "Sampler" | "Integrator" | "PixelFilter" | "Accelerator" | "SurfaceIntegrator" | "ColorSpace" | "Option" => {}
+            _ => self.scene.unsupported.push(directive.to_string()),
+        }
+        Ok(())
+    }
+
+    fn concat(&mut self, m: &Matrix) {
+        self.state.ctm = matrix::mul(&self.state.ctm, m);
+    }
+
+    fn shape(&mut self, ty: &str, params: &Params) -> io::Result<()> {
+        let ctm = self.state.ctm;
+        let mut mesh = match ty {
+            "sphere" => {
+                let center = transform_point(&ctm, &Point::zeros());
+                let radius = params.float("radius", 1.) * transform_vector(&ctm, &Vec3::new(1., 0., 0.)).length();
+                self.scene.objects.push(Box::new(Sphere::new(center.x, center.y, center.z, radius, self.state.material.build())));
+                return Ok(());
+            }
+            "trianglemesh" => {
+                let indices = params.numbers("indices").ok_or_else(|| invalid_data("trianglemesh has no indices"))?;
+                let positions = params.numbers("P").ok_or_else(|| invalid_data("trianglemesh has no positions"))?;
+                let vertex_count = positions.len() / 3;
+                if indices.iter().any(|&i| i < 0. || i as usize >= vertex_count) {
+                    return Err(invalid_data("trianglemesh index out of range"));
+                }
+                let to_points = |values: &[f64]| values.chunks_exact(3).map(|p| Vec3::new(p[0], p[1], p[2])).collect::<Vec<_>>();
+                let normals = params.numbers("N").map(to_points).filter(|n| n.len() == vertex_count);
+                let uvs = params.numbers("uv").or_else(|| params.numbers("st"))
+                    .map(|uv| uv.chunks_exact(2).map(|t| (t[0], t[1])).collect::<Vec<_>>())
+                    .filter(|uv| uv.len() == vertex_count);
+                TriangleMesh{
+                    positions: to_points(positions),
+                    normals,
+                    colors: None,
+                    uvs,
+                    triangles: indices.chunks_exact(3).map(|t| [t[0] as u32, t[1] as u32, t[2] as u32]).collect(),
+                }
+            }
+            "plymesh" => {
+                let filename = params.string("filename").ok_or_else(|| invalid_data("plymesh has no filename"))?;
+                ply::load(self.file(filename)?)?
+            }
+            _ => {
+                self.scene.unsupported.push(format!("Shape \"{}\"", ty));
+                return Ok(());
+            }
+        };
+        for p in mesh.positions.iter_mut() {
+            *p = transform_point(&ctm, p);
+        }
+        if let Some(normals) = mesh.normals.as_mut() {
+            for n in normals.iter_mut() {
+                *n = transform_normal(&ctm, n);
+            }
+        }
+        self.scene.objects.push(Box::new(Mesh::new(mesh, self.state.material.build())));
+        Ok(())
+    }
+
+    fn light(&mut self, ty: &str, params: &Params) {
+        let ctm = &self.state.ctm;
+        let scale = params.color("scale", Color::new(1., 1., 1.));
+        let from = transform_point(ctm, &params.point("from", Point::zeros()));
+        let to = transform_point(ctm, &params.point("to", Point::new(0., 0., 1.)));
+        let light: Box<dyn Light + Send + Sync> = match ty {
+            "point" => Box::new(PointLight{
+                position: from,
+                intensity: params.color("I", Color::new(1., 1., 1.)) * scale,
+            }),
+            "spot" => {
+                let cone_angle = params.float("coneangle", 30.);
+                let cone_delta = params.float("conedelta", 5.);
+                Box::new(SpotLight{
+                    position: from,
+                    direction: (to - from).unit_vec(),
+                    intensity: params.color("I", Color::new(1., 1., 1.)) * scale,
+                    inner_cone_angle: (cone_angle - cone_delta).max(0.).to_radians(),
+                    outer_cone_angle: cone_angle.to_radians(),
+                })
+            }
+            "distant" => Box::new(DirectionalLight{
+                direction: (to - from).unit_vec(),
+                irradiance: params.color("L", Color::new(1., 1., 1.)) * scale,
+            }),
+            _ => {
+                self.scene.unsupported.push(format!("LightSource \"{}\"", ty));
+                return;
+            }
+        };
+        self.scene.lights.push(light);
+    }
+}
+
+/// pbrt's LookAt: the world-to-camera transform of a left-handed camera at `eye` looking
+/// at `look`, with +x to the right, +y up and +z forward.
+fn look_at(eye: &Point, look: &Point, up: &Vec3) -> io::Result<Matrix> {
+    let dir = (look - eye).unit_vec();
+    let right = up.unit_vec().cross(&dir);
+    if right.length() == 0. {
+        return Err(invalid_data("LookAt up vector is parallel to the view direction"));
+    }
+    let right = right.unit_vec();
+    let new_up = dir.cross(&right);
+    let camera_to_world = [
+        [right.x, right.y, right.z, 0.],
+        [new_up.x, new_up.y, new_up.z, 0.],
+        [dir.x, dir.y, dir.z, 0.],
+        [eye.x, eye.y, eye.z, 1.],
+    ];
+    matrix::inverse(&camera_to_world).ok_or_else(|| invalid_data("LookAt transform is singular"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ray::Ray;
+
+    const SCENE: &str = r#"
+        # A glass sphere and a red quad lit by a point light.
+        LookAt 0 0 -5   0 0 0   0 1 0
+        Camera "perspective" "float fov" [45]
+        Film "image" "integer xresolution" [400] "integer yresolution" [200] "string filename" "out.exr"
+        Sampler "halton" "integer pixelsamples" 16
+        WorldBegin
+        LightSource "point" "point from" [0 4 0] "rgb I" [2 2 2]
+        AttributeBegin
+            Material "glass" "float index" 1.33
+            Translate 0 1 0
+            Shape "sphere" "float radius" 0.5
+        AttributeEnd
+        MakeNamedMaterial "red" "string type" "matte" "rgb Kd" [0.8 0.1 0.1]
+        NamedMaterial "red"
+        Shape "trianglemesh" "integer indices" [0 1 2 0 2 3]
+            "point P" [-1 -1 0  1 -1 0  1 1 0  -1 1 0]
+        Shape "disk"
+        WorldEnd
+    "#;
+
+    #[test]
+    fn test_parse() {
+        let scene = parse(SCENE, Path::new("."), 2.).unwrap();
+        assert_eq!(scene.objects.len(), 2);
+        assert_eq!(scene.lights.len(), 1);
+        assert_eq!(scene.unsupported, vec!["Shape \"disk\"".to_string()]);
+
+        // The sphere was moved up by the attribute block's transform only.
+        let down = Ray::new(Point::new(0., 3., 0.), Vec3::new(0., -1., 0.), 0.);
+        let rec = scene.objects[0].hit(&down, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 1.5).abs() < 1e-9);
+        let quad = Ray::new(Point::new(0.5, -0.5, -1.), Vec3::new(0., 0., 1.), 0.);
+        assert!((scene.objects[1].hit(&quad, 0.001, f64::INFINITY).unwrap().t - 1.).abs() < 1e-9);
+
+        // pbrt cameras are left-handed: looking down +z with +y up, +x is on the right.
+        let camera = scene.camera.unwrap();
+        let center = camera.get_ray(0.5, 0.5, 0.);
+        assert!((center.origin - Point::new(0., 0., -5.)).length() < 1e-9);
+        assert!((center.dir.unit_vec() - Vec3::new(0., 0., 1.)).length() < 1e-9);
+        let top_right = camera.get_ray(1., 0., 0.);
+        assert!(top_right.dir.x > 0. && top_right.dir.y > 0.);
+        // The 45 degree field of view spans the shorter, vertical side of the 2:1 film.
+        assert!((top_right.dir.y / top_right.dir.z - (22.5f64).to_radians().tan()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse("Shape \"sphere", Path::new("."), 1.).is_err());
+        assert!(parse("AttributeEnd", Path::new("."), 1.).is_err());
+        assert!(parse("Translate 1 2", Path::new("."), 1.).is_err());
+        assert!(parse("NamedMaterial \"missing\"", Path::new("."), 1.).is_err());
+    }
+
+    #[test]
+    fn test_unquoted_booleans() {
+        let scene = parse("Shape \"sphere\" \"bool a\" true \"bool b\" [false] \"float radius\" 2  Shape \"sphere\"", Path::new("."), 1.).unwrap();
+        assert_eq!(scene.objects.len(), 2);
+        assert_eq!(tokenize("true [false]").unwrap(), vec![Token::Str("true".to_string()), Token::Open, Token::Str("false".to_string()), Token::Close]);
+    }
+
+    #[test]
+    fn test_confined_files() {
+        for filename in ["/etc/mesh.ply", "../mesh.ply", "meshes/../../mesh.ply"] {
+            let text = format!("Shape \"plymesh\" \"string filename\" \"{}\"", filename);
+            let error = parse_scene(&text, Path::new("."), true, 1.).err().unwrap();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{}", filename);
+        }
+        // A file inside the directory is looked for, though this one isn't there.
+        let text = "Shape \"plymesh\" \"string filename\" \"meshes/mesh.ply\"";
+        assert_eq!(parse_scene(text, Path::new("."), true, 1.).err().unwrap().kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_transform_is_column_major() {
+        let scene = parse("Transform [1 0 0 0  0 1 0 0  0 0 1 0  1 2 3 1] Shape \"sphere\"", Path::new("."), 1.).unwrap();
+        let r = Ray::new(Point::new(1., 2., 10.), Vec3::new(0., 0., -1.), 0.);
+        assert!((scene.objects[0].hit(&r, 0.001, f64::INFINITY).unwrap().t - 6.).abs() < 1e-9);
+    }
+}
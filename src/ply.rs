@@ -0,0 +1,335 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+use crate::mesh::TriangleMesh;
+use crate::vec3::Vec3;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum ScalarType {
+    Int8,
+    UInt8,
+    Int16,
+    UInt16,
+    Int32,
+    UInt32,
+    Float32,
+    Float64,
+}
+
+impl ScalarType {
+    fn parse(name: &str) -> io::Result<ScalarType> {
+        match name {
+            "char" | "int8" => Ok(ScalarType::Int8),
+            "uchar" | "uint8" => Ok(ScalarType::UInt8),
+            "short" | "int16" => Ok(ScalarType::Int16),
+            "ushort" | "uint16" => Ok(ScalarType::UInt16),
+            "int" | "int32" => Ok(ScalarType::Int32),
+            "uint" | "uint32" => Ok(ScalarType::UInt32),
+            "float" | "float32" => Ok(ScalarType::Float32),
+            "double" | "float64" => Ok(ScalarType::Float64),
+            _ => Err(invalid_data(&format!("unknown PLY property type {}", name))),
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            ScalarType::Int8 | ScalarType::UInt8 => 1,
+            ScalarType::Int16 | ScalarType::UInt16 => 2,
+            ScalarType::Int32 | ScalarType::UInt32 | ScalarType::Float32 => 4,
+            ScalarType::Float64 => 8,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Property {
+    Scalar(String, ScalarType),
+    List(String, ScalarType, ScalarType),
+}
+
+impl Property {
+    fn name(&self) -> &str {
+        match self {
+            Property::Scalar(name, _) | Property::List(name, _, _) => name,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// The body of a PLY file, read one scalar at a time regardless of encoding.
+enum Body<'a> {
+    Ascii(std::str::SplitAsciiWhitespace<'a>),
+    Binary{data: &'a [u8], pos: usize, big_endian: bool},
+}
+
+impl<'a> Body<'a> {
+    fn scalar(&mut self, ty: ScalarType) -> io::Result<f64> {
+        match self {
+            Body::Ascii(tokens) => {
+                let token = tokens.next().ok_or_else(|| invalid_data("unexpected end of PLY data"))?;
+                token.parse().map_err(|_| invalid_data(&format!("invalid PLY value {}", token)))
+            }
+            Body::Binary{data, pos, big_endian} => {
+                let size = ty.size();
+                if *pos + size > data.len() {
+                    return Err(invalid_data("unexpected end of PLY data"));
+                }
+                let mut bytes = [0u8; 8];
+                bytes[..size].copy_from_slice(&data[*pos..*pos + size]);
+                if *big_endian {
+                    bytes[..size].reverse();
+                }
+                *pos += size;
+                Ok(match ty {
+                    ScalarType::Int8 => bytes[0] as i8 as f64,
+                    ScalarType::UInt8 => bytes[0] as f64,
+                    ScalarType::Int16 => i16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+                    ScalarType::UInt16 => u16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+                    ScalarType::Int32 => i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+                    ScalarType::UInt32 => u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+                    ScalarType::Float32 => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+                    ScalarType::Float64 => f64::from_le_bytes(bytes),
+                })
+            }
+        }
+    }
+
+    /// Reads one property value: a single scalar, or every item of a list.
+    fn property(&mut self, property: &Property, values: &mut Vec<f64>) -> io::Result<()> {
+        values.clear();
+        match property {
+            Property::Scalar(_, ty) => values.push(self.scalar(*ty)?),
+            Property::List(_, count_ty, item_ty) => {
+                let count = self.scalar(*count_ty)? as usize;
+                for _ in 0..count {
+                    values.push(self.scalar(*item_ty)?);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn read_header<R: BufRead>(reader: &mut R) -> io::Result<(Format, Vec<Element>)> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    if line.trim() != "ply" {
+        return Err(invalid_data("not a PLY file"));
+    }
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(invalid_data("PLY header is missing end_header"));
+        }
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["format", "ascii", _] => format = Some(Format::Ascii),
+            ["format", "binary_little_endian", _] => format = Some(Format::BinaryLittleEndian),
+            ["format", "binary_big_endian", _] => format = Some(Format::BinaryBigEndian),
+            ["element", name, count] => {
+                let count = count.parse().map_err(|_| invalid_data("invalid PLY element count"))?;
+                elements.push(Element{name: name.to_string(), count, properties: Vec::new()});
+            }
+            ["property", "list", count_ty, item_ty, name] => {
+                let element = elements.last_mut().ok_or_else(|| invalid_data("PLY property outside of an element"))?;
+                element.properties.push(Property::List(name.to_string(), ScalarType::parse(count_ty)?, ScalarType::parse(item_ty)?));
+            }
+            ["property", ty, name] => {
+                let element = elements.last_mut().ok_or_else(|| invalid_data("PLY property outside of an element"))?;
+                element.properties.push(Property::Scalar(name.to_string(), ScalarType::parse(ty)?));
+            }
+            ["end_header"] => break,
+            ["comment", ..] | ["obj_info", ..] | [] => {}
+            _ => return Err(invalid_data(&format!("unexpected PLY header line: {}", line.trim()))),
+        }
+    }
+    let format = format.ok_or_else(|| invalid_data("PLY header is missing the format"))?;
+    Ok((format, elements))
+}
+
+pub fn load<P: AsRef<Path>>(path: P) -> io::Result<TriangleMesh> {
+    read(&mut BufReader::new(File::open(path)?))
+}
+
+/// Reads an ASCII or binary (either endianness) PLY file. Vertices may carry normals
+/// (`nx`, `ny`, `nz`) and colors (`red`, `green`, `blue`); polygonal faces are fanned
+/// into triangles.
+pub fn read<R: BufRead>(reader: &mut R) -> io::Result<TriangleMesh> {
+    let (format, elements) = read_header(reader)?;
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    let text;
+    let mut body = match format {
+        Format::Ascii => {
+            text = String::from_utf8(data).map_err(|_| invalid_data("PLY ASCII data is not valid UTF-8"))?;
+            Body::Ascii(text.split_ascii_whitespace())
+        }
+        _ => Body::Binary{data: &data, pos: 0, big_endian: format == Format::BinaryBigEndian},
+    };
+
+    let mut mesh = TriangleMesh::default();
+    let mut values = Vec::new();
+    for element in elements.iter() {
+        let find = |names: &[&str]| element.properties.iter().position(|p| names.contains(&p.name()));
+        match element.name.as_str() {
+            "vertex" => {
+                let position = [find(&["x"]), find(&["y"]), find(&["z"])];
+                let normal = [find(&["nx"]), find(&["ny"]), find(&["nz"])];
+                let color = [find(&["red", "diffuse_red"]), find(&["green", "diffuse_green"]), find(&["blue", "diffuse_blue"])];
+                if position.iter().any(|p| p.is_none()) {
+                    return Err(invalid_data("PLY vertices have no position"));
+                }
+                let has_normals = normal.iter().all(|p| p.is_some());
+                let has_colors = color.iter().all(|p| p.is_some());
+                let mut normals = Vec::new();
+                let mut colors = Vec::new();
+                // Integer colors are in [0, 255], floating point colors in [0, 1].
+                let color_scale = match color[0].map(|i| &element.properties[i]) {
+                    Some(Property::Scalar(_, ScalarType::Float32)) | Some(Property::Scalar(_, ScalarType::Float64)) => 1.,
+                    _ => 1. / 255.,
+                };
+                let mut record = vec![0.; element.properties.len()];
+                for _ in 0..element.count {
+                    for (i, property) in element.properties.iter().enumerate() {
+                        body.property(property, &mut values)?;
+                        record[i] = values.first().cloned().unwrap_or(0.);
+                    }
+                    let get = |indices: &[Option<usize>; 3]| {
+                        Vec3::new(record[indices[0].unwrap()], record[indices[1].unwrap()], record[indices[2].unwrap()])
+                    };
+                    mesh.positions.push(get(&position));
+                    if has_normals {
+                        normals.push(get(&normal));
+                    }
+                    if has_colors {
+                        colors.push(get(&color) * color_scale);
+                    }
+                }
+                if has_normals {
+                    mesh.normals = Some(normals);
+                }
+                if has_colors {
+                    mesh.colors = Some(colors);
+                }
+            }
+            "face" => {
+                let indices = find(&["vertex_indices", "vertex_index"]).ok_or_else(|| invalid_data("PLY faces have no vertex indices"))?;
+                for _ in 0..element.count {
+                    for (i, property) in element.properties.iter().enumerate() {
+                        body.property(property, &mut values)?;
+                        if i == indices {
+                            for k in 1..values.len().saturating_sub(1) {
+                                mesh.triangles.push([values[0] as u32, values[k] as u32, values[k + 1] as u32]);
+                            }
+                        }
+                    }
+                }
+            }
+            _ => {
+                for _ in 0..element.count {
+                    for property in element.properties.iter() {
+                        body.property(property, &mut values)?;
+                    }
+                }
+            }
+        }
+    }
+
+    let vertex_count = mesh.positions.len() as u32;
+    if mesh.triangles.iter().any(|tri| tri.iter().any(|&i| i >= vertex_count)) {
+        return Err(invalid_data("PLY face refers to a missing vertex"));
+    }
+    Ok(mesh)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec3::{Color, Point};
+
+    const ASCII: &str = "ply
+format ascii 1.0
+comment a single colored quad
+element vertex 4
+property float x
+property float y
+property float z
+property uchar red
+property uchar green
+property uchar blue
+element face 1
+property list uchar int vertex_indices
+end_header
+0 0 0 255 0 0
+1 0 0 0 255 0
+1 1 0 0 0 255
+0 1 0 255 255 255
+4 0 1 2 3
+";
+
+    fn binary(big_endian: bool) -> Vec<u8> {
+        let format = if big_endian { "binary_big_endian" } else { "binary_little_endian" };
+        let mut bytes = format!("ply\nformat {} 1.0\nelement vertex 3\nproperty float x\nproperty float y\nproperty float z\n\
+            property float nx\nproperty float ny\nproperty float nz\nelement face 1\nproperty list uchar uint vertex_indices\n\
+            end_header\n", format).into_bytes();
+        let floats = [0., 0., 0., 0., 0., 1., 1., 0., 0., 0., 0., 1., 0., 1., 0., 0., 0., 1.];
+        for f in floats.iter() {
+            let f = *f as f32;
+            bytes.extend_from_slice(&if big_endian { f.to_be_bytes() } else { f.to_le_bytes() });
+        }
+        bytes.push(3);
+        for i in 0u32..3 {
+            bytes.extend_from_slice(&if big_endian { i.to_be_bytes() } else { i.to_le_bytes() });
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_read_ascii() {
+        let mesh = read(&mut ASCII.as_bytes()).unwrap();
+        assert_eq!(mesh.positions.len(), 4);
+        assert_eq!(mesh.positions[2], Point::new(1., 1., 0.));
+        assert_eq!(mesh.triangles, vec![[0, 1, 2], [0, 2, 3]]);
+        assert_eq!(mesh.colors.unwrap()[1], Color::new(0., 1., 0.));
+        assert!(mesh.normals.is_none());
+    }
+
+    #[test]
+    fn test_read_binary() {
+        for &big_endian in [false, true].iter() {
+            let mesh = read(&mut binary(big_endian).as_slice()).unwrap();
+            assert_eq!(mesh.positions, vec![Point::new(0., 0., 0.), Point::new(1., 0., 0.), Point::new(0., 1., 0.)]);
+            assert_eq!(mesh.normals.unwrap()[1], Vec3::new(0., 0., 1.));
+            assert_eq!(mesh.triangles, vec![[0, 1, 2]]);
+        }
+    }
+
+    #[test]
+    fn test_read_invalid() {
+        assert!(read(&mut &b"plx\n"[..]).is_err());
+        let truncated = &binary(false)[..100];
+        assert!(read(&mut &truncated[..]).is_err());
+        let bad_index = ASCII.replace("4 0 1 2 3", "3 0 1 9");
+        assert!(read(&mut bad_index.as_bytes()).is_err());
+    }
+}
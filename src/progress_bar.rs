@@ -0,0 +1,122 @@
+use std::io::{IsTerminal, Write};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::renderer::Progress;
+
+const BAR_WIDTH: usize = 30;
+// Redraw the bar at most this often; every band of rows reports its progress.
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A progress bar for headless renders, drawn on one line of the terminal.
+pub struct ProgressBar {
+    // A time-limited render is as far along as its time is used up.
+    time_limit: Option<Duration>,
+    // Whether to draw the bar, which needs a terminal to draw over.
+    visible: bool,
+    // The furthest progress reported, and when the bar was last drawn. Bands report from
+    // worker threads in no fixed order, so the last report may not be the furthest.
+    last: Mutex<(Option<Progress>, Option<Instant>)>,
+}
+
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    if seconds >= 3600 {
+        format!("{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+    } else {
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    }
+}
+
+impl ProgressBar {
+    pub fn new(time_limit: Option<Duration>) -> ProgressBar {
+        ProgressBar{time_limit, visible: std::io::stderr().is_terminal(), last: Mutex::new((None, None))}
+    }
+
+    fn fraction(&self, progress: &Progress) -> f64 {
+        match self.time_limit {
+            Some(limit) => progress.elapsed.as_secs_f64() / limit.as_secs_f64().max(1e-9),
+            None => progress.fraction(),
+        }.min(1.)
+    }
+
+    fn eta(&self, progress: &Progress) -> Option<Duration> {
+        match self.time_limit {
+            Some(limit) => Some(limit.saturating_sub(progress.elapsed)),
+            None => progress.eta(),
+        }
+    }
+
+    /// The bar and statistics for `progress`, e.g.
+    /// `[#######-------]  50.0%  pass 2, 8/16 tiles  1.25 Mrays/s  ETA 0:42`.
+    pub fn format(&self, progress: &Progress) -> String {
+        let fraction = self.fraction(progress);
+        let filled = (fraction * BAR_WIDTH as f64).round() as usize;
+        let eta = self.eta(progress).map_or("?".to_string(), format_duration);
+        format!(
+            "[{}{}] {:5.1}%  pass {}, {}/{} tiles  {:.2} Mrays/s  ETA {}",
+            "#".repeat(filled), "-".repeat(BAR_WIDTH - filled), 100. * fraction,
+            progress.pass, progress.tiles_done, progress.tiles, progress.rays_per_second() / 1e6, eta,
+        )
+    }
+
+    /// Records `progress`, redrawing the bar if it hasn't been drawn for a while.
+    pub fn update(&self, progress: &Progress) {
+        let mut last = self.last.lock().unwrap();
+        if last.0.is_some_and(|furthest| furthest.samples_done > progress.samples_done) {
+            return;
+        }
+        last.0 = Some(*progress);
+        if !self.visible || last.1.is_some_and(|drawn| drawn.elapsed() < REDRAW_INTERVAL) {
+            return;
+        }
+        last.1 = Some(Instant::now());
+        let mut stderr = std::io::stderr();
+        let _ = write!(stderr, "\r{}", self.format(progress));
+        let _ = stderr.flush();
+    }
+
+    /// Ends the bar's line and sums up the render.
+    pub fn finish(&self) {
+        let last = self.last.lock().unwrap();
+        if let Some(progress) = last.0 {
+            if last.1.is_some() {
+                eprintln!();
+            }
+            eprintln!(
+                "Rendered {} samples in {} ({:.2} Mrays/s)",
+                progress.samples_done, format_duration(progress.elapsed), progress.rays_per_second() / 1e6,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn progress(samples_done: u64, elapsed: u64) -> Progress {
+        Progress{tiles_done: 8, tiles: 16, pass: 2, samples_done, samples: 100, rays: 2_500_000, elapsed: Duration::from_secs(elapsed)}
+    }
+
+    #[test]
+    fn test_format() {
+        let bar = ProgressBar::new(None);
+        assert_eq!(
+            bar.format(&progress(25, 2)),
+            format!("[{}{}]  25.0%  pass 2, 8/16 tiles  1.25 Mrays/s  ETA 0:06", "#".repeat(8), "-".repeat(22)),
+        );
+        // Time-limited renders go by the clock instead of the samples.
+        let bar = ProgressBar::new(Some(Duration::from_secs(3700)));
+        assert!(bar.format(&progress(25, 100)).ends_with("ETA 1:00:00"));
+        assert!(ProgressBar::new(None).format(&progress(0, 0)).ends_with("ETA ?"));
+    }
+
+    #[test]
+    fn test_keeps_furthest_progress() {
+        let bar = ProgressBar::new(None);
+        bar.update(&progress(50, 2));
+        bar.update(&progress(25, 3));
+        assert_eq!(bar.last.lock().unwrap().0, Some(progress(50, 2)));
+    }
+}
@@ -0,0 +1,171 @@
+use numpy::{PyArray1, PyArray3, PyArrayMethods};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+use crate::denoise::Denoiser;
+use crate::film::Film;
+use crate::mesh::TriangleMesh;
+use crate::renderer::Renderer;
+use crate::scene_builder::{CameraDesc, MaterialDesc, SceneBuilder};
+use crate::vec3::Vec3;
+
+fn vec3(v: [f64; 3]) -> Vec3 {
+    Vec3::new(v[0], v[1], v[2])
+}
+
+fn value_error(e: std::io::Error) -> PyErr {
+    PyValueError::new_err(e.to_string())
+}
+
+fn material(desc: MaterialDesc) -> PyResult<Material> {
+    desc.validate().map_err(value_error)?;
+    Ok(Material{desc})
+}
+
+/// A surface material. Create one with the static methods; the same material can be
+/// given to any number of objects.
+#[pyclass(frozen)]
+#[derive(Debug, Clone)]
+struct Material {
+    desc: MaterialDesc,
+}
+
+#[pymethods]
+impl Material {
+    /// A diffuse surface.
+    #[staticmethod]
+    fn lambertian(albedo: [f64; 3]) -> PyResult<Material> {
+        material(MaterialDesc::Lambertian{albedo: vec3(albedo)})
+    }
+
+    /// A mirror, blurred by `fuzz`.
+    #[staticmethod]
+    #[pyo3(signature = (albedo, fuzz = 0.))]
+    fn metal(albedo: [f64; 3], fuzz: f64) -> PyResult<Material> {
+        material(MaterialDesc::Metal{albedo: vec3(albedo), fuzz})
+    }
+
+    /// Glass or another clear material with index of refraction `ior`.
+    #[staticmethod]
+    fn dielectric(ior: f64) -> PyResult<Material> {
+        material(MaterialDesc::Dielectric{ior})
+    }
+
+    /// The glTF metallic-roughness material, which can also glow with `emissive`.
+    #[staticmethod]
+    #[pyo3(signature = (base_color, metallic = 0., roughness = 0.5, emissive = [0., 0., 0.]))]
+    fn pbr(base_color: [f64; 3], metallic: f64, roughness: f64, emissive: [f64; 3]) -> PyResult<Material> {
+        material(MaterialDesc::Pbr{base_color: vec3(base_color), metallic, roughness, emissive: vec3(emissive)})
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{:?}", self.desc)
+    }
+}
+
+/// The objects and lights to render. Scenes are built again for each render, so they can
+/// keep changing between renders.
+#[pyclass]
+#[derive(Clone, Default)]
+struct Scene {
+    builder: SceneBuilder,
+}
+
+#[pymethods]
+impl Scene {
+    #[new]
+    fn new() -> Scene {
+        Scene::default()
+    }
+
+    fn add_sphere(&mut self, center: [f64; 3], radius: f64, material: Material) -> PyResult<()> {
+        self.builder.add_sphere(vec3(center), radius, material.desc).map_err(value_error)
+    }
+
+    /// Adds a mesh from triangle vertices, such as an (n, 3) array, and the vertex indices
+    /// of each triangle.
+    fn add_triangles(&mut self, vertices: Vec<[f64; 3]>, triangles: Vec<[u32; 3]>, material: Material) -> PyResult<()> {
+        let vertices = vertices.into_iter().map(vec3).collect();
+        self.builder.add_triangles(vertices, triangles, material.desc).map_err(value_error)
+    }
+
+    /// Adds a PLY or STL mesh, moved and scaled to fit a cube of side `size` around
+    /// `center` if given.
+    #[pyo3(signature = (path, material, center = None, size = 1.))]
+    fn add_mesh(&mut self, path: &str, material: Material, center: Option<[f64; 3]>, size: f64) -> PyResult<()> {
+        let mut mesh = TriangleMesh::load(path)?;
+        if let Some(center) = center {
+            mesh.fit_to(vec3(center), size);
+        }
+        self.builder.add_mesh(mesh, material.desc);
+        Ok(())
+    }
+
+    fn add_point_light(&mut self, position: [f64; 3], intensity: [f64; 3]) {
+        self.builder.add_point_light(vec3(position), vec3(intensity));
+    }
+
+    /// Adds a light infinitely far away, shining along `direction`.
+    fn add_directional_light(&mut self, direction: [f64; 3], irradiance: [f64; 3]) {
+        self.builder.add_directional_light(vec3(direction), vec3(irradiance));
+    }
+
+    fn __len__(&self) -> usize {
+        self.builder.len()
+    }
+}
+
+/// A pinhole camera at `look_from` looking towards `look_at`. Its aspect ratio is the
+/// rendered image's.
+#[pyclass(frozen)]
+#[derive(Debug, Clone)]
+struct Camera {
+    desc: CameraDesc,
+}
+
+#[pymethods]
+impl Camera {
+    #[new]
+    #[pyo3(signature = (look_from = [0., 0., 0.], look_at = [0., 0., -1.], up = [0., 1., 0.], vertical_fov = 90.))]
+    fn new(look_from: [f64; 3], look_at: [f64; 3], up: [f64; 3], vertical_fov: f64) -> PyResult<Camera> {
+        let desc = CameraDesc::new(vec3(look_from), vec3(look_at), vec3(up), vertical_fov).map_err(value_error)?;
+        Ok(Camera{desc})
+    }
+}
+
+/// Renders `scene` and returns the linear HDR image as a float32 array of shape
+/// (height, width, 3), with the top row first. The GIL is released while rendering.
+#[pyfunction]
+#[pyo3(signature = (scene, camera, width, height, samples = 16, max_depth = 50, seed = 0, spectral = false, denoise = false))]
+#[allow(clippy::too_many_arguments)]
+fn render<'py>(
+    py: Python<'py>, scene: &Scene, camera: &Camera, width: u32, height: u32,
+    samples: usize, max_depth: usize, seed: u64, spectral: bool, denoise: bool,
+) -> PyResult<Bound<'py, PyArray3<f32>>> {
+    if width == 0 || height == 0 || samples == 0 {
+        return Err(PyValueError::new_err("width, height and samples must be positive"));
+    }
+    let (scene, camera) = (scene.builder.clone(), camera.desc);
+    let image = py.detach(move || {
+        let camera = camera.build(width as f64 / height as f64);
+        let mut renderer = Renderer::new(width, height, max_depth, samples);
+        renderer.set_seed(seed);
+        renderer.set_spectral(spectral);
+        let mut film = Film::new(width as usize, height as usize);
+        renderer.draw(&scene.build(), &camera, &mut film);
+        let beauty = if denoise { Denoiser::default().denoise(&film) } else { film.beauty };
+        beauty.iter().flat_map(|c| [c.x as f32, c.y as f32, c.z as f32]).collect::<Vec<f32>>()
+    });
+    PyArray1::from_vec(py, image).reshape([height as usize, width as usize, 3])
+}
+
+/// The `raytracing` Python module.
+#[pymodule]
+fn raytracing(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    // Fail on import rather than at the first render if NumPy is missing.
+    m.py().import("numpy")?;
+    m.add_class::<Material>()?;
+    m.add_class::<Scene>()?;
+    m.add_class::<Camera>()?;
+    m.add_function(wrap_pyfunction!(render, m)?)
+}
@@ -5,11 +5,15 @@ use crate::vec3::Vec3;
 pub struct Ray {
     pub origin: Point,
     pub dir: Vec3,
+    pub time: f64,
+    /// The hero wavelength (in nm) of a spectral path, for materials whose behaviour
+    /// depends on it. `None` when rendering in RGB.
+    pub wavelength: Option<f64>,
 }
 
 impl Ray {
-    pub fn new(origin: Point, dir: Vec3) -> Ray {
-        Ray{origin, dir}
+    pub fn new(origin: Point, dir: Vec3, time: f64) -> Ray {
+        Ray{origin, dir, time, wavelength: None}
     }
 
     pub fn at(&self, t: f64) -> Point {
@@ -25,7 +29,7 @@ mod test {
     fn test_at() {
         let origin = Point::zeros();
         let dir = Vec3::new(1., 2., 3.);
-        let r = Ray::new(origin, dir);
+        let r = Ray::new(origin, dir, 0.);
         assert_eq!(r.at(1.0), Point::new(1., 2., 3.));
     }
 }
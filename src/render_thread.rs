@@ -0,0 +1,170 @@
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+
+use crate::camera::Camera;
+use crate::denoise::Denoiser;
+use crate::film::Film;
+use crate::renderer::{CancellationToken, RenderLimits, RenderStatus, Renderer};
+use crate::sampler::SamplerKind;
+use crate::scene::Scene;
+use crate::vec3::Color;
+
+/// What the render thread should render next.
+pub struct RenderJob {
+    pub camera: Camera,
+    pub samples: usize,
+    pub sampler: SamplerKind,
+    /// Denoise the image once it has all its samples too.
+    pub denoise: bool,
+}
+
+/// An image published by the render thread at the end of each pass of a job.
+pub struct Frame {
+    pub film: Film,
+    /// The image to show: the film's beauty, denoised on passes before the last like a
+    /// preview, and on the last if the job asked.
+    pub display: Vec<Color>,
+    pub samples: usize,
+    /// Whether this is the job's last pass.
+    pub complete: bool,
+}
+
+/// Renders on a worker thread, so the window keeps handling input while a frame renders.
+/// Submitting a job cancels the one in progress, whose frames are then never received.
+pub struct RenderThread {
+    jobs: Option<Sender<(u64, RenderJob, CancellationToken)>>,
+    frames: Receiver<(u64, Frame)>,
+    // The number of the latest job, and the token that cancels it.
+    current: u64,
+    cancel: CancellationToken,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl RenderThread {
+    /// Starts the thread, which calls `wake` whenever it has published a frame.
+    pub fn spawn<F>(mut renderer: Renderer, scene: Arc<Scene>, wake: F) -> RenderThread
+        where F: Fn() + Send + 'static
+    {
+        let (job_sender, jobs) = mpsc::channel::<(u64, RenderJob, CancellationToken)>();
+        let (frame_sender, frames) = mpsc::channel();
+        let handle = thread::spawn(move || {
+            let denoiser = Denoiser::default();
+            while let Ok(mut next) = jobs.recv() {
+                // Jobs queued up meanwhile have been replaced by the newest one.
+                while let Ok(newer) = jobs.try_recv() {
+                    next = newer;
+                }
+                let (id, job, cancel) = next;
+                renderer.set_samples_per_pixel(job.samples);
+                renderer.set_sampler(job.sampler);
+                let limits = RenderLimits{deadline: None, cancel: Some(cancel)};
+                let mut state = renderer.start();
+                loop {
+                    let status = renderer.render_pass(&scene, &job.camera, &mut state, &limits);
+                    if status == Some(RenderStatus::Cancelled) {
+                        break;
+                    }
+                    let complete = status.is_some();
+                    let mut film = renderer.new_film();
+                    renderer.resolve(&state, &mut film);
+                    let display = if !complete || job.denoise { denoiser.denoise(&film) } else { film.beauty.clone() };
+                    if frame_sender.send((id, Frame{film, display, samples: job.samples, complete})).is_err() {
+                        return;
+                    }
+                    wake();
+                    if complete {
+                        break;
+                    }
+                }
+            }
+        });
+        RenderThread{
+            jobs: Some(job_sender),
+            frames,
+            current: 0,
+            cancel: CancellationToken::new(),
+            handle: Some(handle),
+        }
+    }
+
+    /// Cancels the job in progress, if any, and starts on `job`.
+    pub fn submit(&mut self, job: RenderJob) {
+        self.cancel.cancel();
+        self.cancel = CancellationToken::new();
+        self.current += 1;
+        if let Some(jobs) = &self.jobs {
+            // The thread only stops once `self` is dropped.
+            jobs.send((self.current, job, self.cancel.clone())).expect("render thread stopped");
+        }
+    }
+
+    /// The newest frame of the latest job published since the last call, if any.
+    pub fn latest_frame(&self) -> Option<Frame> {
+        self.frames.try_iter().filter(|(id, _)| *id == self.current).map(|(_, frame)| frame).last()
+    }
+}
+
+impl Drop for RenderThread {
+    fn drop(&mut self) {
+        self.cancel.cancel();
+        // Closing the channel ends the thread's loop.
+        self.jobs = None;
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::HittableList;
+    use crate::material::Lambertian;
+    use crate::sphere::Sphere;
+
+    fn spawn() -> (RenderThread, Receiver<()>) {
+        let scene = Scene::new(HittableList::new(vec![
+            Box::new(Sphere::new(0., -100.5, -1., 100., Box::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))))),
+        ]), Vec::new());
+        let (wake_sender, woken) = mpsc::channel();
+        let thread = RenderThread::spawn(Renderer::new(16, 8, 10, 4), Arc::new(scene), move || {
+            let _ = wake_sender.send(());
+        });
+        (thread, woken)
+    }
+
+    fn job(samples: usize) -> RenderJob {
+        RenderJob{camera: Camera::new(90., 2.), samples, sampler: SamplerKind::Sobol, denoise: false}
+    }
+
+    /// Waits for frames until the latest job completes.
+    fn wait_for_completion(thread: &RenderThread, woken: &Receiver<()>) -> Frame {
+        loop {
+            woken.recv().unwrap();
+            if let Some(frame) = thread.latest_frame().filter(|frame| frame.complete) {
+                return frame;
+            }
+        }
+    }
+
+    #[test]
+    fn test_render_thread_publishes_passes() {
+        let (mut thread, woken) = spawn();
+        thread.submit(job(40));
+        let frame = wait_for_completion(&thread, &woken);
+        assert_eq!(frame.samples, 40);
+        assert!(frame.film.samples.iter().all(|&n| n == 40));
+        assert_eq!(frame.display, frame.film.beauty);
+    }
+
+    #[test]
+    fn test_submit_replaces_job_in_progress() {
+        let (mut thread, woken) = spawn();
+        thread.submit(job(1 << 20));
+        thread.submit(job(8));
+        let frame = wait_for_completion(&thread, &woken);
+        assert_eq!(frame.samples, 8);
+        assert!(frame.film.samples.iter().all(|&n| n == 8));
+    }
+}
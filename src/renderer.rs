@@ -1,76 +1,697 @@
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
 use crate::camera::Camera;
-use crate::hittable::{Hittable, HittableList};
+use crate::checkpoint::{invalid_data, read_f64, read_u32, read_u64, read_vec3, write_f64, write_u32, write_u64, write_vec3};
+use crate::film::{Film, FilmPixel, SplatTile};
+use crate::filter::Filter;
+use crate::fps::{self, Counter};
+use crate::hittable::{HitRecord, Hittable};
 use crate::ray::Ray;
-use crate::vec3::{Color, Vec3};
-
-use rand::distributions::{Distribution, Uniform};
+use crate::sampler::{Sampler, SamplerKind};
+use crate::scene::Scene;
+use crate::spectrum::{SampledSpectrum, SampledWavelengths, SpectralFilm};
+use crate::vec3::{Color, Vec3, luminance};
 
 const T_MIN: f64 = 0.001;
 
+/// Samples per pixel in each pass over the image. A render only finishes between passes,
+/// but stops between pixels when it runs out of time or is cancelled.
+const PASS_SAMPLES: usize = 16;
+
+/// Luminance below which pixels count as this bright when judging their relative error,
+/// so the noise of nearly black pixels doesn't keep them sampling forever.
+const DARK_LUMINANCE: f64 = 0.05;
+
+/// Stops sampling pixels early once their estimate is good enough.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AdaptiveSampling {
+    /// Samples taken before the first convergence check, and between checks.
+    pub min_samples: usize,
+    /// The standard error of a pixel's mean luminance, relative to the mean, to stop at.
+    pub target_error: f64,
+}
+
+/// Lets another thread stop a render early.
+#[derive(Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// How a call to `Renderer::render` ended.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum RenderStatus {
+    Complete,
+    TimedOut,
+    Cancelled,
+}
+
+/// When to stop a render before it has all its samples.
+#[derive(Clone, Default)]
+pub struct RenderLimits {
+    pub deadline: Option<Instant>,
+    pub cancel: Option<CancellationToken>,
+}
+
+impl RenderLimits {
+    /// How the render should stop, if it should.
+    fn reached(&self) -> Option<RenderStatus> {
+        if self.cancel.as_ref().is_some_and(|token| token.is_cancelled()) {
+            Some(RenderStatus::Cancelled)
+        } else if self.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            Some(RenderStatus::TimedOut)
+        } else {
+            None
+        }
+    }
+}
+
+/// How far a render has got, reported each time a band of rows finishes a pass.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Progress {
+    /// Bands of rows finished in the current pass, out of `tiles`.
+    pub tiles_done: usize,
+    pub tiles: usize,
+    /// The current pass, counting from 1.
+    pub pass: usize,
+    /// Pixel samples taken so far, out of the most the render can take.
+    pub samples_done: u64,
+    pub samples: u64,
+    /// Rays intersected with the scene so far: camera, bounce and shadow rays.
+    pub rays: u64,
+    pub elapsed: Duration,
+}
+
+impl Progress {
+    /// The fraction of the samples taken. Adaptive sampling can finish early.
+    pub fn fraction(&self) -> f64 {
+        if self.samples == 0 { 1. } else { self.samples_done as f64 / self.samples as f64 }
+    }
+
+    /// The time left at the rate so far, once there is a rate to go by.
+    pub fn eta(&self) -> Option<Duration> {
+        let fraction = self.fraction();
+        if fraction <= 0. {
+            return None;
+        }
+        Some(self.elapsed.mul_f64((1. - fraction).max(0.) / fraction))
+    }
+
+    pub fn rays_per_second(&self) -> f64 {
+        self.rays as f64 / self.elapsed.as_secs_f64().max(1e-9)
+    }
+}
+
+/// Called with the progress of a render, from whichever thread made it.
+pub type ProgressCallback = Arc<dyn Fn(&Progress) + Send + Sync>;
+
+/// Running mean and variance of a pixel's sample luminances, by Welford's algorithm.
+#[derive(Default)]
+struct RunningVariance {
+    count: usize,
+    mean: f64,
+    m2: f64,
+}
+
+impl RunningVariance {
+    fn add(&mut self, x: f64) {
+        self.count += 1;
+        let delta = x - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (x - self.mean);
+    }
+
+    /// The standard error of the mean relative to the mean.
+    fn relative_error(&self) -> f64 {
+        if self.count < 2 {
+            return f64::INFINITY;
+        }
+        let variance = self.m2 / (self.count - 1) as f64;
+        (variance / self.count as f64).sqrt() / self.mean.abs().max(DARK_LUMINANCE)
+    }
+}
+
 pub struct Renderer {
     width: u32,
     height: u32,
     t_min: f64, // 0.001
     max_ray_depth: usize,
     samples_per_pixel: usize,
+    // Every pixel sample's random numbers derive from this.
+    seed: u64,
+    sampler: SamplerKind,
+    filter: Filter,
+    adaptive: Option<AdaptiveSampling>,
+    // Set when tracing spectral paths instead of RGB ones.
+    spectral_film: Option<SpectralFilm>,
+    progress: Option<ProgressCallback>,
+}
+
+/// A band of rows of a render in progress, which starts at pixel `first` of the image.
+struct Band {
+    first: usize,
+    tile: SplatTile,
+    stats: Vec<RunningVariance>,
+}
+
+/// A render in progress: the samples splatted so far and each pixel's statistics. It can
+/// be continued after it stops early, resolved into a film at any point, and saved to be
+/// continued by another process.
+pub struct RenderState {
+    pixels: Vec<FilmPixel>,
+    bands: Vec<Band>,
+    band_rows: usize,
+    // Every pixel gets this many samples, unless it converges, before the next pass starts.
+    pass_end: usize,
+    pass: usize,
+    samples_done: u64,
+    rays: u64,
+    started: Instant,
+}
+
+impl RenderState {
+    /// Writes everything needed to continue the render. Samples are derived from the seed
+    /// and each pixel's sample index, so the sampler itself needs no saving.
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        write_u64(writer, self.pass_end as u64)?;
+        write_u64(writer, self.pass as u64)?;
+        write_u64(writer, self.samples_done)?;
+        write_u64(writer, self.rays)?;
+        write_u64(writer, self.started.elapsed().as_nanos() as u64)?;
+        for pixel in &self.pixels {
+            write_f64(writer, pixel.depth)?;
+            write_vec3(writer, &pixel.normal)?;
+            write_vec3(writer, &pixel.albedo)?;
+            write_u32(writer, pixel.material.map_or(0, |material| material as u32 + 1))?;
+            write_u64(writer, pixel.object.map_or(0, |object| object as u64 + 1))?;
+        }
+        for index in 0..self.bands.len() {
+            self.write_band(index, writer)?;
+        }
+        Ok(())
+    }
+
+    /// The number of bands of rows the image is rendered in.
+    pub fn band_count(&self) -> usize {
+        self.bands.len()
+    }
+
+    /// Writes the samples of band `index`: its tile and its pixels' statistics.
+    pub fn write_band<W: Write>(&self, index: usize, writer: &mut W) -> io::Result<()> {
+        let band = &self.bands[index];
+        band.tile.write(writer)?;
+        for stats in &band.stats {
+            write_u64(writer, stats.count as u64)?;
+            write_f64(writer, stats.mean)?;
+            write_f64(writer, stats.m2)?;
+        }
+        Ok(())
+    }
+
+    /// Reads the samples of band `index` written by `write_band`, replacing its own.
+    pub fn read_band<R: Read>(&mut self, index: usize, reader: &mut R) -> io::Result<()> {
+        let band = &mut self.bands[index];
+        band.tile.read(reader)?;
+        for stats in band.stats.iter_mut() {
+            stats.count = read_u64(reader)? as usize;
+            stats.mean = read_f64(reader)?;
+            stats.m2 = read_f64(reader)?;
+        }
+        Ok(())
+    }
+
+    /// Reads a render written by `write` with the same settings as `renderer`.
+    pub fn read<R: Read>(reader: &mut R, renderer: &Renderer) -> io::Result<RenderState> {
+        let mut state = renderer.start();
+        state.pass_end = read_u64(reader)? as usize;
+        state.pass = read_u64(reader)? as usize;
+        state.samples_done = read_u64(reader)?;
+        state.rays = read_u64(reader)?;
+        let elapsed = Duration::from_nanos(read_u64(reader)?);
+        state.started = Instant::now().checked_sub(elapsed).unwrap_or_else(Instant::now);
+        if state.pass_end > renderer.samples_per_pixel {
+            return Err(invalid_data("checkpoint has more samples than the render"));
+        }
+        for pixel in state.pixels.iter_mut() {
+            pixel.depth = read_f64(reader)?;
+            pixel.normal = read_vec3(reader)?;
+            pixel.albedo = read_vec3(reader)?;
+            pixel.material = match read_u32(reader)? { 0 => None, material => Some(material as usize - 1) };
+            pixel.object = match read_u64(reader)? { 0 => None, object => Some(object as usize - 1) };
+        }
+        for index in 0..state.bands.len() {
+            state.read_band(index, reader)?;
+        }
+        Ok(state)
+    }
 }
 
 impl Renderer {
     pub fn new(width: u32, height: u32, max_ray_depth: usize, samples_per_pixel: usize) -> Renderer {
         let t_min = T_MIN;
-        Renderer{width, height, t_min, max_ray_depth, samples_per_pixel}
+        Renderer{width, height, t_min, max_ray_depth, samples_per_pixel, seed: 0, sampler: SamplerKind::Sobol, filter: Filter::default(), adaptive: None, spectral_film: None, progress: None}
+    }
+
+    pub fn set_samples_per_pixel(&mut self, samples_per_pixel: usize) {
+        self.samples_per_pixel = samples_per_pixel;
+    }
+
+    /// Sets the seed the random numbers are derived from. The same seed renders the same image.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+
+    pub fn set_sampler(&mut self, sampler: SamplerKind) {
+        self.sampler = sampler;
+    }
+
+    pub fn set_filter(&mut self, filter: Filter) {
+        self.filter = filter;
+    }
+
+    /// With adaptive sampling, `samples_per_pixel` is the most a pixel gets.
+    pub fn set_adaptive(&mut self, adaptive: Option<AdaptiveSampling>) {
+        self.adaptive = adaptive;
+    }
+
+    /// The settings that decide what an image looks like, to check a render is continued
+    /// the way it was started.
+    pub fn settings(&self) -> String {
+        format!(
+            "{}x{}, {} samples, depth {}, seed {}, {} sampler, {} filter of radius {}, adaptive {:?}, spectral {}",
+            self.width, self.height, self.samples_per_pixel, self.max_ray_depth, self.seed, self.sampler,
+            self.filter.kind, self.filter.radius, self.adaptive, self.spectral_film.is_some(),
+        )
+    }
+
+    /// Reports the progress of renders to `progress` as they go.
+    pub fn set_progress(&mut self, progress: Option<ProgressCallback>) {
+        self.progress = progress;
+    }
+
+    /// Whether a pixel needs no more samples than the ones in `stats`.
+    fn converged(&self, stats: &RunningVariance) -> bool {
+        match &self.adaptive {
+            Some(adaptive) => stats.count.is_multiple_of(adaptive.min_samples.max(1)) && stats.relative_error() < adaptive.target_error,
+            None => false,
+        }
+    }
+
+    /// Switches between RGB and spectral path tracing. Spectral paths sample wavelengths,
+    /// which lets dispersive materials split light into its colors.
+    pub fn set_spectral(&mut self, spectral: bool) {
+        self.spectral_film = if spectral { Some(SpectralFilm::new()) } else { None };
     }
 
-    fn ray_color(&self, world: &HittableList, ray: &Ray, depth: usize) -> Color {
+    /// The BSDF (times cosine) and incoming radiance for each of the scene's lights that
+    /// is visible from the hit. Rays never hit these lights by chance, so they're sampled directly.
+    fn visible_lights(&self, scene: &Scene, ray: &Ray, hit_record: &HitRecord) -> Vec<(Color, Color)> {
+        let mut visible = Vec::new();
+        for light in scene.lights.iter() {
+            if let Some((wi, distance, radiance)) = light.illuminate(&hit_record.p) {
+                let f = hit_record.material.eval(ray, hit_record, &wi);
+                if f == Color::zeros() {
+                    continue;
+                }
+                let shadow_ray = Ray::new(hit_record.p, wi, ray.time);
+                fps::count(Counter::ShadowRays);
+                if scene.world.hit(&shadow_ray, self.t_min, distance).is_none() {
+                    visible.push((f, radiance));
+                }
+            }
+        }
+        visible
+    }
+
+    fn background(ray: &Ray) -> Color {
+        let unit_direction = Vec3::unit_vec(&ray.dir);
+        let t = 0.5 * (unit_direction.y + 1.0);
+        Color::new(1.0, 1.0, 1.0) * (1.0 - t) + Color::new(0.5, 0.7, 1.0) * t
+    }
+
+    /// Counts a ray along a path, which left the camera if it has all its bounces left.
+    fn count_path_ray(&self, depth: usize) {
+        fps::count(if depth == self.max_ray_depth { Counter::PrimaryRays } else { Counter::SecondaryRays });
+        fps::count(Counter::PathSegments);
+    }
+
+    fn ray_color(&self, scene: &Scene, ray: &Ray, sampler: &mut dyn Sampler, depth: usize) -> Color {
         if depth == 0 {
-            Color::zeros()
-        } else if let Some(hit_record) = world.hit(ray, self.t_min, f64::INFINITY) {
-            if let Some((scattered, attenuation)) = hit_record.material.scatter(ray, &hit_record) {
-                attenuation * self.ray_color(world, &scattered, depth - 1)
+            return Color::zeros();
+        }
+        self.count_path_ray(depth);
+        if let Some(hit_record) = scene.world.hit(ray, self.t_min, f64::INFINITY) {
+            let mut emitted = hit_record.material.emitted(&hit_record);
+            for (f, radiance) in self.visible_lights(scene, ray, &hit_record) {
+                emitted += f * radiance;
+            }
+            if let Some((scattered, attenuation)) = hit_record.material.scatter(ray, &hit_record, sampler) {
+                emitted + attenuation * self.ray_color(scene, &scattered, sampler, depth - 1)
             } else {
-                Color::zeros()
+                emitted
             }
         } else {
-            let unit_direction = Vec3::unit_vec(&ray.dir);
-            let t = 0.5 * (unit_direction.y + 1.0);
-            Color::new(1.0, 1.0, 1.0) * (1.0 - t) + Color::new(0.5, 0.7, 1.0) * t
-        }
-    }
-
-    fn draw_pixels(&self, world: &HittableList, camera: &Camera, pixel_chunk: &mut [(usize, &mut [u8])]) {
-        let between = Uniform::new(0., 1.);
-        let mut rng = rand::thread_rng();
-        for (i, pixel) in pixel_chunk {
-            let mut color = Color::zeros();
-            for _ in 0..self.samples_per_pixel {
-                let i = *i as u32;
-                let x = ((i % self.width) as f64 + between.sample(&mut rng)) / (self.width as f64);
-                let y = ((i / self.width) as f64) / (self.height as f64);
-                let ray = camera.get_ray(x, y);
-                color += self.ray_color(world, &ray, self.max_ray_depth);
+            Renderer::background(ray)
+        }
+    }
+
+    /// Like `ray_color`, but for the radiance at each of the path's wavelengths. RGB
+    /// colors from materials, lights and the background are upsampled to spectra.
+    fn spectral_ray_color(&self, scene: &Scene, ray: &Ray, wavelengths: &mut SampledWavelengths, sampler: &mut dyn Sampler, depth: usize) -> SampledSpectrum {
+        if depth == 0 {
+            return SampledSpectrum::zeros();
+        }
+        self.count_path_ray(depth);
+        if let Some(hit_record) = scene.world.hit(ray, self.t_min, f64::INFINITY) {
+            if hit_record.material.is_dispersive() {
+                wavelengths.terminate_secondary();
+            }
+            let mut emitted = wavelengths.upsample(&hit_record.material.emitted(&hit_record));
+            for (f, radiance) in self.visible_lights(scene, ray, &hit_record) {
+                emitted = emitted + wavelengths.upsample(&f) * wavelengths.upsample(&radiance);
+            }
+            if let Some((mut scattered, attenuation)) = hit_record.material.scatter(ray, &hit_record, sampler) {
+                scattered.wavelength = ray.wavelength;
+                emitted + wavelengths.upsample(&attenuation) * self.spectral_ray_color(scene, &scattered, wavelengths, sampler, depth - 1)
+            } else {
+                emitted
+            }
+        } else {
+            wavelengths.upsample(&Renderer::background(ray))
+        }
+    }
+
+    /// Fills in the auxiliary buffers of `pixel` from the first hit of the ray through the
+    /// center of pixel `i`, in the middle of the shutter interval.
+    fn first_hit(&self, scene: &Scene, camera: &Camera, i: u32, pixel: &mut FilmPixel) {
+        let x = ((i % self.width) as f64 + 0.5) / (self.width as f64);
+        let y = ((i / self.width) as f64 + 0.5) / (self.height as f64);
+        let ray = camera.get_ray(x, y, 0.5);
+        fps::count(Counter::PrimaryRays);
+        if let Some((object, hit_record)) = scene.world.hit_object(&ray, self.t_min, f64::INFINITY) {
+            pixel.depth = hit_record.t * ray.dir.length();
+            pixel.normal = hit_record.normal;
+            pixel.albedo = hit_record.material.albedo(&hit_record);
+            pixel.material = scene.material_index(hit_record.material);
+            pixel.object = Some(object);
+        }
+    }
+
+    /// Brings the pixels of `band` up to `pass_end` samples each, or until they converge,
+    /// splatting the samples into its tile. Stops early if `limits` are reached. Returns the
+    /// number of samples taken and rays traced.
+    fn draw_pixels(&self, scene: &Scene, camera: &Camera, band: &mut Band, pixels: &mut [FilmPixel], pass_end: usize, limits: &RenderLimits) -> (u64, u64) {
+        let rays_before = fps::thread_rays();
+        let started = Instant::now();
+        let mut samples = 0;
+        let mut sampler = self.sampler.create(self.seed, self.samples_per_pixel);
+        let Band{first, tile, stats} = band;
+        for (offset, (pixel, stats)) in pixels.iter_mut().zip(stats.iter_mut()).enumerate() {
+            if stats.count >= pass_end || self.converged(stats) {
+                continue;
+            }
+            if limits.reached().is_some() {
+                break;
+            }
+            let i = (*first + offset) as u32;
+            let (px, py) = (i % self.width, i / self.width);
+            if stats.count == 0 {
+                self.first_hit(scene, camera, i, pixel);
+            }
+            for sample in stats.count..pass_end {
+                sampler.start_pixel_sample(px, py, sample);
+                let (dx, dy) = sampler.get_2d();
+                let (x, y) = (px as f64 + dx, py as f64 + dy);
+                let mut ray = camera.get_ray(x / self.width as f64, y / self.height as f64, sampler.get_1d());
+                fps::count(Counter::Paths);
+                let color = match &self.spectral_film {
+                    Some(film) => {
+                        let mut wavelengths = SampledWavelengths::sample_hero(sampler.get_1d());
+                        ray.wavelength = Some(wavelengths.hero());
+                        let radiance = self.spectral_ray_color(scene, &ray, &mut wavelengths, sampler.as_mut(), self.max_ray_depth);
+                        film.to_rgb(&wavelengths, &radiance)
+                    }
+                    None => self.ray_color(scene, &ray, sampler.as_mut(), self.max_ray_depth),
+                };
+                tile.add(x, y, &color, &self.filter);
+                stats.add(luminance(&color));
+                samples += 1;
+                if self.converged(stats) {
+                    break;
+                }
+            }
+        }
+        fps::flush(started.elapsed());
+        (samples, fps::thread_rays() - rays_before)
+    }
+
+    /// An empty film of the renderer's image size.
+    pub fn new_film(&self) -> Film {
+        Film::new(self.width as usize, self.height as usize)
+    }
+
+    /// Starts a render of the renderer's image size with nothing sampled yet.
+    pub fn start(&self) -> RenderState {
+        let (width, height) = (self.width as usize, self.height as usize);
+        // Bands of rows are rendered in parallel, each splatting into its own tile.
+        let num_bands: usize = 16;
+        let band_rows = height.div_ceil(num_bands).max(1);
+        let bands = (0..height).step_by(band_rows).map(|row| {
+            let rows = row..(row + band_rows).min(height);
+            Band{
+                first: row * width,
+                stats: (0..rows.len() * width).map(|_| RunningVariance::default()).collect(),
+                tile: SplatTile::new(width, height, rows, self.filter.radius),
+            }
+        }).collect();
+        let pass_end = PASS_SAMPLES.min(self.samples_per_pixel);
+        RenderState{
+            pixels: vec![FilmPixel::default(); width * height],
+            bands,
+            band_rows,
+            pass_end,
+            pass: 1,
+            samples_done: 0,
+            rays: 0,
+            started: Instant::now(),
+        }
+    }
+
+    /// Continues `state` pass by pass until every pixel has its samples or has converged,
+    /// or until `limits` stop it. A stopped render can be continued by calling this again.
+    pub fn render(&self, scene: &Scene, camera: &Camera, state: &mut RenderState, limits: &RenderLimits) -> RenderStatus {
+        loop {
+            if let Some(status) = self.render_pass(scene, camera, state, limits) {
+                return status;
+            }
+        }
+    }
+
+    /// Continues `state` until the end of its current pass. Returns how the render ended if
+    /// it did, or `None` if there are more passes to go.
+    pub fn render_pass(&self, scene: &Scene, camera: &Camera, state: &mut RenderState, limits: &RenderLimits) -> Option<RenderStatus> {
+        let chunk_size = state.band_rows * self.width as usize;
+        let pass_end = state.pass_end;
+        let (pass, started, tiles) = (state.pass, state.started, state.bands.len());
+        let total_samples = (self.width as u64 * self.height as u64).saturating_mul(self.samples_per_pixel as u64);
+        let tiles_done = AtomicUsize::new(0);
+        let samples_done = AtomicU64::new(state.samples_done);
+        let rays = AtomicU64::new(state.rays);
+        rayon::scope(|s| {
+            for (band, chunk) in state.bands.iter_mut().zip(state.pixels.chunks_mut(chunk_size)) {
+                let (tiles_done, samples_done, rays) = (&tiles_done, &samples_done, &rays);
+                s.spawn(move |_| {
+                    let (band_samples, band_rays) = self.draw_pixels(scene, camera, band, chunk, pass_end, limits);
+                    let samples_done = samples_done.fetch_add(band_samples, Ordering::Relaxed) + band_samples;
+                    let rays = rays.fetch_add(band_rays, Ordering::Relaxed) + band_rays;
+                    let tiles_done = tiles_done.fetch_add(1, Ordering::Relaxed) + 1;
+                    if let Some(progress) = &self.progress {
+                        progress(&Progress{tiles_done, tiles, pass, samples_done, samples: total_samples, rays, elapsed: started.elapsed()});
+                    }
+                });
             }
-            // Divide the color by the number of samples and gamma-correct for gamma=2.0.
-            let scale = 1. / (self.samples_per_pixel as f64);
-            color.x = (scale * color.x).sqrt();
-            color.y = (scale * color.y).sqrt();
-            color.z = (scale * color.z).sqrt();
-            let color = color.clamped(0., 0.999) * 256.;
-            let rgba = [color[0] as u8, color[1] as u8, color[2] as u8, 0xff];
-            pixel.copy_from_slice(&rgba);
-        }
-    }
-
-    pub fn draw(&self, world: &HittableList, camera: &Camera, frame: &mut [u8]) {
-        let mut pixel_list: Vec<(usize, &mut [u8])> = frame.chunks_exact_mut(4).enumerate().collect();
-        let num_threads: usize = 16;
-        // TODO: does this need to be adjusted, e.g. what if it's a 4x2 image but num_threads is 3?
-        let chunk_size = pixel_list.len() / num_threads;
+        });
+        state.samples_done = samples_done.into_inner();
+        state.rays = rays.into_inner();
+        if let Some(status) = limits.reached() {
+            return Some(status);
+        }
+        let all_converged = self.adaptive.is_some() && state.bands.iter().all(|band| band.stats.iter().all(|s| self.converged(s)));
+        if pass_end >= self.samples_per_pixel || all_converged {
+            return Some(RenderStatus::Complete);
+        }
+        state.pass_end = (pass_end + PASS_SAMPLES).min(self.samples_per_pixel);
+        state.pass += 1;
+        None
+    }
+
+    /// Renders the bands of `state` listed in `indices` to completion, in parallel, leaving
+    /// the other bands to other processes. Each band ends up as a whole render would leave it.
+    pub fn render_bands(&self, scene: &Scene, camera: &Camera, state: &mut RenderState, indices: &[usize]) {
+        let chunk_size = state.band_rows * self.width as usize;
+        let limits = RenderLimits::default();
         rayon::scope(|s| {
-            for chunk in pixel_list.chunks_exact_mut(chunk_size) {
+            for (index, (band, chunk)) in state.bands.iter_mut().zip(state.pixels.chunks_mut(chunk_size)).enumerate() {
+                if !indices.contains(&index) {
+                    continue;
+                }
+                let limits = &limits;
                 s.spawn(move |_| {
-                    self.draw_pixels(world, camera, chunk);
+                    let mut pass_end = PASS_SAMPLES.min(self.samples_per_pixel);
+                    loop {
+                        self.draw_pixels(scene, camera, band, chunk, pass_end, limits);
+                        let converged = self.adaptive.is_some() && band.stats.iter().all(|s| self.converged(s));
+                        if pass_end >= self.samples_per_pixel || converged {
+                            break;
+                        }
+                        pass_end = (pass_end + PASS_SAMPLES).min(self.samples_per_pixel);
+                    }
                 });
             }
         });
     }
+
+    /// Fills in the auxiliary buffers of every pixel of `state`, for bands whose samples
+    /// were rendered elsewhere.
+    pub fn first_hits(&self, scene: &Scene, camera: &Camera, state: &mut RenderState) {
+        let chunk_size = state.band_rows * self.width as usize;
+        rayon::scope(|s| {
+            for (index, chunk) in state.pixels.chunks_mut(chunk_size).enumerate() {
+                s.spawn(move |_| {
+                    for (offset, pixel) in chunk.iter_mut().enumerate() {
+                        self.first_hit(scene, camera, (index * chunk_size + offset) as u32, pixel);
+                    }
+                });
+            }
+        });
+    }
+
+    /// Stores the image rendered so far in `state`, and its auxiliary buffers, in `film`.
+    pub fn resolve(&self, state: &RenderState, film: &mut Film) {
+        let mut pixels = state.pixels.clone();
+        let colors = SplatTile::merge(state.bands.iter().map(|band| &band.tile), film.width, film.height);
+        let counts = state.bands.iter().flat_map(|band| band.stats.iter().map(|s| s.count as u32));
+        for ((pixel, color), count) in pixels.iter_mut().zip(colors).zip(counts) {
+            pixel.color = color;
+            pixel.samples = count;
+        }
+        film.store(&pixels);
+    }
+
+    /// Renders the linear HDR radiance of each pixel and its auxiliary buffers into `film`.
+    pub fn draw(&self, scene: &Scene, camera: &Camera, film: &mut Film) {
+        let mut state = self.start();
+        self.render(scene, camera, &mut state, &RenderLimits::default());
+        self.resolve(&state, film);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::HittableList;
+    use crate::material::{Dielectric, Lambertian};
+    use crate::sphere::Sphere;
+
+    fn scene() -> Scene {
+        Scene::new(HittableList::new(vec![
+            Box::new(Sphere::new(0., -100.5, -1., 100., Box::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))))),
+            Box::new(Sphere::new(0., 0., -1., 0.5, Box::new(Dielectric::new(1.5)))),
+        ]), Vec::new())
+    }
+
+    fn render(seed: u64, threads: usize) -> Vec<Color> {
+        let mut renderer = Renderer::new(16, 8, 10, 4);
+        renderer.set_seed(seed);
+        let mut film = Film::new(16, 8);
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+        pool.install(|| renderer.draw(&scene(), &Camera::new(90., 2.), &mut film));
+        film.beauty
+    }
+
+    #[test]
+    fn test_render_is_reproducible() {
+        let reference = render(1, 1);
+        assert_eq!(reference, render(1, 4));
+        assert_ne!(reference, render(2, 1));
+    }
+
+    #[test]
+    fn test_running_variance() {
+        let mut stats = RunningVariance::default();
+        for x in [1., 2., 3., 4.].iter() {
+            stats.add(*x);
+        }
+        // Mean 2.5 and sample variance 5/3, so the standard error is sqrt(5/12).
+        assert!((stats.relative_error() - (5f64 / 12.).sqrt() / 2.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_adaptive_sampling_skips_smooth_pixels() {
+        let mut renderer = Renderer::new(16, 8, 10, 64);
+        renderer.set_adaptive(Some(AdaptiveSampling{min_samples: 8, target_error: 0.02}));
+        let mut film = Film::new(16, 8);
+        renderer.draw(&scene(), &Camera::new(90., 2.), &mut film);
+        // The sky along the top barely changes within a pixel; the diffuse ground is noisy.
+        assert!(film.samples[..16].iter().all(|&n| n == 8));
+        assert!(film.samples[16 * 7..].iter().any(|&n| n > 8));
+        assert!(film.samples.iter().all(|&n| n <= 64));
+    }
+
+    #[test]
+    fn test_cancelled_render_keeps_partial_image() {
+        let renderer = Renderer::new(16, 8, 10, 64);
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        let mut state = renderer.start();
+        let limits = RenderLimits{deadline: None, cancel: Some(cancel)};
+        assert_eq!(renderer.render(&scene(), &Camera::new(90., 2.), &mut state, &limits), RenderStatus::Cancelled);
+        let mut film = Film::new(16, 8);
+        renderer.resolve(&state, &mut film);
+        assert!(film.samples.iter().all(|&n| n == 0));
+    }
+
+    #[test]
+    fn test_timed_out_render_resumes() {
+        let renderer = Renderer::new(16, 8, 10, 40);
+        let camera = Camera::new(90., 2.);
+        let mut state = renderer.start();
+        let limits = RenderLimits{deadline: Some(Instant::now()), cancel: None};
+        assert_eq!(renderer.render(&scene(), &camera, &mut state, &limits), RenderStatus::TimedOut);
+        assert_eq!(renderer.render(&scene(), &camera, &mut state, &RenderLimits::default()), RenderStatus::Complete);
+        let mut film = Film::new(16, 8);
+        renderer.resolve(&state, &mut film);
+        assert!(film.samples.iter().all(|&n| n == 40));
+    }
+
+    #[test]
+    fn test_progress_reports_every_band() {
+        let mut renderer = Renderer::new(16, 8, 10, 20);
+        let reports = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = reports.clone();
+        renderer.set_progress(Some(Arc::new(move |progress: &Progress| sink.lock().unwrap().push(*progress))));
+        let mut film = Film::new(16, 8);
+        renderer.draw(&scene(), &Camera::new(90., 2.), &mut film);
+        let reports = reports.lock().unwrap();
+        // Two passes, of 16 and 4 samples, over 8 one-row bands.
+        assert_eq!(reports.len(), 16);
+        let last = reports.iter().max_by_key(|p| p.samples_done).unwrap();
+        assert_eq!((last.pass, last.samples_done, last.samples), (2, 16 * 8 * 20, 16 * 8 * 20));
+        assert_eq!(last.fraction(), 1.);
+        assert!(last.rays >= last.samples_done);
+    }
 }
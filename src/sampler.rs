@@ -0,0 +1,555 @@
+use std::fmt;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+/// A source of sample values in [0, 1) for one pixel sample at a time. A path draws its
+/// dimensions one or two at a time, always in the same order: pixel position, time,
+/// wavelength, then the decisions at each bounce. Low-discrepancy samplers spread each
+/// dimension evenly over a pixel's samples, which converges faster than independent ones.
+///
+/// Every value derives from the render's seed, the pixel and the sample index, so a render
+/// comes out bit for bit the same however its pixels are split between threads.
+pub trait Sampler {
+    /// Starts sample `index` of pixel (`x`, `y`), from the first dimension.
+    fn start_pixel_sample(&mut self, x: u32, y: u32, index: usize);
+
+    fn get_1d(&mut self) -> f64;
+
+    fn get_2d(&mut self) -> (f64, f64);
+
+    /// A uniform number in [min, max).
+    fn uniform(&mut self, min: f64, max: f64) -> f64 {
+        min + (max - min) * self.get_1d()
+    }
+}
+
+/// The samplers to choose from.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SamplerKind {
+    /// Independent uniform random numbers.
+    Independent,
+    /// Jittered strata, shuffled separately for each dimension.
+    Stratified,
+    /// The Halton sequence, Owen scrambled for each pixel.
+    Halton,
+    /// The Sobol sequence with hash-based Owen scrambling.
+    Sobol,
+    /// Owen-scrambled Sobol points shared by all pixels, shifted by a blue noise mask so
+    /// that the error is spread as blue noise over the image.
+    BlueNoise,
+}
+
+impl SamplerKind {
+    pub const ALL: [SamplerKind; 5] = [SamplerKind::Independent, SamplerKind::Stratified, SamplerKind::Halton, SamplerKind::Sobol, SamplerKind::BlueNoise];
+
+    pub fn create(&self, seed: u64, samples_per_pixel: usize) -> Box<dyn Sampler> {
+        match self {
+            SamplerKind::Independent => Box::new(IndependentSampler::new(seed)),
+            SamplerKind::Stratified => Box::new(StratifiedSampler::new(seed, samples_per_pixel)),
+            SamplerKind::Halton => Box::new(HaltonSampler::new(seed)),
+            SamplerKind::Sobol => Box::new(SobolSampler::new(seed, false)),
+            SamplerKind::BlueNoise => Box::new(SobolSampler::new(seed, true)),
+        }
+    }
+
+    /// The next sampler, for cycling through them in the viewer.
+    pub fn next(&self) -> SamplerKind {
+        let index = SamplerKind::ALL.iter().position(|s| s == self).unwrap_or(0);
+        SamplerKind::ALL[(index + 1) % SamplerKind::ALL.len()]
+    }
+}
+
+impl FromStr for SamplerKind {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<SamplerKind, ()> {
+        match s {
+            "independent" => Ok(SamplerKind::Independent),
+            "stratified" => Ok(SamplerKind::Stratified),
+            "halton" => Ok(SamplerKind::Halton),
+            "sobol" => Ok(SamplerKind::Sobol),
+            "bluenoise" => Ok(SamplerKind::BlueNoise),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for SamplerKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            SamplerKind::Independent => "independent",
+            SamplerKind::Stratified => "stratified",
+            SamplerKind::Halton => "halton",
+            SamplerKind::Sobol => "sobol",
+            SamplerKind::BlueNoise => "bluenoise",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// SplitMix64's finalizer, which spreads similar inputs over the whole range.
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+/// Hashes `values` into one well-mixed value, the same on every run and machine.
+pub fn hash(values: &[u64]) -> u64 {
+    values.iter().fold(0, |hash, &v| mix(hash ^ v))
+}
+
+/// The largest f64 below one.
+const ONE_MINUS_EPSILON: f64 = 1. - f64::EPSILON / 2.;
+
+/// The PCG32 random number generator.
+#[derive(Clone)]
+struct Pcg32 {
+    state: u64,
+    increment: u64,
+}
+
+impl Pcg32 {
+    fn new(seed: u64) -> Pcg32 {
+        let mut rng = Pcg32{state: 0, increment: (mix(seed) << 1) | 1};
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.next_u32();
+        rng
+    }
+
+    fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.state = old.wrapping_mul(6364136223846793005).wrapping_add(self.increment);
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        xorshifted.rotate_right((old >> 59) as u32)
+    }
+
+    fn next_f64(&mut self) -> f64 {
+        let bits = ((self.next_u32() as u64) << 32) | self.next_u32() as u64;
+        (bits >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Independent uniform random numbers from a generator seeded for each pixel sample.
+#[derive(Clone)]
+pub struct IndependentSampler {
+    seed: u64,
+    rng: Pcg32,
+}
+
+impl IndependentSampler {
+    pub fn new(seed: u64) -> IndependentSampler {
+        IndependentSampler{seed, rng: Pcg32::new(seed)}
+    }
+
+    /// A sampler seeded by hashing `values`, for code that isn't handed one.
+    pub fn hashed(values: &[f64]) -> IndependentSampler {
+        let bits: Vec<u64> = values.iter().map(|v| v.to_bits()).collect();
+        IndependentSampler::new(hash(&bits))
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, index: usize) {
+        self.rng = Pcg32::new(hash(&[self.seed, x as u64, y as u64, index as u64]));
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        self.rng.next_f64()
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.rng.next_f64(), self.rng.next_f64())
+    }
+}
+
+/// Element `i` of a random permutation of 0..`length` chosen by `seed`, without building
+/// the permutation (Kensler, "Correlated Multi-Jittered Sampling").
+fn permutation_element(mut i: u32, length: u32, seed: u32) -> u32 {
+    let mut w = length - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= seed >> 16;
+        i ^= (i & w) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= seed >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        // Values past the end are permuted again until they land inside.
+        if i < length {
+            return i.wrapping_add(seed) % length;
+        }
+    }
+}
+
+/// Divides each dimension of a pixel into one stratum per sample, and each pair of
+/// dimensions into a grid of them, visiting the strata in a different random order for
+/// every dimension. Samples past `samples_per_pixel` are independent.
+pub struct StratifiedSampler {
+    seed: u64,
+    samples_per_pixel: usize,
+    // The 2D grid is as square as the sample count allows.
+    x_strata: usize,
+    pixel: (u32, u32),
+    index: usize,
+    dimension: u64,
+    rng: Pcg32,
+}
+
+impl StratifiedSampler {
+    pub fn new(seed: u64, samples_per_pixel: usize) -> StratifiedSampler {
+        let samples_per_pixel = samples_per_pixel.max(1);
+        let x_strata = (1..=samples_per_pixel).take_while(|n| n * n <= samples_per_pixel)
+            .filter(|n| samples_per_pixel.is_multiple_of(*n)).last().unwrap_or(1);
+        StratifiedSampler{seed, samples_per_pixel, x_strata, pixel: (0, 0), index: 0, dimension: 0, rng: Pcg32::new(seed)}
+    }
+
+    /// The stratum this sample falls in for the next dimension.
+    fn next_stratum(&mut self) -> Option<usize> {
+        let key = hash(&[self.seed, self.pixel.0 as u64, self.pixel.1 as u64, self.dimension]);
+        self.dimension += 1;
+        if self.index >= self.samples_per_pixel {
+            return None;
+        }
+        Some(permutation_element(self.index as u32, self.samples_per_pixel as u32, key as u32) as usize)
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, index: usize) {
+        self.pixel = (x, y);
+        self.index = index;
+        self.dimension = 0;
+        self.rng = Pcg32::new(hash(&[self.seed, x as u64, y as u64, index as u64]));
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        match self.next_stratum() {
+            Some(stratum) => (stratum as f64 + self.rng.next_f64()) / self.samples_per_pixel as f64,
+            None => self.rng.next_f64(),
+        }
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        match self.next_stratum() {
+            Some(stratum) => {
+                let y_strata = self.samples_per_pixel / self.x_strata;
+                let (sx, sy) = (stratum % self.x_strata, stratum / self.x_strata);
+                ((sx as f64 + self.rng.next_f64()) / self.x_strata as f64, (sy as f64 + self.rng.next_f64()) / y_strata as f64)
+            }
+            None => (self.rng.next_f64(), self.rng.next_f64()),
+        }
+    }
+}
+
+/// Bases of the Halton dimensions. Further dimensions are independent.
+const PRIMES: [u64; 64] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97, 101,
+    103, 107, 109, 113, 127, 131, 137, 139, 149, 151, 157, 163, 167, 173, 179, 181, 191, 193, 197, 199,
+    211, 223, 227, 229, 233, 239, 241, 251, 257, 263, 269, 271, 277, 281, 283, 293, 307, 311,
+];
+
+/// Mirrors the base `base` digits of `index` around the radix point, Owen scrambled: each
+/// digit is permuted by a permutation chosen by `seed` and the digits before it. Leading
+/// zeros are scrambled too, until the digits are below f64 precision.
+fn scrambled_radical_inverse(base: u64, mut index: u64, seed: u64) -> f64 {
+    let inverse_base = 1. / base as f64;
+    let mut reversed = 0;
+    let mut scale = 1.;
+    while 1. - scale < 1. {
+        let next = index / base;
+        let digit = (index - next * base) as u32;
+        let digit = permutation_element(digit, base as u32, mix(seed ^ reversed) as u32);
+        reversed = reversed * base + digit as u64;
+        scale *= inverse_base;
+        index = next;
+    }
+    (reversed as f64 * scale).min(ONE_MINUS_EPSILON)
+}
+
+/// The Halton sequence, one prime base per dimension. Every pixel walks the same sequence
+/// with its own Owen scramble per dimension.
+pub struct HaltonSampler {
+    seed: u64,
+    pixel: (u32, u32),
+    index: usize,
+    dimension: usize,
+    rng: Pcg32,
+}
+
+impl HaltonSampler {
+    pub fn new(seed: u64) -> HaltonSampler {
+        HaltonSampler{seed, pixel: (0, 0), index: 0, dimension: 0, rng: Pcg32::new(seed)}
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, index: usize) {
+        self.pixel = (x, y);
+        self.index = index;
+        self.dimension = 0;
+        self.rng = Pcg32::new(hash(&[self.seed, x as u64, y as u64, index as u64]));
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let dimension = self.dimension;
+        self.dimension += 1;
+        if dimension >= PRIMES.len() {
+            return self.rng.next_f64();
+        }
+        let key = hash(&[self.seed, self.pixel.0 as u64, self.pixel.1 as u64, dimension as u64]);
+        scrambled_radical_inverse(PRIMES[dimension], self.index as u64, key)
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.get_1d(), self.get_1d())
+    }
+}
+
+/// The first Sobol dimension, the van der Corput sequence in base 2.
+fn sobol_0(index: u32) -> u32 {
+    index.reverse_bits()
+}
+
+/// The second Sobol dimension, whose direction numbers come from the polynomial x + 1.
+fn sobol_1(mut index: u32) -> u32 {
+    let mut direction = 1u32 << 31;
+    let mut result = 0;
+    while index != 0 {
+        if index & 1 == 1 {
+            result ^= direction;
+        }
+        index >>= 1;
+        direction ^= direction >> 1;
+    }
+    result
+}
+
+/// An Owen scramble of the bits of `x`, computed by hashing (Burley, "Practical Hash-based
+/// Owen Scrambling").
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    let mut x = x.reverse_bits();
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x.reverse_bits()
+}
+
+fn to_unit(bits: u32) -> f64 {
+    (bits as f64 / (1u64 << 32) as f64).min(ONE_MINUS_EPSILON)
+}
+
+/// Owen-scrambled Sobol points. Every pair of dimensions reuses the first two Sobol
+/// dimensions with its own scramble and its own shuffled order of the samples, which keeps
+/// the good 2D distribution without tables of direction numbers.
+///
+/// Normally each pixel gets its own scrambles. With `blue_noise`, all pixels share them
+/// and each pixel's points are instead shifted by a blue noise mask, so neighbouring pixels
+/// get very different points and the remaining error looks like blue noise rather than
+/// white noise, which is much less visible at low sample counts.
+pub struct SobolSampler {
+    seed: u64,
+    blue_noise: bool,
+    pixel: (u32, u32),
+    index: usize,
+    dimension: u64,
+}
+
+impl SobolSampler {
+    pub fn new(seed: u64, blue_noise: bool) -> SobolSampler {
+        SobolSampler{seed, blue_noise, pixel: (0, 0), index: 0, dimension: 0}
+    }
+
+    fn next_point(&mut self) -> (f64, f64) {
+        let key = if self.blue_noise {
+            hash(&[self.seed, self.dimension])
+        } else {
+            hash(&[self.seed, self.pixel.0 as u64, self.pixel.1 as u64, self.dimension])
+        };
+        self.dimension += 1;
+        let index = nested_uniform_scramble(self.index as u32, key as u32);
+        let u = to_unit(nested_uniform_scramble(sobol_0(index), (key >> 32) as u32));
+        let v = to_unit(nested_uniform_scramble(sobol_1(index), mix(key) as u32));
+        if !self.blue_noise {
+            return (u, v);
+        }
+        // Look up the mask at a different offset for each dimension so they don't correlate.
+        let offsets = mix(key ^ 0x5bd1_e995);
+        let lookup = |shift: u64| {
+            let x = (self.pixel.0 as usize + (offsets >> shift) as usize) % BLUE_NOISE_SIZE;
+            let y = (self.pixel.1 as usize + (offsets >> (shift + 16)) as usize) % BLUE_NOISE_SIZE;
+            blue_noise_mask()[y * BLUE_NOISE_SIZE + x]
+        };
+        let rotate = |u: f64, shift: f64| { let u = u + shift; (if u >= 1. { u - 1. } else { u }).min(ONE_MINUS_EPSILON) };
+        (rotate(u, lookup(0)), rotate(v, lookup(32)))
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, index: usize) {
+        self.pixel = (x, y);
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        self.next_point().0
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        self.next_point()
+    }
+}
+
+const BLUE_NOISE_SIZE: usize = 64;
+
+/// A tileable blue noise mask with values in [0, 1), built on first use.
+fn blue_noise_mask() -> &'static [f64] {
+    static MASK: OnceLock<Vec<f64>> = OnceLock::new();
+    MASK.get_or_init(|| {
+        let ranks = blue_noise_ranks(BLUE_NOISE_SIZE);
+        let count = ranks.len() as f64;
+        ranks.iter().map(|&rank| (rank as f64 + 0.5) / count).collect()
+    })
+}
+
+/// Ranks the pixels of a `size` by `size` tile by repeatedly picking the one farthest from
+/// those already picked, measured by a wrapping Gaussian energy, as in the void filling
+/// phase of Ulichney's void-and-cluster method. Any prefix of the ranking is evenly spread.
+fn blue_noise_ranks(size: usize) -> Vec<usize> {
+    const SIGMA: f64 = 1.5;
+    let count = size * size;
+    // The energy a pixel adds at each wrapped offset.
+    let mut kernel = vec![0.; count];
+    for dy in 0..size {
+        for dx in 0..size {
+            let (wx, wy) = (dx.min(size - dx) as f64, dy.min(size - dy) as f64);
+            kernel[dy * size + dx] = (-(wx * wx + wy * wy) / (2. * SIGMA * SIGMA)).exp();
+        }
+    }
+    let mut energy = vec![0.; count];
+    let mut ranks = vec![usize::MAX; count];
+    for rank in 0..count {
+        let next = (0..count).filter(|&p| ranks[p] == usize::MAX)
+            .min_by(|&a, &b| energy[a].partial_cmp(&energy[b]).unwrap())
+            .unwrap();
+        ranks[next] = rank;
+        let (nx, ny) = (next % size, next / size);
+        for (p, e) in energy.iter_mut().enumerate() {
+            let dx = (p % size + size - nx) % size;
+            let dy = (p / size + size - ny) % size;
+            *e += kernel[dy * size + dx];
+        }
+    }
+    ranks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reproducible_and_in_range() {
+        for kind in SamplerKind::ALL.iter() {
+            let mut a = kind.create(7, 16);
+            let mut b = kind.create(7, 16);
+            a.start_pixel_sample(3, 5, 2);
+            b.start_pixel_sample(3, 5, 2);
+            let values: Vec<f64> = (0..100).map(|_| a.get_1d()).collect();
+            assert!(values.iter().all(|&u| (0. ..1.).contains(&u)), "{}", kind);
+            assert_eq!(values, (0..100).map(|_| b.get_1d()).collect::<Vec<f64>>(), "{}", kind);
+
+            // Other pixels and samples get other values.
+            b.start_pixel_sample(4, 5, 2);
+            assert_ne!(values[0], b.get_1d(), "{}", kind);
+            b.start_pixel_sample(3, 5, 3);
+            assert_ne!(values[0], b.get_1d(), "{}", kind);
+        }
+    }
+
+    /// Root mean square error, over many pixels, of estimating the area of a quarter disk
+    /// with `samples` samples from the third and fourth dimensions.
+    fn quarter_disk_error(kind: SamplerKind, samples: usize) -> f64 {
+        let pixels = 256;
+        let mut squared_error = 0.;
+        for pixel in 0..pixels {
+            let mut sampler = kind.create(1, samples);
+            let mut inside = 0;
+            for index in 0..samples {
+                sampler.start_pixel_sample(pixel, 0, index);
+                sampler.get_2d();
+                let (u, v) = sampler.get_2d();
+                if u * u + v * v < 1. {
+                    inside += 1;
+                }
+            }
+            let estimate = inside as f64 / samples as f64;
+            squared_error += (estimate - std::f64::consts::FRAC_PI_4).powi(2);
+        }
+        (squared_error / pixels as f64).sqrt()
+    }
+
+    #[test]
+    fn test_low_discrepancy_converges_faster() {
+        let independent = quarter_disk_error(SamplerKind::Independent, 64);
+        for &kind in SamplerKind::ALL[1..].iter() {
+            let error = quarter_disk_error(kind, 64);
+            assert!(error < 0.5 * independent, "{} error {} vs independent {}", kind, error, independent);
+        }
+    }
+
+    #[test]
+    fn test_stratified_covers_every_stratum() {
+        let mut sampler = StratifiedSampler::new(3, 8);
+        let mut strata: Vec<usize> = (0..8).map(|index| {
+            sampler.start_pixel_sample(0, 0, index);
+            (sampler.get_1d() * 8.) as usize
+        }).collect();
+        strata.sort_unstable();
+        assert_eq!(strata, (0..8).collect::<Vec<usize>>());
+    }
+
+    #[test]
+    fn test_sobol_1() {
+        let values: Vec<f64> = (0..4).map(|i| to_unit(sobol_1(i))).collect();
+        assert_eq!(values, vec![0., 0.5, 0.75, 0.25]);
+    }
+
+    #[test]
+    fn test_blue_noise_ranks_are_spread_out() {
+        let size = 32;
+        let ranks = blue_noise_ranks(size);
+        let mut sorted = ranks.clone();
+        sorted.sort_unstable();
+        assert_eq!(sorted, (0..size * size).collect::<Vec<usize>>());
+
+        // The first 16 pixels picked are far apart, even across the wrap.
+        let first: Vec<usize> = (0..size * size).filter(|&p| ranks[p] < 16).collect();
+        let wrapped = |a: usize, b: usize| { let d = (a + size - b) % size; d.min(size - d) as f64 };
+        for &a in first.iter() {
+            for &b in first.iter().filter(|&&b| b != a) {
+                let distance = wrapped(a % size, b % size).hypot(wrapped(a / size, b / size));
+                assert!(distance >= 4., "pixels {} and {} are {} apart", a, b, distance);
+            }
+        }
+    }
+}
@@ -0,0 +1,38 @@
+use std::collections::HashMap;
+
+use crate::hittable::{Hittable, HittableList};
+use crate::light::Light;
+use crate::material::Material;
+
+pub type Lights = Vec<Box<dyn Light + Send + Sync>>;
+
+/// Everything the renderer needs to know about the world apart from the camera.
+pub struct Scene {
+    pub world: HittableList,
+    pub lights: Lights,
+    // The index of each material in the world, by its address.
+    materials: HashMap<usize, usize>,
+}
+
+fn address(material: &dyn Material) -> usize {
+    material as *const dyn Material as *const u8 as usize
+}
+
+impl Scene {
+    pub fn new(world: HittableList, lights: Lights) -> Scene {
+        let mut found = Vec::new();
+        world.materials(&mut found);
+        let mut materials = HashMap::new();
+        for material in found {
+            let index = materials.len();
+            materials.entry(address(material)).or_insert(index);
+        }
+        Scene{world, lights, materials}
+    }
+
+    /// The index of `material` among the world's materials, which is the same for the
+    /// same scene in any process.
+    pub fn material_index(&self, material: &dyn Material) -> Option<usize> {
+        self.materials.get(&address(material)).copied()
+    }
+}
@@ -0,0 +1,239 @@
+use std::io;
+
+use crate::camera::Camera;
+use crate::hittable::{Hittable, HittableList};
+use crate::light::{DirectionalLight, Light, PointLight};
+use crate::material::{Dielectric, Lambertian, Material, Metal};
+use crate::mesh::{Mesh, TriangleMesh};
+use crate::pbr::PbrMaterial;
+use crate::scene::Scene;
+use crate::sphere::Sphere;
+use crate::vec3::{Color, Point, Vec3};
+
+/// A material by its parameters, which can be built any number of times, so bindings can
+/// give the same material to several objects.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum MaterialDesc {
+    Lambertian{albedo: Color},
+    Metal{albedo: Color, fuzz: f64},
+    Dielectric{ior: f64},
+    Pbr{base_color: Color, metallic: f64, roughness: f64, emissive: Color},
+}
+
+impl MaterialDesc {
+    /// Checks the parameters are ones the material can be built with.
+    pub fn validate(&self) -> io::Result<()> {
+        let unit = |value: f64| (0. ..=1.).contains(&value);
+        match *self {
+            MaterialDesc::Lambertian{albedo} | MaterialDesc::Metal{albedo, ..} if !is_color(&albedo) => Err(invalid_input("the albedo must be finite and not negative")),
+            MaterialDesc::Metal{fuzz, ..} if !unit(fuzz) => Err(invalid_input("the fuzz must be between 0 and 1")),
+            MaterialDesc::Dielectric{ior} if !(ior > 0. && ior.is_finite()) => Err(invalid_input("the index of refraction must be positive")),
+            MaterialDesc::Pbr{base_color, ..} if !is_color(&base_color) => Err(invalid_input("the base color must be finite and not negative")),
+            MaterialDesc::Pbr{metallic, roughness, ..} if !unit(metallic) || !unit(roughness) => Err(invalid_input("metallic and roughness must be between 0 and 1")),
+            MaterialDesc::Pbr{emissive, ..} if !is_color(&emissive) => Err(invalid_input("the emissive color must be finite and not negative")),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn build(&self) -> Box<dyn Material + Send + Sync> {
+        match *self {
+            MaterialDesc::Lambertian{albedo} => Box::new(Lambertian::new(albedo)),
+            MaterialDesc::Metal{albedo, fuzz} => Box::new(Metal::new(albedo, fuzz)),
+            MaterialDesc::Dielectric{ior} => Box::new(Dielectric::new(ior)),
+            MaterialDesc::Pbr{base_color, metallic, roughness, emissive} => {
+                let mut material = PbrMaterial::new(base_color, metallic, roughness);
+                material.emissive = emissive;
+                Box::new(material)
+            }
+        }
+    }
+}
+
+/// A pinhole camera at `look_from` looking towards `look_at`, with `up` pointing up in the
+/// image and a vertical field of view in degrees. Its aspect ratio is given when it's built.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CameraDesc {
+    look_from: Point,
+    look_at: Point,
+    up: Vec3,
+    vertical_fov: f64,
+}
+
+impl CameraDesc {
+    pub fn new(look_from: Point, look_at: Point, up: Vec3, vertical_fov: f64) -> io::Result<CameraDesc> {
+        if look_from == look_at || (look_from - look_at).cross(&up) == Vec3::zeros() {
+            return Err(invalid_input("the camera needs a view direction that isn't along up"));
+        }
+        if !(vertical_fov > 0. && vertical_fov < 180.) {
+            return Err(invalid_input("the field of view must be between 0 and 180 degrees"));
+        }
+        Ok(CameraDesc{look_from, look_at, up, vertical_fov})
+    }
+
+    pub fn build(&self, aspect_ratio: f64) -> Camera {
+        Camera::look_at(self.look_from, self.look_at, self.up, self.vertical_fov, aspect_ratio)
+    }
+}
+
+fn is_finite(v: &Vec3) -> bool {
+    v.x.is_finite() && v.y.is_finite() && v.z.is_finite()
+}
+
+fn is_color(c: &Color) -> bool {
+    is_finite(c) && c.x >= 0. && c.y >= 0. && c.z >= 0.
+}
+
+fn invalid_input(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+#[derive(Clone)]
+enum Shape {
+    Sphere(Point, f64, MaterialDesc),
+    Mesh(TriangleMesh, MaterialDesc),
+}
+
+#[derive(Clone)]
+enum LightDesc {
+    Point(Point, Color),
+    Directional(Vec3, Color),
+}
+
+/// A scene put together an object at a time, as the language bindings do, which can be
+/// built into a `Scene` as often as it needs rendering.
+#[derive(Clone, Default)]
+pub struct SceneBuilder {
+    shapes: Vec<Shape>,
+    lights: Vec<LightDesc>,
+}
+
+impl SceneBuilder {
+    pub fn new() -> SceneBuilder {
+        SceneBuilder::default()
+    }
+
+    pub fn add_sphere(&mut self, center: Point, radius: f64, material: MaterialDesc) -> io::Result<()> {
+        if !is_finite(&center) {
+            return Err(invalid_input("the sphere's center must be finite"));
+        }
+        if !(radius > 0. && radius.is_finite()) {
+            return Err(invalid_input("the sphere's radius must be positive"));
+        }
+        self.shapes.push(Shape::Sphere(center, radius, material));
+        Ok(())
+    }
+
+    pub fn add_mesh(&mut self, mesh: TriangleMesh, material: MaterialDesc) {
+        self.shapes.push(Shape::Mesh(mesh, material));
+    }
+
+    /// Adds a mesh of `triangles`, each the indices of three of the `vertices`.
+    pub fn add_triangles(&mut self, vertices: Vec<Point>, triangles: Vec<[u32; 3]>, material: MaterialDesc) -> io::Result<()> {
+        if triangles.iter().flatten().any(|&i| i as usize >= vertices.len()) {
+            return Err(invalid_input("triangle vertex index out of range"));
+        }
+        self.add_mesh(TriangleMesh{positions: vertices, triangles, ..TriangleMesh::default()}, material);
+        Ok(())
+    }
+
+    pub fn add_point_light(&mut self, position: Point, intensity: Color) {
+        self.lights.push(LightDesc::Point(position, intensity));
+    }
+
+    /// Adds a light infinitely far away, shining along `direction`.
+    pub fn add_directional_light(&mut self, direction: Vec3, irradiance: Color) {
+        self.lights.push(LightDesc::Directional(direction, irradiance));
+    }
+
+    /// The number of objects, not counting lights.
+    pub fn len(&self) -> usize {
+        self.shapes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shapes.is_empty()
+    }
+
+    pub fn build(&self) -> Scene {
+        let objects = self.shapes.iter().map(|shape| -> Box<dyn Hittable + Send + Sync> {
+            match shape {
+                Shape::Sphere(center, radius, material) => Box::new(Sphere::new(center.x, center.y, center.z, *radius, material.build())),
+                Shape::Mesh(mesh, material) => Box::new(Mesh::new(mesh.clone(), material.build())),
+            }
+        }).collect();
+        let lights = self.lights.iter().map(|light| -> Box<dyn Light + Send + Sync> {
+            match *light {
+                LightDesc::Point(position, intensity) => Box::new(PointLight{position, intensity}),
+                LightDesc::Directional(direction, irradiance) => Box::new(DirectionalLight{direction, irradiance}),
+            }
+        }).collect();
+        Scene::new(HittableList::new(objects), lights)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_material_validation() {
+        assert!(MaterialDesc::Dielectric{ior: 1.5}.validate().is_ok());
+        for ior in [0., -1.5, f64::NAN] {
+            assert_eq!(MaterialDesc::Dielectric{ior}.validate().unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        }
+        let grey = Color::new(0.5, 0.5, 0.5);
+        assert!(MaterialDesc::Lambertian{albedo: grey}.validate().is_ok());
+        assert!(MaterialDesc::Metal{albedo: grey, fuzz: 0.1}.validate().is_ok());
+        assert!(MaterialDesc::Pbr{base_color: grey, metallic: 1., roughness: 0., emissive: Color::new(4., 4., 4.)}.validate().is_ok());
+        let invalid = [
+            MaterialDesc::Lambertian{albedo: Color::new(f64::NAN, 0.5, 0.5)},
+            MaterialDesc::Lambertian{albedo: Color::new(0.5, -0.5, 0.5)},
+            MaterialDesc::Metal{albedo: Color::new(0.5, 0.5, f64::INFINITY), fuzz: 0.},
+            MaterialDesc::Metal{albedo: grey, fuzz: -0.1},
+            MaterialDesc::Metal{albedo: grey, fuzz: f64::NAN},
+            MaterialDesc::Dielectric{ior: f64::INFINITY},
+            MaterialDesc::Pbr{base_color: Color::new(-1., 0., 0.), metallic: 0., roughness: 0.5, emissive: Color::zeros()},
+            MaterialDesc::Pbr{base_color: grey, metallic: 1.5, roughness: 0.5, emissive: Color::zeros()},
+            MaterialDesc::Pbr{base_color: grey, metallic: 0., roughness: f64::NAN, emissive: Color::zeros()},
+            MaterialDesc::Pbr{base_color: grey, metallic: 0., roughness: 0.5, emissive: Color::new(0., f64::NAN, 0.)},
+        ];
+        for material in invalid {
+            assert_eq!(material.validate().unwrap_err().kind(), io::ErrorKind::InvalidInput, "{:?}", material);
+        }
+    }
+
+    #[test]
+    fn test_camera_validation() {
+        let (origin, ahead, up) = (Point::zeros(), Point::new(0., 0., -1.), Vec3::new(0., 1., 0.));
+        assert!(CameraDesc::new(origin, ahead, up, 90.).is_ok());
+        for fov in [0., 180., -10., f64::NAN] {
+            assert_eq!(CameraDesc::new(origin, ahead, up, fov).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        }
+        assert!(CameraDesc::new(origin, origin, up, 90.).is_err());
+        assert!(CameraDesc::new(origin, Point::new(0., 2., 0.), up, 90.).is_err());
+    }
+
+    #[test]
+    fn test_add_triangles_checks_indices() {
+        let mut builder = SceneBuilder::new();
+        let vertices = vec![Point::zeros(), Point::new(1., 0., 0.), Point::new(0., 1., 0.)];
+        let material = MaterialDesc::Lambertian{albedo: Color::new(0.5, 0.5, 0.5)};
+        assert!(builder.add_triangles(vertices.clone(), vec![[0, 1, 3]], material).is_err());
+        assert!(builder.is_empty());
+        builder.add_triangles(vertices, vec![[0, 1, 2]], material).unwrap();
+        assert_eq!(builder.len(), 1);
+    }
+
+    #[test]
+    fn test_add_sphere_checks_its_shape() {
+        let mut builder = SceneBuilder::new();
+        let material = MaterialDesc::Lambertian{albedo: Color::new(0.5, 0.5, 0.5)};
+        for radius in [0., -1., f64::NAN, f64::INFINITY] {
+            assert!(builder.add_sphere(Point::zeros(), radius, material).is_err());
+        }
+        assert!(builder.add_sphere(Point::new(0., f64::NAN, 0.), 1., material).is_err());
+        assert!(builder.is_empty());
+        builder.add_sphere(Point::zeros(), 1., material).unwrap();
+        assert_eq!(builder.len(), 1);
+    }
+}
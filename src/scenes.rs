@@ -0,0 +1,148 @@
+use crate::heightfield::{HeightMap, Heightfield};
+use crate::hittable::Hittable;
+use crate::material::{Dielectric, Dispersion, Lambertian, Metal};
+use crate::mesh::{Mesh, TriangleMesh};
+use crate::moving_sphere::MovingSphere;
+use crate::pbr::PbrMaterial;
+use crate::sdf::{Intersection, Mandelbulb, Repetition, Scale, SdfBox, SdfObject, SdfSphere, SmoothUnion, Subtraction, Torus, Translate, Twist};
+use crate::sphere::Sphere;
+use crate::transform::{AnimatedTransform, RigidTransform};
+use crate::vec3::{Color, Point, Vec3};
+
+pub type Objects = Vec<Box<dyn Hittable + Send + Sync>>;
+
+pub const SCENES: [&str; 5] = ["spheres", "sdf", "motion", "terrain", "prism"];
+
+pub fn by_name(name: &str) -> Option<Objects> {
+    match name {
+        "spheres" => Some(spheres()),
+        "sdf" => Some(sdf()),
+        "motion" => Some(motion()),
+        "terrain" => Some(terrain()),
+        "prism" => Some(prism()),
+        _ => None,
+    }
+}
+
+pub fn spheres() -> Objects {
+    let material_ground = Box::new(Lambertian::new(Color::new(0.8, 0.8, 0.0)));
+    let material_center = Box::new(Dielectric::new(-0.4));
+    let material_left = Box::new(Metal::new(Color::new(0.8, 0.8, 0.8), 0.3));
+    let material_right = Box::new(Metal::new(Color::new(0.8, 0.6, 0.2), 1.0));
+
+    vec![
+        Box::new(Sphere::new( 0.0, -100.5, -1., 100.0, material_ground)),
+        Box::new(Sphere::new( 0.0,    0.0, -1.,   0.5, material_center)),
+        Box::new(Sphere::new(-1.0,    0.0, -1.0,   0.5, material_left)),
+        Box::new(Sphere::new( 1.0,    0.0, -1.0,   0.5, material_right)),
+    ]
+}
+
+/// Procedural shapes built from signed distance fields.
+pub fn sdf() -> Objects {
+    let ground = Box::new(Sphere::new(0.0, -100.5, -1., 100.0, Box::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))));
+
+    let mandelbulb = SdfObject::new(
+        Box::new(Translate{
+            offset: Vec3::new(-1.1, 0., -1.2),
+            inner: Box::new(Scale{factor: 0.45, inner: Box::new(Mandelbulb{power: 8., iterations: 12})}),
+        }),
+        10., Box::new(Lambertian::new(Color::new(0.8, 0.3, 0.3))));
+
+    let blob = SdfObject::new(
+        Box::new(Translate{
+            offset: Vec3::new(0., 0., -1.),
+            inner: Box::new(Subtraction{
+                a: Box::new(SmoothUnion{
+                    a: Box::new(SdfSphere{radius: 0.35}),
+                    b: Box::new(SdfBox{half_extents: Vec3::new(0.25, 0.25, 0.25)}),
+                    k: 0.2,
+                }),
+                b: Box::new(Translate{offset: Vec3::new(0., 0.3, 0.3), inner: Box::new(SdfSphere{radius: 0.25})}),
+            }),
+        }),
+        10., Box::new(Metal::new(Color::new(0.8, 0.8, 0.8), 0.1)));
+
+    let twisted = SdfObject::new(
+        Box::new(Translate{
+            offset: Vec3::new(1.1, 0., -1.2),
+            inner: Box::new(Twist{rate: 4., inner: Box::new(SdfBox{half_extents: Vec3::new(0.15, 0.45, 0.15)})}),
+        }),
+        10., Box::new(Lambertian::new(Color::new(0.2, 0.4, 0.8))));
+
+    let ring = SdfObject::new(
+        Box::new(Translate{
+            offset: Vec3::new(0., -0.4, -1.),
+            inner: Box::new(Torus{major_radius: 0.6, minor_radius: 0.05}),
+        }),
+        10., Box::new(Metal::new(Color::new(0.8, 0.6, 0.2), 0.2)));
+
+    let pillars = SdfObject::new(
+        Box::new(Intersection{
+            a: Box::new(Repetition{period: Vec3::new(0.6, 0., 0.), inner: Box::new(SdfSphere{radius: 0.15})}),
+            b: Box::new(Translate{offset: Vec3::new(0., 0., -3.), inner: Box::new(SdfBox{half_extents: Vec3::new(3., 1., 0.5)})}),
+        }),
+        20., Box::new(Lambertian::new(Color::new(0.3, 0.7, 0.3))));
+
+    vec![ground, Box::new(mandelbulb), Box::new(blob), Box::new(twisted), Box::new(ring), Box::new(pillars)]
+}
+
+/// Objects moving during the shutter interval [0, 1], to show off motion blur.
+pub fn motion() -> Objects {
+    let ground = Box::new(Sphere::new(0.0, -100.5, -1., 100.0, Box::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))));
+
+    let bouncing = Box::new(MovingSphere::new(
+        Point::new(-1.0, 0.0, -1.0), Point::new(-1.0, 0.4, -1.0), 0., 1., 0.3,
+        Box::new(Lambertian::new(Color::new(0.8, 0.3, 0.3)))));
+
+    let sliding = Box::new(MovingSphere::new(
+        Point::new(-0.3, -0.2, -1.2), Point::new(0.3, -0.2, -1.2), 0., 1., 0.25,
+        Box::new(Metal::new(Color::new(0.8, 0.8, 0.8), 0.1))));
+
+    let spinning_cube = SdfObject::new(
+        Box::new(SdfBox{half_extents: Vec3::new(0.25, 0.25, 0.25)}),
+        10., Box::new(Lambertian::new(Color::new(0.2, 0.4, 0.8))));
+    let spinning = Box::new(AnimatedTransform::new(
+        Box::new(spinning_cube),
+        RigidTransform::new(Vec3::new(1.0, 0.0, -1.0), 0.),
+        RigidTransform::new(Vec3::new(1.0, 0.1, -1.0), 0.5),
+        0., 1.));
+
+    vec![ground, bouncing, sliding, spinning]
+}
+
+/// Procedural fBm landscape stretching away from the camera.
+pub fn terrain() -> Objects {
+    let map = HeightMap::fbm(512, 512, 1, 8);
+    let material = Box::new(Lambertian::new(Color::new(0.4, 0.5, 0.3)));
+    vec![Box::new(Heightfield::new(map, Point::new(-20., -2., -40.), Vec3::new(40., 3., 40.), material))]
+}
+
+/// A triangular flint glass prism and a crown glass ball, with a small bright light shining
+/// through the prism onto the floor. Render with `--spectral` to see the light split up.
+pub fn prism() -> Objects {
+    let ground = Box::new(Sphere::new(0.0, -100.5, -1., 100.0, Box::new(Lambertian::new(Color::new(0.8, 0.8, 0.8)))));
+
+    // An equilateral cross-section in the xy plane, extruded along z.
+    let (side, depth, center) = (0.8, 0.6, Point::new(0., -0.5, -1.2));
+    let height = side * 3f64.sqrt() / 2.;
+    let corners = [Vec3::new(-side / 2., 0., 0.), Vec3::new(side / 2., 0., 0.), Vec3::new(0., height, 0.)];
+    let mut positions: Vec<Point> = corners.iter().map(|c| center + c + Vec3::new(0., 0., depth / 2.)).collect();
+    positions.extend(corners.iter().map(|c| center + c - Vec3::new(0., 0., depth / 2.)));
+    let prism = TriangleMesh{
+        positions,
+        normals: None,
+        colors: None,
+        uvs: None,
+        // Front, back, bottom, right and left faces, wound counter-clockwise seen from outside.
+        triangles: vec![[0, 1, 2], [3, 5, 4], [3, 4, 1], [3, 1, 0], [4, 5, 2], [4, 2, 1], [5, 3, 0], [5, 0, 2]],
+    };
+    let glass = Box::new(Mesh::new(prism, Box::new(Dielectric::dispersive(Dispersion::DENSE_FLINT))));
+
+    let mut lamp = PbrMaterial::new(Color::zeros(), 0., 1.);
+    lamp.emissive = Color::new(40., 40., 40.);
+    let light = Box::new(Sphere::new(-1.5, 0.6, -1.2, 0.1, Box::new(lamp)));
+    let lens = Box::new(Sphere::new(0.9, -0.2, -1.4, 0.3, Box::new(Dielectric::dispersive(Dispersion::CROWN))));
+
+    vec![ground, glass, light, lens]
+}
@@ -0,0 +1,316 @@
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::vec3::{Point, Vec3};
+
+const MAX_STEPS: usize = 256;
+const HIT_EPSILON: f64 = 1e-4;
+const NORMAL_EPSILON: f64 = 1e-5;
+
+/// A signed distance field: negative inside a shape, positive outside, and (ideally) never
+/// larger than the distance to the nearest surface.
+pub trait Sdf {
+    fn distance(&self, p: &Point) -> f64;
+}
+
+pub type SdfNode = Box<dyn Sdf + Send + Sync>;
+
+fn vmax(v: &Vec3) -> f64 {
+    v.x.max(v.y).max(v.z)
+}
+
+fn vabs(v: &Vec3) -> Vec3 {
+    Vec3::new(v.x.abs(), v.y.abs(), v.z.abs())
+}
+
+pub struct SdfSphere {
+    pub radius: f64,
+}
+
+impl Sdf for SdfSphere {
+    fn distance(&self, p: &Point) -> f64 {
+        p.length() - self.radius
+    }
+}
+
+pub struct SdfBox {
+    pub half_extents: Vec3,
+}
+
+impl Sdf for SdfBox {
+    fn distance(&self, p: &Point) -> f64 {
+        let q = vabs(p) - self.half_extents;
+        let outside = Vec3::new(q.x.max(0.), q.y.max(0.), q.z.max(0.));
+        outside.length() + vmax(&q).min(0.)
+    }
+}
+
+/// A torus lying in the xz-plane.
+pub struct Torus {
+    pub major_radius: f64,
+    pub minor_radius: f64,
+}
+
+impl Sdf for Torus {
+    fn distance(&self, p: &Point) -> f64 {
+        let ring = (p.x * p.x + p.z * p.z).sqrt() - self.major_radius;
+        (ring * ring + p.y * p.y).sqrt() - self.minor_radius
+    }
+}
+
+/// The Mandelbulb fractal, using the usual distance estimator with a bailout radius of 2.
+pub struct Mandelbulb {
+    pub power: f64,
+    pub iterations: usize,
+}
+
+impl Sdf for Mandelbulb {
+    fn distance(&self, p: &Point) -> f64 {
+        let mut z = *p;
+        let mut dr = 1.;
+        let mut r = z.length();
+        for _ in 0..self.iterations {
+            if r > 2. || r == 0. {
+                break;
+            }
+            let theta = (z.z / r).acos() * self.power;
+            let phi = z.y.atan2(z.x) * self.power;
+            dr = r.powf(self.power - 1.) * self.power * dr + 1.;
+            let zr = r.powf(self.power);
+            z = zr * Vec3::new(theta.sin() * phi.cos(), theta.sin() * phi.sin(), theta.cos()) + p;
+            r = z.length();
+        }
+        if r == 0. {
+            return 0.;
+        }
+        0.5 * r.ln() * r / dr
+    }
+}
+
+pub struct Translate {
+    pub offset: Vec3,
+    pub inner: SdfNode,
+}
+
+impl Sdf for Translate {
+    fn distance(&self, p: &Point) -> f64 {
+        self.inner.distance(&(p - self.offset))
+    }
+}
+
+pub struct Scale {
+    pub factor: f64,
+    pub inner: SdfNode,
+}
+
+impl Sdf for Scale {
+    fn distance(&self, p: &Point) -> f64 {
+        self.inner.distance(&(p / self.factor)) * self.factor
+    }
+}
+
+/// Union of two fields, blended over a distance of roughly `k` (polynomial smooth min).
+pub struct SmoothUnion {
+    pub a: SdfNode,
+    pub b: SdfNode,
+    pub k: f64,
+}
+
+impl Sdf for SmoothUnion {
+    fn distance(&self, p: &Point) -> f64 {
+        let a = self.a.distance(p);
+        let b = self.b.distance(p);
+        if self.k <= 0. {
+            return a.min(b);
+        }
+        let h = (0.5 + 0.5 * (b - a) / self.k).clamp(0., 1.);
+        b + (a - b) * h - self.k * h * (1. - h)
+    }
+}
+
+/// The parts of `a` that are not inside `b`.
+pub struct Subtraction {
+    pub a: SdfNode,
+    pub b: SdfNode,
+}
+
+impl Sdf for Subtraction {
+    fn distance(&self, p: &Point) -> f64 {
+        self.a.distance(p).max(-self.b.distance(p))
+    }
+}
+
+pub struct Intersection {
+    pub a: SdfNode,
+    pub b: SdfNode,
+}
+
+impl Sdf for Intersection {
+    fn distance(&self, p: &Point) -> f64 {
+        self.a.distance(p).max(self.b.distance(p))
+    }
+}
+
+/// Infinite repetition of `inner` with the given period along each axis. A period of zero
+/// leaves that axis unrepeated.
+pub struct Repetition {
+    pub period: Vec3,
+    pub inner: SdfNode,
+}
+
+impl Sdf for Repetition {
+    fn distance(&self, p: &Point) -> f64 {
+        let wrap = |x: f64, period: f64| if period > 0. { x - period * (x / period).round() } else { x };
+        let q = Vec3::new(wrap(p.x, self.period.x), wrap(p.y, self.period.y), wrap(p.z, self.period.z));
+        self.inner.distance(&q)
+    }
+}
+
+/// Twists `inner` around the y axis by `rate` radians per unit of height.
+pub struct Twist {
+    pub rate: f64,
+    pub inner: SdfNode,
+}
+
+impl Sdf for Twist {
+    fn distance(&self, p: &Point) -> f64 {
+        let angle = self.rate * p.y;
+        let (s, c) = angle.sin_cos();
+        let q = Vec3::new(c * p.x - s * p.z, p.y, s * p.x + c * p.z);
+        // Twisting stretches space, so shrink the step by the local Lipschitz bound.
+        let r = (p.x * p.x + p.z * p.z).sqrt();
+        self.inner.distance(&q) / (1. + (self.rate * r).powi(2)).sqrt()
+    }
+}
+
+/// A `Hittable` that sphere traces an SDF tree, marching at most `max_distance` along a ray.
+pub struct SdfObject {
+    pub sdf: SdfNode,
+    pub max_distance: f64,
+    pub material: Box<dyn Material + Send + Sync>,
+}
+
+impl SdfObject {
+    pub fn new(sdf: SdfNode, max_distance: f64, material: Box<dyn Material + Send + Sync>) -> SdfObject {
+        SdfObject{sdf, max_distance, material}
+    }
+
+    /// Estimates the surface normal at `p` from the gradient of the field, using the
+    /// tetrahedral central-difference technique (four evaluations instead of six).
+    fn normal(&self, p: &Point) -> Vec3 {
+        let e = NORMAL_EPSILON;
+        let k = [Vec3::new(1., -1., -1.), Vec3::new(-1., -1., 1.), Vec3::new(-1., 1., -1.), Vec3::new(1., 1., 1.)];
+        let mut gradient = Vec3::zeros();
+        for k in k.iter() {
+            gradient += k * self.sdf.distance(&(p + k * e));
+        }
+        gradient.unit_vec()
+    }
+}
+
+impl Hittable for SdfObject {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let speed = r.dir.length();
+        let limit = t_max.min(t_min + self.max_distance / speed);
+        let mut t = t_min;
+        // A ray scattered off the surface starts on it, and at a grazing angle stays within
+        // HIT_EPSILON of it for a while. Step it clear, in growing steps, before anything
+        // counts as a hit, or it hits the point it left from.
+        let mut escape_step = if self.sdf.distance(&r.at(t_min)).abs() < HIT_EPSILON { HIT_EPSILON } else { 0. };
+        for _ in 0..MAX_STEPS {
+            if t > limit {
+                return None;
+            }
+            let p = r.at(t);
+            let d = self.sdf.distance(&p).abs();
+            if d < HIT_EPSILON && escape_step > 0. {
+                t += escape_step / speed;
+                escape_step *= 2.;
+                continue;
+            }
+            escape_step = 0.;
+            if d < HIT_EPSILON {
+                let outward_normal = self.normal(&p);
+                let mut rec = HitRecord{p, normal: outward_normal, t, front_face: false, material: self.material.as_ref(), u: 0., v: 0., vertex_color: None};
+                rec.set_face_normal(r, &outward_normal);
+                return Some(rec);
+            }
+            t += d / speed;
+        }
+        None
+    }
+
+    fn materials<'a>(&'a self, materials: &mut Vec<&'a dyn Material>) {
+        materials.push(self.material.as_ref());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::vec3::Color;
+
+    fn approx(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-3
+    }
+
+    #[test]
+    fn test_primitives() {
+        let sphere = SdfSphere{radius: 1.};
+        assert!(approx(sphere.distance(&Point::new(2., 0., 0.)), 1.));
+        assert!(approx(sphere.distance(&Point::zeros()), -1.));
+
+        let cube = SdfBox{half_extents: Vec3::new(1., 1., 1.)};
+        assert!(approx(cube.distance(&Point::new(3., 0., 0.)), 2.));
+        assert!(approx(cube.distance(&Point::new(2., 2., 1.)), 2_f64.sqrt()));
+
+        let torus = Torus{major_radius: 1., minor_radius: 0.25};
+        assert!(approx(torus.distance(&Point::new(1., 0., 0.)), -0.25));
+        assert!(approx(torus.distance(&Point::zeros()), 0.75));
+    }
+
+    #[test]
+    fn test_operators() {
+        let unit = || -> SdfNode { Box::new(SdfSphere{radius: 1.}) };
+        let moved = Translate{offset: Vec3::new(3., 0., 0.), inner: unit()};
+        assert!(approx(moved.distance(&Point::new(3., 0., 0.)), -1.));
+
+        let cut = Subtraction{a: unit(), b: Box::new(SdfSphere{radius: 0.5})};
+        assert!(approx(cut.distance(&Point::zeros()), 0.5));
+
+        let smooth = SmoothUnion{a: unit(), b: Box::new(moved), k: 0.5};
+        assert!(smooth.distance(&Point::new(1.5, 0., 0.)) < 0.5);
+
+        let repeated = Repetition{period: Vec3::new(4., 0., 0.), inner: unit()};
+        assert!(approx(repeated.distance(&Point::new(8., 0., 0.)), -1.));
+        assert!(approx(repeated.distance(&Point::new(8., 3., 0.)), 2.));
+    }
+
+    #[test]
+    fn test_sphere_trace() {
+        let material = Box::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let object = SdfObject::new(Box::new(SdfSphere{radius: 1.}), 100., material);
+        let r = Ray::new(Point::new(0., 0., -5.), Vec3::new(0., 0., 2.), 0.);
+        let rec = object.hit(&r, 0.001, f64::INFINITY).unwrap();
+        assert!(approx(rec.t, 2.));
+        assert!(approx(rec.normal.z, -1.));
+        assert!(rec.front_face);
+
+        let miss = Ray::new(Point::new(0., 2., -5.), Vec3::new(0., 0., 1.), 0.);
+        assert!(object.hit(&miss, 0.001, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn test_grazing_exit() {
+        let material = Box::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let object = SdfObject::new(Box::new(SdfSphere{radius: 1.}), 100., material);
+        // Leaving the surface almost along it, still within HIT_EPSILON of it at t_min.
+        let grazing = Ray::new(Point::new(1., 0., 0.), Vec3::new(0.05, 1., 0.), 0.);
+        assert!(object.hit(&grazing, 0.001, f64::INFINITY).is_none());
+        // Going in, the ray still finds the far side.
+        let inward = Ray::new(Point::new(1., 0., 0.), Vec3::new(-1., 0.05, 0.), 0.);
+        let rec = object.hit(&inward, 0.001, f64::INFINITY).unwrap();
+        assert!(rec.t > 1.9 && !rec.front_face);
+    }
+}
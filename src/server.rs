@@ -0,0 +1,502 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+use crate::config::Config;
+use crate::renderer::{CancellationToken, Progress, ProgressCallback};
+
+// The largest request body accepted, to keep a bad client from using up the memory.
+const MAX_BODY: usize = 64 << 20;
+// The most finished jobs kept for their clients, beyond which the oldest are dropped.
+const MAX_FINISHED_JOBS: usize = 64;
+// Command line flags that take no value.
+const SWITCHES: [&str; 6] = ["denoise", "resume", "spectral", "dither", "aovs", "half"];
+
+/// A render the service was asked for.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JobRequest {
+    /// Command line arguments, from the query string: `?samples=64&spectral` is
+    /// `--samples 64 --spectral`.
+    pub args: Vec<String>,
+    /// A pbrt-v3 scene from the request body, if there was one.
+    pub pbrt: Option<String>,
+    /// Whether to render an OpenEXR image rather than a PNG (`?format=exr`).
+    pub exr: bool,
+}
+
+/// Renders a job: called with the request, a callback to report progress to and a token
+/// that cancels it, and returns the encoded image.
+pub type Runner = Box<dyn Fn(&JobRequest, ProgressCallback, CancellationToken) -> io::Result<Vec<u8>> + Send>;
+
+enum Status {
+    Queued,
+    Rendering(Option<Progress>),
+    Done(Vec<u8>),
+    Failed(String),
+    Cancelled,
+}
+
+impl Status {
+    fn is_finished(&self) -> bool {
+        matches!(self, Status::Done(_) | Status::Failed(_) | Status::Cancelled)
+    }
+}
+
+struct Job {
+    request: JobRequest,
+    status: Status,
+    cancel: CancellationToken,
+}
+
+#[derive(Default)]
+struct JobTable {
+    jobs: HashMap<u64, Job>,
+    // IDs count up from 1 and aren't reused once their job is removed.
+    last_id: u64,
+}
+
+impl JobTable {
+    fn insert(&mut self, job: Job) -> u64 {
+        self.last_id += 1;
+        self.jobs.insert(self.last_id, job);
+        self.last_id
+    }
+
+    /// Drops the oldest finished jobs beyond `MAX_FINISHED_JOBS`.
+    fn evict_finished(&mut self) {
+        let mut finished: Vec<u64> = self.jobs.iter().filter(|(_, job)| job.status.is_finished()).map(|(&id, _)| id).collect();
+        if finished.len() > MAX_FINISHED_JOBS {
+            finished.sort_unstable();
+            for id in &finished[..finished.len() - MAX_FINISHED_JOBS] {
+                self.jobs.remove(id);
+            }
+        }
+    }
+}
+
+type Jobs = Arc<Mutex<JobTable>>;
+
+/// Serves renders over HTTP on `listener`, one job at a time, in the order they come in:
+///
+/// * `POST /jobs` queues a render and returns its ID as `{"id": 1}`.
+/// * `GET /jobs/<id>` returns its status (`queued`, `rendering`, `done`, `failed` or
+///   `cancelled`) and, while rendering, its progress.
+/// * `GET /jobs/<id>/image` returns the image once it's done.
+/// * `DELETE /jobs/<id>` cancels it, or once it's finished, removes it.
+///
+/// Only the last 64 finished jobs are kept; older ones are removed as if deleted.
+pub fn serve(listener: TcpListener, runner: Runner) -> io::Result<()> {
+    let jobs: Jobs = Arc::new(Mutex::new(JobTable::default()));
+    let (queue, queued) = mpsc::channel();
+    let worker_jobs = jobs.clone();
+    thread::spawn(move || run_jobs(worker_jobs, queued, runner));
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let (jobs, queue) = (jobs.clone(), queue.clone());
+        thread::spawn(move || {
+            let _ = handle(stream, &jobs, |id| {
+                let _ = queue.send(id);
+            });
+        });
+    }
+    Ok(())
+}
+
+fn run_jobs(jobs: Jobs, queued: Receiver<u64>, runner: Runner) {
+    for id in queued {
+        let (request, cancel) = {
+            let mut jobs = jobs.lock().unwrap();
+            let job = match jobs.jobs.get_mut(&id) {
+                Some(job) if matches!(job.status, Status::Queued) => job,
+                _ => continue,
+            };
+            job.status = Status::Rendering(None);
+            (job.request.clone(), job.cancel.clone())
+        };
+        let progress_jobs = jobs.clone();
+        let progress: ProgressCallback = Arc::new(move |progress| {
+            if let Some(Job{status: status @ Status::Rendering(_), ..}) = progress_jobs.lock().unwrap().jobs.get_mut(&id) {
+                *status = Status::Rendering(Some(*progress));
+            }
+        });
+        // A panicking render fails its job rather than take the service down with it.
+        let result = panic::catch_unwind(AssertUnwindSafe(|| runner(&request, progress, cancel.clone())))
+            .unwrap_or_else(|payload| {
+                let message = payload.downcast_ref::<&str>().map(|s| s.to_string())
+                    .or_else(|| payload.downcast_ref::<String>().cloned())
+                    .unwrap_or_else(|| "unknown panic".to_string());
+                Err(io::Error::other(format!("the render panicked: {}", message)))
+            });
+        let mut jobs = jobs.lock().unwrap();
+        if let Some(job) = jobs.jobs.get_mut(&id) {
+            job.status = match result {
+                _ if cancel.is_cancelled() => Status::Cancelled,
+                Ok(image) => Status::Done(image),
+                Err(e) => Status::Failed(e.to_string()),
+            };
+        }
+        jobs.evict_finished();
+    }
+}
+
+struct Request {
+    method: String,
+    path: String,
+    query: Vec<(String, Option<String>)>,
+    body: Vec<u8>,
+}
+
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn json(status: &'static str, json: String) -> Response {
+        Response{status, content_type: "application/json", body: json.into_bytes()}
+    }
+
+    fn error(status: &'static str, message: &str) -> Response {
+        Response::json(status, format!("{{\"error\": {}}}\n", json_string(message)))
+    }
+}
+
+/// Decodes `%XX` escapes and `+` for spaces, as in query strings.
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+                match hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                    Some(byte) => {
+                        decoded.push(byte);
+                        i += 2;
+                    }
+                    None => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn json_string(s: &str) -> String {
+    let mut json = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+fn read_request<R: BufRead>(reader: &mut R) -> io::Result<Request> {
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => (method.to_string(), target.to_string()),
+        _ => return Err(invalid("malformed request line")),
+    };
+    let mut content_length = 0;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(invalid("request ended in the headers"));
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().map_err(|_| invalid("bad Content-Length"))?;
+            }
+        }
+    }
+    if content_length > MAX_BODY {
+        return Err(invalid("request body too large"));
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+    let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+    let query = query.split('&').filter(|pair| !pair.is_empty()).map(|pair| match pair.split_once('=') {
+        Some((key, value)) => (percent_decode(key), Some(percent_decode(value))),
+        None => (percent_decode(pair), None),
+    }).collect();
+    Ok(Request{method, path: path.to_string(), query, body})
+}
+
+/// Turns a `POST /jobs` request into a job request.
+fn job_request(request: &Request) -> Result<JobRequest, String> {
+    let mut job = JobRequest{args: Vec::new(), pbrt: None, exr: false};
+    for (key, value) in &request.query {
+        match (key.as_str(), value.as_deref()) {
+            ("format", Some("exr")) => job.exr = true,
+            ("format", Some("png")) => job.exr = false,
+            ("format", _) => return Err("format is png or exr".to_string()),
+            (key, Some(_)) if SWITCHES.contains(&key) => return Err(format!("{} takes no value", key)),
+            // Keep a value from being read as a flag of its own.
+            (key, Some(value)) if value.starts_with("--") => return Err(format!("invalid value for {}: {}", key, value)),
+            (key, value) => {
+                job.args.push(format!("--{}", key));
+                job.args.extend(value.map(str::to_string));
+            }
+        }
+    }
+    // The service decides where images go and which files are read, whichever way the
+    // arguments spell it.
+    let config = Config::parse(job.args.iter().cloned())?;
+    let denied = [
+        ("output", config.output.is_some()),
+        ("stats", config.stats.is_some()),
+        ("checkpoint", config.checkpoint.is_some()),
+        ("mesh", !config.meshes.is_empty()),
+        ("gltf", config.gltf.is_some()),
+        ("pbrt", config.pbrt.is_some()),
+        ("volume", config.volume.is_some()),
+        ("heightmap", config.heightmap.is_some()),
+        ("coordinator", config.coordinator.is_some()),
+        ("worker", config.worker.is_some()),
+        ("serve", config.serve.is_some()),
+    ];
+    if let Some((flag, _)) = denied.iter().find(|(_, set)| *set) {
+        return Err(format!("{} can't be set through the service", flag));
+    }
+    if !request.body.is_empty() {
+        job.pbrt = Some(String::from_utf8(request.body.clone()).map_err(|_| "the scene must be UTF-8 text".to_string())?);
+    }
+    Ok(job)
+}
+
+fn status_json(id: u64, status: &Status) -> String {
+    let name = match status {
+        Status::Queued => "queued",
+        Status::Rendering(_) => "rendering",
+        Status::Done(_) => "done",
+        Status::Failed(_) => "failed",
+        Status::Cancelled => "cancelled",
+    };
+    let mut json = format!("{{\"id\": {}, \"status\": \"{}\"", id, name);
+    match status {
+        Status::Rendering(Some(progress)) => {
+            json.push_str(&format!(
+                ", \"progress\": {:.4}, \"samples_done\": {}, \"samples\": {}, \"rays_per_second\": {:.0}, \"elapsed_seconds\": {:.3}",
+                progress.fraction(), progress.samples_done, progress.samples, progress.rays_per_second(), progress.elapsed.as_secs_f64(),
+            ));
+            if let Some(eta) = progress.eta() {
+                json.push_str(&format!(", \"eta_seconds\": {:.3}", eta.as_secs_f64()));
+            }
+        }
+        Status::Done(_) => json.push_str(", \"progress\": 1"),
+        Status::Failed(error) => json.push_str(&format!(", \"error\": {}", json_string(error))),
+        _ => {}
+    }
+    json.push_str("}\n");
+    json
+}
+
+fn respond(request: &Request, jobs: &Jobs, enqueue: impl FnOnce(u64)) -> Response {
+    let segments: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+    match (request.method.as_str(), segments.as_slice()) {
+        ("POST", ["jobs"]) => match job_request(request) {
+            Ok(job) => {
+                let id = jobs.lock().unwrap().insert(Job{request: job, status: Status::Queued, cancel: CancellationToken::new()});
+                enqueue(id);
+                Response::json("201 Created", format!("{{\"id\": {}}}\n", id))
+            }
+            Err(e) => Response::error("400 Bad Request", &e),
+        },
+        (method, ["jobs", id, rest @ ..]) if rest.len() <= 1 => {
+            let jobs = &mut jobs.lock().unwrap();
+            let (id, job) = match id.parse().ok().and_then(|id: u64| jobs.jobs.get_mut(&id).map(|job| (id, job))) {
+                Some(found) => found,
+                None => return Response::error("404 Not Found", "no such job"),
+            };
+            match (method, rest) {
+                ("GET", []) => Response::json("200 OK", status_json(id, &job.status)),
+                ("GET", ["image"]) => match &job.status {
+                    Status::Done(image) => Response{
+                        status: "200 OK",
+                        content_type: if job.request.exr { "image/x-exr" } else { "image/png" },
+                        body: image.clone(),
+                    },
+                    _ => Response::error("409 Conflict", "the job isn't done"),
+                },
+                ("DELETE", []) if job.status.is_finished() => {
+                    let response = Response::json("200 OK", status_json(id, &job.status));
+                    jobs.jobs.remove(&id);
+                    response
+                }
+                ("DELETE", []) => {
+                    job.cancel.cancel();
+                    if matches!(job.status, Status::Queued) {
+                        job.status = Status::Cancelled;
+                    }
+                    let response = Response::json("200 OK", status_json(id, &job.status));
+                    jobs.evict_finished();
+                    response
+                }
+                _ => Response::error("405 Method Not Allowed", "unsupported method"),
+            }
+        }
+        _ => Response::error("404 Not Found", "unknown path"),
+    }
+}
+
+fn handle(stream: TcpStream, jobs: &Jobs, enqueue: impl FnOnce(u64)) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let response = match read_request(&mut reader) {
+        Ok(request) => respond(&request, jobs, enqueue),
+        Err(e) => Response::error("400 Bad Request", &e.to_string()),
+    };
+    let mut stream = stream;
+    write!(stream, "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status, response.content_type, response.body.len())?;
+    stream.write_all(&response.body)?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::time::Duration;
+
+    fn request(address: &str, method: &str, target: &str, body: &str) -> (String, Vec<u8>) {
+        let mut stream = TcpStream::connect(address).unwrap();
+        write!(stream, "{} {} HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n{}", method, target, body.len(), body).unwrap();
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).unwrap();
+        let split = response.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+        let head = String::from_utf8_lossy(&response[..split]).into_owned();
+        (head.lines().next().unwrap().to_string(), response[split + 4..].to_vec())
+    }
+
+    /// Polls job `id` until its status is `status`, as a client would, and returns the last status.
+    fn wait_for(address: &str, id: &str, status: &str) -> String {
+        let mut json = String::new();
+        for _ in 0..100 {
+            json = String::from_utf8(request(address, "GET", &format!("/jobs/{}", id), "").1).unwrap();
+            if json.contains(&format!("\"{}\"", status)) {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        json
+    }
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!(percent_decode("a%20b+c%2Fd"), "a b c/d");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz"), "%zz");
+    }
+
+    fn query_job(query: &[(&str, Option<&str>)]) -> Result<JobRequest, String> {
+        let query = query.iter().map(|(key, value)| (key.to_string(), value.map(str::to_string))).collect();
+        job_request(&Request{method: "POST".to_string(), path: "/jobs".to_string(), query, body: Vec::new()})
+    }
+
+    #[test]
+    fn test_job_request() {
+        let job = query_job(&[("samples", Some("4")), ("denoise", None), ("exposure", Some("-1")), ("format", Some("exr"))]).unwrap();
+        assert_eq!(job.args, vec!["--samples", "4", "--denoise", "--exposure", "-1"]);
+        assert!(job.exr);
+        assert!(query_job(&[("output", Some("x.png"))]).is_err());
+        assert!(query_job(&[("mesh", Some("/etc/passwd"))]).is_err());
+        // Flags smuggled in as values.
+        assert!(query_job(&[("spectral", Some("--output")), ("x.png", None)]).is_err());
+        assert!(query_job(&[("denoise", Some("x.png"))]).is_err());
+        assert!(query_job(&[("samples", Some("--stats"))]).is_err());
+        assert!(query_job(&[("unknown", None)]).is_err());
+    }
+
+    #[test]
+    fn test_render_service() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let runner: Runner = Box::new(|request: &JobRequest, _progress: ProgressCallback, _cancel: CancellationToken| {
+            if request.args.is_empty() {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "no \"args\""));
+            }
+            Ok(format!("{:?} {}", request.args, request.pbrt.as_deref().unwrap_or("")).into_bytes())
+        });
+        thread::spawn(move || serve(listener, runner));
+
+        let (status, body) = request(&address, "POST", "/jobs?samples=4&spectral&format=exr", "Shape \"sphere\"");
+        assert_eq!((status.as_str(), body.as_slice()), ("HTTP/1.1 201 Created", &b"{\"id\": 1}\n"[..]));
+        let (status, _) = request(&address, "POST", "/jobs?output=x.png", "");
+        assert_eq!(status, "HTTP/1.1 400 Bad Request");
+        let (_, body) = request(&address, "POST", "/jobs", "");
+        let failed_id = String::from_utf8(body).unwrap().trim_start_matches("{\"id\": ").trim_end_matches("}\n").to_string();
+
+        let status = wait_for(&address, "1", "done");
+        assert_eq!(status, "{\"id\": 1, \"status\": \"done\", \"progress\": 1}\n");
+        let (response, image) = request(&address, "GET", "/jobs/1/image", "");
+        assert_eq!(response, "HTTP/1.1 200 OK");
+        assert_eq!(String::from_utf8(image).unwrap(), "[\"--samples\", \"4\", \"--spectral\"] Shape \"sphere\"");
+
+        let status = wait_for(&address, &failed_id, "failed");
+        assert!(status.ends_with("\"status\": \"failed\", \"error\": \"no \\\"args\\\"\"}\n"), "{}", status);
+        assert_eq!(request(&address, "GET", "/jobs/99", "").0, "HTTP/1.1 404 Not Found");
+        assert_eq!(request(&address, "GET", &format!("/jobs/{}/image", failed_id), "").0, "HTTP/1.1 409 Conflict");
+
+        // Deleting a finished job removes it, and its ID isn't given out again.
+        let (status, body) = request(&address, "DELETE", "/jobs/1", "");
+        assert_eq!((status.as_str(), body.as_slice()), ("HTTP/1.1 200 OK", &b"{\"id\": 1, \"status\": \"done\", \"progress\": 1}\n"[..]));
+        assert_eq!(request(&address, "GET", "/jobs/1", "").0, "HTTP/1.1 404 Not Found");
+        assert_eq!(request(&address, "POST", "/jobs?samples=4", "").1, b"{\"id\": 3}\n");
+    }
+    #[test]
+    fn test_finished_jobs_are_evicted() {
+        let mut jobs = JobTable::default();
+        let job = |status| Job{request: query_job(&[]).unwrap(), status, cancel: CancellationToken::new()};
+        let queued = jobs.insert(job(Status::Queued));
+        for _ in 0..MAX_FINISHED_JOBS + 2 {
+            jobs.insert(job(Status::Cancelled));
+        }
+        jobs.evict_finished();
+        assert_eq!(jobs.jobs.len(), MAX_FINISHED_JOBS + 1);
+        // The oldest finished jobs go, but not the unfinished one before them.
+        assert!(jobs.jobs.contains_key(&queued));
+        assert!(!jobs.jobs.contains_key(&2) && !jobs.jobs.contains_key(&3));
+        assert_eq!(jobs.insert(job(Status::Queued)), MAX_FINISHED_JOBS as u64 + 4);
+    }
+
+    #[test]
+    fn test_panicking_render() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let runner: Runner = Box::new(|request: &JobRequest, _progress: ProgressCallback, _cancel: CancellationToken| {
+            if request.args.is_empty() {
+                panic!("no args");
+            }
+            Ok(b"image".to_vec())
+        });
+        thread::spawn(move || serve(listener, runner));
+
+        request(&address, "POST", "/jobs", "");
+        request(&address, "POST", "/jobs?samples=4", "");
+        assert_eq!(wait_for(&address, "1", "failed"),
+            "{\"id\": 1, \"status\": \"failed\", \"error\": \"the render panicked: no args\"}\n");
+        // The next job still renders.
+        assert_eq!(wait_for(&address, "2", "done"), "{\"id\": 2, \"status\": \"done\", \"progress\": 1}\n");
+    }
+}
@@ -0,0 +1,281 @@
+use crate::vec3::Color;
+
+const PLANCK: f64 = 6.62607015e-34;
+const LIGHT_SPEED: f64 = 2.99792458e8;
+const BOLTZMANN: f64 = 1.380649e-23;
+
+pub const LAMBDA_MIN: f64 = 360.;
+pub const LAMBDA_MAX: f64 = 830.;
+
+fn piecewise_gaussian(x: f64, mu: f64, sigma_low: f64, sigma_high: f64) -> f64 {
+    let sigma = if x < mu { sigma_low } else { sigma_high };
+    let t = (x - mu) / sigma;
+    (-0.5 * t * t).exp()
+}
+
+/// CIE 1931 color matching functions at wavelength `lambda` (in nm), using the
+/// multi-lobe Gaussian fit from Wyman, Sloan and Shirley (2013).
+pub fn cie_xyz(lambda: f64) -> (f64, f64, f64) {
+    let x = 1.056 * piecewise_gaussian(lambda, 599.8, 37.9, 31.0)
+        + 0.362 * piecewise_gaussian(lambda, 442.0, 16.0, 26.7)
+        - 0.065 * piecewise_gaussian(lambda, 501.1, 20.4, 26.2);
+    let y = 0.821 * piecewise_gaussian(lambda, 568.8, 46.9, 40.5)
+        + 0.286 * piecewise_gaussian(lambda, 530.9, 16.3, 31.1);
+    let z = 1.217 * piecewise_gaussian(lambda, 437.0, 11.8, 36.0)
+        + 0.681 * piecewise_gaussian(lambda, 459.0, 26.0, 13.8);
+    (x, y, z)
+}
+
+/// Converts CIE XYZ to linear sRGB (D65 white point).
+pub fn xyz_to_linear_srgb(x: f64, y: f64, z: f64) -> Color {
+    Color::new(
+        3.2406 * x - 1.5372 * y - 0.4986 * z,
+        -0.9689 * x + 1.8758 * y + 0.0415 * z,
+        0.0557 * x - 0.2040 * y + 1.0570 * z,
+    )
+}
+
+/// Number of wavelengths traced together along each path.
+pub const WAVELENGTH_SAMPLES: usize = 4;
+
+/// Radiance or reflectance at each of a path's sampled wavelengths.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SampledSpectrum(pub [f64; WAVELENGTH_SAMPLES]);
+
+impl SampledSpectrum {
+    pub fn zeros() -> SampledSpectrum {
+        SampledSpectrum([0.; WAVELENGTH_SAMPLES])
+    }
+}
+
+impl_op_ex!(+ |a: &SampledSpectrum, b: &SampledSpectrum| -> SampledSpectrum {
+    SampledSpectrum([a.0[0] + b.0[0], a.0[1] + b.0[1], a.0[2] + b.0[2], a.0[3] + b.0[3]])
+});
+
+impl_op_ex!(* |a: &SampledSpectrum, b: &SampledSpectrum| -> SampledSpectrum {
+    SampledSpectrum([a.0[0] * b.0[0], a.0[1] * b.0[1], a.0[2] * b.0[2], a.0[3] * b.0[3]])
+});
+
+/// The wavelengths (in nm) carried by one path, using hero wavelength sampling: a hero
+/// wavelength picked uniformly at random and the others evenly spaced after it, wrapping
+/// around the visible range.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SampledWavelengths {
+    pub lambda: [f64; WAVELENGTH_SAMPLES],
+    secondary_terminated: bool,
+}
+
+impl SampledWavelengths {
+    /// Picks the wavelengths from `u` uniform in [0, 1).
+    pub fn sample_hero(u: f64) -> SampledWavelengths {
+        let range = LAMBDA_MAX - LAMBDA_MIN;
+        let mut lambda = [0.; WAVELENGTH_SAMPLES];
+        for (i, l) in lambda.iter_mut().enumerate() {
+            let offset = (u + i as f64 / WAVELENGTH_SAMPLES as f64) % 1.;
+            *l = LAMBDA_MIN + offset * range;
+        }
+        SampledWavelengths{lambda, secondary_terminated: false}
+    }
+
+    pub fn hero(&self) -> f64 {
+        self.lambda[0]
+    }
+
+    /// Drops all but the hero wavelength, for when the path took a direction (e.g. through
+    /// a dispersive interface) that only makes sense for that one wavelength.
+    pub fn terminate_secondary(&mut self) {
+        self.secondary_terminated = true;
+    }
+
+    /// Evaluates the spectrum upsampled from a linear RGB color at each wavelength.
+    pub fn upsample(&self, rgb: &Color) -> SampledSpectrum {
+        let mut s = SampledSpectrum::zeros();
+        for (value, &lambda) in s.0.iter_mut().zip(self.lambda.iter()) {
+            *value = rgb_to_spectrum(rgb, lambda);
+        }
+        s
+    }
+}
+
+// Smits' (1999) basis spectra for converting RGB to a smooth spectrum, in ten equal bins
+// from 380 nm to 720 nm.
+const SMITS_MIN: f64 = 380.;
+const SMITS_MAX: f64 = 720.;
+const SMITS_WHITE: [f64; 10] = [1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000];
+const SMITS_CYAN: [f64; 10] = [0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000];
+const SMITS_MAGENTA: [f64; 10] = [1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959];
+const SMITS_YELLOW: [f64; 10] = [0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840];
+const SMITS_RED: [f64; 10] = [0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149];
+const SMITS_GREEN: [f64; 10] = [0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025];
+const SMITS_BLUE: [f64; 10] = [1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496];
+
+/// Value at `lambda` (in nm) of the smooth spectrum Smits' method builds for a linear RGB
+/// color: white for the smallest component, then the secondary and primary basis spectra
+/// for the remaining differences.
+pub fn rgb_to_spectrum(rgb: &Color, lambda: f64) -> f64 {
+    let bin = (((lambda - SMITS_MIN) / (SMITS_MAX - SMITS_MIN) * 10.).floor().max(0.) as usize).min(9);
+    let (r, g, b) = (rgb.x, rgb.y, rgb.z);
+    let basis = |spectrum: &[f64; 10]| spectrum[bin];
+    if r <= g && r <= b {
+        r * basis(&SMITS_WHITE) + if g <= b {
+            (g - r) * basis(&SMITS_CYAN) + (b - g) * basis(&SMITS_BLUE)
+        } else {
+            (b - r) * basis(&SMITS_CYAN) + (g - b) * basis(&SMITS_GREEN)
+        }
+    } else if g <= r && g <= b {
+        g * basis(&SMITS_WHITE) + if r <= b {
+            (r - g) * basis(&SMITS_MAGENTA) + (b - r) * basis(&SMITS_BLUE)
+        } else {
+            (b - g) * basis(&SMITS_MAGENTA) + (r - b) * basis(&SMITS_RED)
+        }
+    } else {
+        b * basis(&SMITS_WHITE) + if r <= g {
+            (r - b) * basis(&SMITS_YELLOW) + (g - r) * basis(&SMITS_GREEN)
+        } else {
+            (g - b) * basis(&SMITS_YELLOW) + (r - g) * basis(&SMITS_RED)
+        }
+    }
+}
+
+/// Turns the radiance carried at a path's wavelengths into linear sRGB via CIE XYZ.
+pub struct SpectralFilm {
+    // The RGB an equal-energy spectrum of 1 converts to, so it can be balanced to white.
+    white: Color,
+}
+
+impl Default for SpectralFilm {
+    fn default() -> SpectralFilm {
+        SpectralFilm::new()
+    }
+}
+
+impl SpectralFilm {
+    pub fn new() -> SpectralFilm {
+        let (mut x, mut y, mut z) = (0., 0., 0.);
+        let mut lambda = LAMBDA_MIN;
+        while lambda <= LAMBDA_MAX {
+            let (xb, yb, zb) = cie_xyz(lambda);
+            x += xb;
+            y += yb;
+            z += zb;
+            lambda += 1.;
+        }
+        SpectralFilm{white: xyz_to_linear_srgb(x, y, z)}
+    }
+
+    /// Monte Carlo estimate of the RGB color of a path's radiance. With the secondary
+    /// wavelengths terminated, the hero wavelength stands in for all of them.
+    pub fn to_rgb(&self, wavelengths: &SampledWavelengths, radiance: &SampledSpectrum) -> Color {
+        let samples = if wavelengths.secondary_terminated { 1 } else { WAVELENGTH_SAMPLES };
+        let (mut x, mut y, mut z) = (0., 0., 0.);
+        for (&lambda, &l) in wavelengths.lambda.iter().zip(radiance.0.iter()).take(samples) {
+            let (xb, yb, zb) = cie_xyz(lambda);
+            x += l * xb;
+            y += l * yb;
+            z += l * zb;
+        }
+        // Divide by the sample count and the pdf of each wavelength, 1 / (LAMBDA_MAX - LAMBDA_MIN).
+        let scale = (LAMBDA_MAX - LAMBDA_MIN) / samples as f64;
+        let rgb = xyz_to_linear_srgb(x * scale, y * scale, z * scale);
+        Color::new(rgb.x / self.white.x, rgb.y / self.white.y, rgb.z / self.white.z)
+    }
+}
+
+/// Decodes an sRGB-encoded channel value in [0, 1] to linear.
+pub fn srgb_to_linear(c: f64) -> f64 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Encodes a linear channel value in [0, 1] with the sRGB transfer function.
+pub fn linear_to_srgb(c: f64) -> f64 {
+    if c <= 0.0031308 {
+        12.92 * c
+    } else {
+        1.055 * c.powf(1. / 2.4) - 0.055
+    }
+}
+
+/// Spectral radiance of a black body at `temperature` (in K) for wavelength `lambda` (in nm).
+pub fn planck(lambda: f64, temperature: f64) -> f64 {
+    let l = lambda * 1e-9;
+    let c1 = 2. * PLANCK * LIGHT_SPEED * LIGHT_SPEED;
+    let c2 = PLANCK * LIGHT_SPEED / BOLTZMANN;
+    c1 / (l.powi(5) * ((c2 / (l * temperature)).exp() - 1.))
+}
+
+/// Linear sRGB color of a black body at `temperature` (in K), normalized to unit luminance.
+pub fn blackbody(temperature: f64) -> Color {
+    if temperature <= 0. {
+        return Color::zeros();
+    }
+    let (mut x, mut y, mut z) = (0., 0., 0.);
+    let mut lambda = LAMBDA_MIN;
+    while lambda <= LAMBDA_MAX {
+        let radiance = planck(lambda, temperature);
+        let (xb, yb, zb) = cie_xyz(lambda);
+        x += radiance * xb;
+        y += radiance * yb;
+        z += radiance * zb;
+        lambda += 5.;
+    }
+    xyz_to_linear_srgb(x / y, 1., z / y).clamped(0., f64::INFINITY)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cie_peak() {
+        let (_, y, _) = cie_xyz(555.);
+        assert!((y - 1.).abs() < 0.05);
+        let (_, y, _) = cie_xyz(400.);
+        assert!(y < 0.01);
+    }
+
+    #[test]
+    fn test_hero_wavelengths_cover_range() {
+        let wavelengths = SampledWavelengths::sample_hero(0.9);
+        assert!((wavelengths.hero() - (LAMBDA_MIN + 0.9 * (LAMBDA_MAX - LAMBDA_MIN))).abs() < 1e-9);
+        for &lambda in wavelengths.lambda.iter() {
+            assert!((LAMBDA_MIN..LAMBDA_MAX).contains(&lambda));
+        }
+        let spacing = (wavelengths.lambda[2] - wavelengths.lambda[1]).abs();
+        assert!((spacing - (LAMBDA_MAX - LAMBDA_MIN) / 4.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_upsampled_colors_round_trip() {
+        let film = SpectralFilm::new();
+        let n = 2000;
+        for &rgb in [Color::new(1., 1., 1.), Color::new(0.8, 0.1, 0.1), Color::new(0.1, 0.2, 0.9)].iter() {
+            let mut sum = Color::zeros();
+            for i in 0..n {
+                let wavelengths = SampledWavelengths::sample_hero((i as f64 + 0.5) / n as f64);
+                sum += film.to_rgb(&wavelengths, &wavelengths.upsample(&rgb));
+            }
+            let estimate = sum / n as f64;
+            assert!((estimate - rgb).length() < 0.15, "{:?} came back as {:?}", rgb, estimate);
+        }
+    }
+
+    #[test]
+    fn test_blackbody_d65_is_white() {
+        let c = blackbody(6504.);
+        assert!((c.x - c.y).abs() < 0.1);
+        assert!((c.z - c.y).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_blackbody_hue() {
+        let warm = blackbody(1500.);
+        assert!(warm.x > warm.y && warm.y > warm.z);
+        let cool = blackbody(15000.);
+        assert!(cool.z > cool.x);
+        assert_eq!(blackbody(0.), Color::zeros());
+    }
+}
@@ -16,7 +16,7 @@ impl Sphere {
 }
 
 impl Hittable for Sphere {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
         let oc: Vec3 = r.origin - self.center;
         let a = r.dir.length_squared();
         let half_b = oc.dot(&r.dir);
@@ -38,10 +38,14 @@ impl Hittable for Sphere {
             let p = r.at(t);
             let normal = (p - self.center) / self.radius;
             let front_face = false; // placeholder value
-            let mut rec = HitRecord{p, normal, t, front_face, material: self.material.as_ref()};
+            let mut rec = HitRecord{p, normal, t, front_face, material: self.material.as_ref(), u: 0., v: 0., vertex_color: None};
             let outward_normal = (rec.p - self.center) / self.radius;
             rec.set_face_normal(r, &outward_normal);
             Some(rec)
         }
     }
+
+    fn materials<'a>(&'a self, materials: &mut Vec<&'a dyn Material>) {
+        materials.push(self.material.as_ref());
+    }
 }
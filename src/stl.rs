@@ -0,0 +1,122 @@
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use crate::mesh::TriangleMesh;
+use crate::vec3::Point;
+
+const HEADER_SIZE: usize = 80;
+const TRIANGLE_SIZE: usize = 50;
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+pub fn load<P: AsRef<Path>>(path: P) -> io::Result<TriangleMesh> {
+    read(&mut File::open(path)?)
+}
+
+/// Reads an ASCII or binary STL file. STL stores each triangle with its own three
+/// vertices, so the mesh has no shared vertices and uses flat facet normals.
+pub fn read<R: Read>(reader: &mut R) -> io::Result<TriangleMesh> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    // Binary files may also start with "solid", so trust the size when it matches.
+    if data.len() >= HEADER_SIZE + 4 {
+        let count = u32::from_le_bytes([data[80], data[81], data[82], data[83]]) as usize;
+        if data.len() == HEADER_SIZE + 4 + count * TRIANGLE_SIZE {
+            return Ok(read_binary(&data[HEADER_SIZE + 4..], count));
+        }
+    }
+    if data.starts_with(b"solid") {
+        read_ascii(&data)
+    } else {
+        Err(invalid_data("not an STL file"))
+    }
+}
+
+fn read_binary(data: &[u8], count: usize) -> TriangleMesh {
+    let mut mesh = TriangleMesh::default();
+    let float = |b: &[u8], i: usize| f32::from_le_bytes([b[4 * i], b[4 * i + 1], b[4 * i + 2], b[4 * i + 3]]) as f64;
+    for record in data.chunks_exact(TRIANGLE_SIZE).take(count) {
+        // Skip the facet normal (3 floats), then read 3 vertices; the attribute count is ignored.
+        let first = mesh.positions.len() as u32;
+        for v in 0..3 {
+            let base = 3 + 3 * v;
+            mesh.positions.push(Point::new(float(record, base), float(record, base + 1), float(record, base + 2)));
+        }
+        mesh.triangles.push([first, first + 1, first + 2]);
+    }
+    mesh
+}
+
+fn read_ascii(data: &[u8]) -> io::Result<TriangleMesh> {
+    let text = std::str::from_utf8(data).map_err(|_| invalid_data("STL ASCII data is not valid UTF-8"))?;
+    let mut mesh = TriangleMesh::default();
+    let mut corners = 0;
+    for line in text.lines() {
+        let mut words = line.split_ascii_whitespace();
+        if words.next() != Some("vertex") {
+            continue;
+        }
+        let parse = |w: Option<&str>| w.and_then(|w| w.parse().ok()).ok_or_else(|| invalid_data("invalid STL vertex"));
+        let p = Point::new(parse(words.next())?, parse(words.next())?, parse(words.next())?);
+        mesh.positions.push(p);
+        corners += 1;
+        if corners % 3 == 0 {
+            let first = mesh.positions.len() as u32 - 3;
+            mesh.triangles.push([first, first + 1, first + 2]);
+        }
+    }
+    if corners % 3 != 0 {
+        return Err(invalid_data("STL facet does not have three vertices"));
+    }
+    Ok(mesh)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_ascii() {
+        let text = "solid tri
+  facet normal 0 0 1
+    outer loop
+      vertex 0 0 0
+      vertex 1 0 0
+      vertex 0 1.5 0
+    endloop
+  endfacet
+endsolid tri
+";
+        let mesh = read(&mut text.as_bytes()).unwrap();
+        assert_eq!(mesh.positions, vec![Point::new(0., 0., 0.), Point::new(1., 0., 0.), Point::new(0., 1.5, 0.)]);
+        assert_eq!(mesh.triangles, vec![[0, 1, 2]]);
+    }
+
+    #[test]
+    fn test_read_binary() {
+        // The header deliberately starts with "solid" like many exporters write it.
+        let mut bytes = b"solid binary".to_vec();
+        bytes.resize(HEADER_SIZE, 0);
+        bytes.extend_from_slice(&2u32.to_le_bytes());
+        for offset in [0f32, 5.].iter() {
+            let floats = [0., 0., 1., *offset, 0., 0., offset + 1., 0., 0., *offset, 1., 0.];
+            for f in floats.iter() {
+                bytes.extend_from_slice(&f.to_le_bytes());
+            }
+            bytes.extend_from_slice(&[0, 0]);
+        }
+        let mesh = read(&mut bytes.as_slice()).unwrap();
+        assert_eq!(mesh.positions.len(), 6);
+        assert_eq!(mesh.positions[4], Point::new(6., 0., 0.));
+        assert_eq!(mesh.triangles, vec![[0, 1, 2], [3, 4, 5]]);
+    }
+
+    #[test]
+    fn test_read_invalid() {
+        assert!(read(&mut &b"garbage"[..]).is_err());
+        assert!(read(&mut &b"solid x\nvertex 0 0 0\nvertex 1 0 0\nendsolid\n"[..]).is_err());
+    }
+}
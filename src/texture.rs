@@ -0,0 +1,75 @@
+use crate::hittable::HitRecord;
+use crate::vec3::Color;
+
+pub trait Texture {
+    fn value(&self, rec: &HitRecord) -> Color;
+}
+
+/// Colors interpolated from the vertices of the hit mesh, or `fallback` for surfaces
+/// without vertex colors.
+pub struct VertexColor {
+    pub fallback: Color,
+}
+
+impl Texture for VertexColor {
+    fn value(&self, rec: &HitRecord) -> Color {
+        rec.vertex_color.unwrap_or(self.fallback)
+    }
+}
+
+/// A bitmap of linear colors, sampled bilinearly at the hit's texture coordinates and
+/// repeated outside [0, 1].
+pub struct ImageTexture {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+}
+
+impl ImageTexture {
+    pub fn new(width: usize, height: usize, pixels: Vec<Color>) -> ImageTexture {
+        assert_eq!(pixels.len(), width * height);
+        ImageTexture{width, height, pixels}
+    }
+
+    fn texel(&self, x: i64, y: i64) -> Color {
+        let x = x.rem_euclid(self.width as i64) as usize;
+        let y = y.rem_euclid(self.height as i64) as usize;
+        self.pixels[y * self.width + x]
+    }
+
+    /// Samples the image at (u, v), with v = 0 at the top row as in glTF.
+    pub fn sample(&self, u: f64, v: f64) -> Color {
+        let x = u * self.width as f64 - 0.5;
+        let y = v * self.height as f64 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        let top = (1. - fx) * self.texel(x0, y0) + fx * self.texel(x0 + 1, y0);
+        let bottom = (1. - fx) * self.texel(x0, y0 + 1) + fx * self.texel(x0 + 1, y0 + 1);
+        (1. - fy) * top + fy * bottom
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, rec: &HitRecord) -> Color {
+        self.sample(rec.u, rec.v)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_image_texture_sample() {
+        let black = Color::zeros();
+        let white = Color::new(1., 1., 1.);
+        let image = ImageTexture::new(2, 1, vec![black, white]);
+        assert_eq!(image.sample(0.25, 0.5), black);
+        assert_eq!(image.sample(0.75, 0.5), white);
+        assert!((image.sample(0.5, 0.5) - Color::new(0.5, 0.5, 0.5)).length() < 1e-9);
+        // Wraps around horizontally.
+        assert!((image.sample(1.0, 0.5) - Color::new(0.5, 0.5, 0.5)).length() < 1e-9);
+        assert_eq!(image.sample(1.25, 0.5), black);
+    }
+}
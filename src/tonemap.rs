@@ -0,0 +1,182 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::sampler::{IndependentSampler, Sampler};
+use crate::spectrum::{blackbody, linear_to_srgb};
+use crate::vec3::Color;
+
+/// The curve that compresses HDR values into the displayable [0, 1] range.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ToneCurve {
+    /// Clip everything above 1.
+    Clamp,
+    /// x / (1 + x).
+    Reinhard,
+    /// John Hable's filmic curve from Uncharted 2.
+    Filmic,
+    /// Krzysztof Narkowicz's fit of the ACES reference rendering transform.
+    Aces,
+}
+
+impl ToneCurve {
+    pub const ALL: [ToneCurve; 4] = [ToneCurve::Clamp, ToneCurve::Reinhard, ToneCurve::Filmic, ToneCurve::Aces];
+
+    pub fn apply(&self, x: f64) -> f64 {
+        let x = x.max(0.);
+        match self {
+            ToneCurve::Clamp => x.min(1.),
+            ToneCurve::Reinhard => x / (1. + x),
+            ToneCurve::Filmic => {
+                const WHITE: f64 = 11.2;
+                let hable = |x: f64| {
+                    let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+                    (x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f) - e / f
+                };
+                (hable(2. * x) / hable(WHITE)).min(1.)
+            }
+            ToneCurve::Aces => ((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)).clamp(0., 1.),
+        }
+    }
+
+    /// The next curve, for cycling through them in the viewer.
+    pub fn next(&self) -> ToneCurve {
+        let index = ToneCurve::ALL.iter().position(|c| c == self).unwrap_or(0);
+        ToneCurve::ALL[(index + 1) % ToneCurve::ALL.len()]
+    }
+}
+
+impl FromStr for ToneCurve {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<ToneCurve, ()> {
+        match s {
+            "clamp" => Ok(ToneCurve::Clamp),
+            "reinhard" => Ok(ToneCurve::Reinhard),
+            "filmic" => Ok(ToneCurve::Filmic),
+            "aces" => Ok(ToneCurve::Aces),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for ToneCurve {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ToneCurve::Clamp => "clamp",
+            ToneCurve::Reinhard => "reinhard",
+            ToneCurve::Filmic => "filmic",
+            ToneCurve::Aces => "aces",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Turns a linear HDR image into 8-bit sRGB: white balance, exposure, a tone curve, the
+/// sRGB transfer function and optional dithering, in that order.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ToneMapper {
+    /// Exposure adjustment in stops.
+    pub exposure: f64,
+    pub curve: ToneCurve,
+    /// Color temperature (in K) of the light that should come out white.
+    pub white_balance: f64,
+    pub dither: bool,
+}
+
+/// Color temperature of the sRGB white point, D65, for which white balance does nothing.
+pub const NEUTRAL_WHITE_BALANCE: f64 = 6504.;
+
+impl ToneMapper {
+    pub fn new(exposure: f64, curve: ToneCurve, white_balance: f64, dither: bool) -> ToneMapper {
+        ToneMapper{exposure, curve, white_balance, dither}
+    }
+
+    /// Per-channel gains that make a black body at the white balance temperature neutral,
+    /// keeping its luminance.
+    fn white_balance_gains(&self) -> Color {
+        if self.white_balance == NEUTRAL_WHITE_BALANCE {
+            return Color::new(1., 1., 1.);
+        }
+        let white = blackbody(self.white_balance);
+        let neutral = blackbody(NEUTRAL_WHITE_BALANCE);
+        Color::new(neutral.x / white.x, neutral.y / white.y, neutral.z / white.z)
+    }
+
+    /// Maps a linear color to display-referred sRGB in [0, 1].
+    pub fn map(&self, color: &Color, gains: &Color) -> Color {
+        let exposed = color * gains * 2f64.powf(self.exposure);
+        Color::new(
+            linear_to_srgb(self.curve.apply(exposed.x)),
+            linear_to_srgb(self.curve.apply(exposed.y)),
+            linear_to_srgb(self.curve.apply(exposed.z)),
+        )
+    }
+
+    /// Writes `image` to an RGBA8 `frame` of the same size in pixels.
+    pub fn apply(&self, image: &[Color], frame: &mut [u8]) {
+        let gains = self.white_balance_gains();
+        // A fixed seed keeps the noise, and so the output, the same from frame to frame.
+        let mut sampler = IndependentSampler::new(0);
+        for (color, pixel) in image.iter().zip(frame.chunks_exact_mut(4)) {
+            let mut mapped = self.map(color, &gains) * 255.;
+            if self.dither {
+                // Triangular noise of up to one quantization step hides banding in gradients.
+                let mut noise = || sampler.get_1d() + sampler.get_1d() - 1.;
+                mapped += Color::new(noise(), noise(), noise());
+            }
+            let mapped = mapped.clamped(0., 255.);
+            pixel.copy_from_slice(&[mapped.x.round() as u8, mapped.y.round() as u8, mapped.z.round() as u8, 0xff]);
+        }
+    }
+}
+
+impl fmt::Display for ToneMapper {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} curve, exposure {:+.1} EV, white balance {} K, dithering {}",
+            self.curve, self.exposure, self.white_balance, if self.dither { "on" } else { "off" })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_curves() {
+        for curve in ToneCurve::ALL.iter() {
+            assert!(curve.apply(0.).abs() < 1e-3, "{}", curve);
+            assert!(curve.apply(-1.).abs() < 1e-3, "{}", curve);
+            assert!(curve.apply(1000.) <= 1., "{}", curve);
+            let mut previous = 0.;
+            for i in 1..100 {
+                let y = curve.apply(i as f64 * 0.1);
+                assert!(y >= previous, "{} is not monotonic", curve);
+                previous = y;
+            }
+            assert_eq!(curve.to_string().parse::<ToneCurve>(), Ok(*curve));
+        }
+        assert_eq!(ToneCurve::Aces.next(), ToneCurve::Clamp);
+    }
+
+    #[test]
+    fn test_exposure_and_white_balance() {
+        let mapper = ToneMapper::new(1., ToneCurve::Clamp, NEUTRAL_WHITE_BALANCE, false);
+        let gains = mapper.white_balance_gains();
+        assert_eq!(gains, Color::new(1., 1., 1.));
+        let mapped = mapper.map(&Color::new(0.25, 0.25, 0.25), &gains);
+        assert!((mapped.x - linear_to_srgb(0.5)).abs() < 1e-9);
+
+        // Balancing for warm light makes a warm light neutral.
+        let warm = ToneMapper::new(0., ToneCurve::Clamp, 3000., false);
+        let balanced = blackbody(3000.) * warm.white_balance_gains() * 0.5;
+        assert!((balanced.x - balanced.z).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_apply() {
+        let mapper = ToneMapper::new(0., ToneCurve::Clamp, NEUTRAL_WHITE_BALANCE, false);
+        let mut frame = [0u8; 8];
+        mapper.apply(&[Color::new(0., 1., 5.), Color::new(0.5, 0.5, 0.5)], &mut frame);
+        assert_eq!(frame, [0, 255, 255, 255, 188, 188, 188, 255]);
+    }
+}
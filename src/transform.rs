@@ -0,0 +1,101 @@
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+
+/// A rigid transform made of a rotation about the y axis followed by a translation.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RigidTransform {
+    pub translation: Vec3,
+    pub rotation_y: f64, // radians
+}
+
+impl RigidTransform {
+    pub fn new(translation: Vec3, rotation_y: f64) -> RigidTransform {
+        RigidTransform{translation, rotation_y}
+    }
+
+    fn rotate(v: &Vec3, angle: f64) -> Vec3 {
+        let (s, c) = angle.sin_cos();
+        Vec3::new(c * v.x + s * v.z, v.y, -s * v.x + c * v.z)
+    }
+
+    fn lerp(&self, other: &RigidTransform, f: f64) -> RigidTransform {
+        RigidTransform{
+            translation: self.translation + f * (other.translation - self.translation),
+            rotation_y: self.rotation_y + f * (other.rotation_y - self.rotation_y),
+        }
+    }
+}
+
+/// Moves an object from transform `start` at `time0` to `end` at `time1`, interpolating
+/// linearly in between and holding still outside that interval.
+pub struct AnimatedTransform {
+    pub object: Box<dyn Hittable + Send + Sync>,
+    pub start: RigidTransform,
+    pub end: RigidTransform,
+    pub time0: f64,
+    pub time1: f64,
+}
+
+impl AnimatedTransform {
+    pub fn new(object: Box<dyn Hittable + Send + Sync>, start: RigidTransform, end: RigidTransform, time0: f64, time1: f64) -> AnimatedTransform {
+        AnimatedTransform{object, start, end, time0, time1}
+    }
+
+    pub fn at(&self, time: f64) -> RigidTransform {
+        if self.time1 <= self.time0 {
+            return self.start;
+        }
+        let f = ((time - self.time0) / (self.time1 - self.time0)).clamp(0., 1.);
+        self.start.lerp(&self.end, f)
+    }
+}
+
+impl Hittable for AnimatedTransform {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let transform = self.at(r.time);
+        let angle = transform.rotation_y;
+        // Intersect in object space, then bring the hit back into world space. Rigid
+        // transforms preserve distances, so `t` and `front_face` carry over unchanged.
+        let origin = RigidTransform::rotate(&(r.origin - transform.translation), -angle);
+        let dir = RigidTransform::rotate(&r.dir, -angle);
+        let local = Ray::new(origin, dir, r.time);
+        let mut rec = self.object.hit(&local, t_min, t_max)?;
+        rec.p = RigidTransform::rotate(&rec.p, angle) + transform.translation;
+        rec.normal = RigidTransform::rotate(&rec.normal, angle);
+        Some(rec)
+    }
+
+    fn materials<'a>(&'a self, materials: &mut Vec<&'a dyn Material>) {
+        self.object.materials(materials);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::sphere::Sphere;
+    use crate::vec3::{Color, Point};
+
+    #[test]
+    fn test_animated_translation() {
+        let material = Box::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let sphere = Box::new(Sphere::new(0., 0., 0., 0.5, material));
+        let object = AnimatedTransform::new(
+            sphere,
+            RigidTransform::new(Vec3::new(0., 0., -2.), 0.),
+            RigidTransform::new(Vec3::new(0., 2., -2.), std::f64::consts::PI),
+            0., 1.);
+
+        let r = Ray::new(Point::zeros(), Vec3::new(0., 0., -1.), 0.);
+        let rec = object.hit(&r, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 1.5).abs() < 1e-9);
+        assert!((rec.p - Point::new(0., 0., -1.5)).length() < 1e-9);
+        assert!((rec.normal - Vec3::new(0., 0., 1.)).length() < 1e-9);
+
+        let r = Ray::new(Point::zeros(), Vec3::new(0., 0., -1.), 1.);
+        assert!(object.hit(&r, 0.001, f64::INFINITY).is_none());
+    }
+}
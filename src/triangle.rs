@@ -0,0 +1,32 @@
+use crate::ray::Ray;
+use crate::vec3::Point;
+
+const EPSILON: f64 = 1e-12;
+
+/// Intersects `r` with the triangle (v0, v1, v2) using the Möller-Trumbore algorithm.
+/// Returns the ray parameter and the barycentric coordinates (u, v) of v1 and v2.
+pub fn intersect_triangle(r: &Ray, v0: &Point, v1: &Point, v2: &Point, t_min: f64, t_max: f64) -> Option<(f64, f64, f64)> {
+    let edge1 = v1 - v0;
+    let edge2 = v2 - v0;
+    let pvec = r.dir.cross(&edge2);
+    let det = edge1.dot(&pvec);
+    if det.abs() < EPSILON {
+        return None;
+    }
+    let inv_det = 1. / det;
+    let tvec = r.origin - v0;
+    let u = tvec.dot(&pvec) * inv_det;
+    if !(0. ..=1.).contains(&u) {
+        return None;
+    }
+    let qvec = tvec.cross(&edge1);
+    let v = r.dir.dot(&qvec) * inv_det;
+    if v < 0. || u + v > 1. {
+        return None;
+    }
+    let t = edge2.dot(&qvec) * inv_det;
+    if t < t_min || t > t_max {
+        return None;
+    }
+    Some((t, u, v))
+}
@@ -1,4 +1,4 @@
-use rand::distributions::{Distribution, Uniform};
+use crate::sampler::Sampler;
 
 #[derive(Copy, Clone, Debug, PartialEq, PartialOrd)]
 pub struct Vec3 {
@@ -29,7 +29,7 @@ impl Vec3 {
         Vec3{
             x: self[1]*rhs[2] - self[2]*rhs[1],
             y: self[2]*rhs[0] - self[0]*rhs[2],
-            z: self[1]*rhs[0] - self[0]*rhs[2],
+            z: self[0]*rhs[1] - self[1]*rhs[0],
         }
     }
 
@@ -49,28 +49,22 @@ impl Vec3 {
         Vec3::new(clamp(self.x, min, max), clamp(self.y, min, max), clamp(self.z, min, max))
     }
 
-    pub fn random(min: f64, max: f64) -> Vec3 {
-        let u = Uniform::new(min, max);
-        let mut rng = rand::thread_rng();
-        Vec3::new(u.sample(&mut rng), u.sample(&mut rng), u.sample(&mut rng))
+    /// Maps a 2D sample to the unit sphere, so that stratified samples stay stratified
+    /// (which rejection sampling wouldn't).
+    pub fn random_unit_vector(sampler: &mut dyn Sampler) -> Vec3 {
+        let (u, v) = sampler.get_2d();
+        let z = 1. - 2. * u;
+        let r = (1. - z * z).max(0.).sqrt();
+        let phi = 2. * std::f64::consts::PI * v;
+        Vec3::new(r * phi.cos(), r * phi.sin(), z)
     }
 
-    pub fn random_in_unit_sphere() -> Vec3 {
-        loop {
-            let p = Vec3::random(-1., 1.); 
-            if p.length_squared() >= 1. {
-                continue
-            }
-            return p
-        }
-    }
-
-    pub fn random_unit_vector() -> Vec3 {
-        return Vec3::random_in_unit_sphere().unit_vec()
+    pub fn random_in_unit_sphere(sampler: &mut dyn Sampler) -> Vec3 {
+        Vec3::random_unit_vector(sampler) * sampler.get_1d().cbrt()
     }
 
-    pub fn random_in_hemisphere(normal: &Vec3) -> Vec3 {
-        let in_unit_sphere = Vec3::random_in_unit_sphere();
+    pub fn random_in_hemisphere(normal: &Vec3, sampler: &mut dyn Sampler) -> Vec3 {
+        let in_unit_sphere = Vec3::random_in_unit_sphere(sampler);
         if in_unit_sphere.dot(normal) > 0.0 {
             in_unit_sphere
         } else {
@@ -84,6 +78,11 @@ impl Vec3 {
     }
 }
 
+/// Relative luminance of a linear sRGB color.
+pub fn luminance(c: &Color) -> f64 {
+    0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z
+}
+
 pub fn reflect(v: &Vec3, n: &Vec3) -> Vec3 {
     v - 2.*v.dot(n)*n
 }
@@ -227,7 +226,7 @@ mod tests {
 
     #[test]
     fn test_length() {
-        assert_eq!(Vec3::new(1., 2., 3.).length(), (14. as f64).sqrt());
+        assert_eq!(Vec3::new(1., 2., 3.).length(), 14_f64.sqrt());
     }
 
     #[test]
@@ -240,7 +239,7 @@ mod tests {
     fn test_cross() {
         let a = Vec3::new(0., 1., 2.);
         let b = Vec3::new(2., 1., 0.);
-        assert_eq!(a.cross(&b), Vec3::new(-2., 4., 2.));
+        assert_eq!(a.cross(&b), Vec3::new(-2., 4., -2.));
     }
 
     #[test]
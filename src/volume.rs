@@ -0,0 +1,286 @@
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::sampler::{IndependentSampler, Sampler};
+use crate::spectrum::blackbody;
+use crate::vec3::{Color, Point, Vec3};
+
+const MAGIC: &[u8; 4] = b"RVOL";
+// The most voxels a grid may have, so a corrupt header can't ask for an absurd allocation.
+const MAX_VOXELS: usize = 1 << 28;
+
+/// A dense voxel grid of densities and (optionally) temperatures in Kelvin.
+///
+/// The on-disk format is the magic `RVOL`, followed by little-endian u32 `nx`, `ny`, `nz`
+/// and channel count (1 = density, 2 = density + temperature), followed by `nx*ny*nz`
+/// little-endian f32 values per channel, one channel after another, with x varying fastest.
+pub struct VoxelGrid {
+    nx: usize,
+    ny: usize,
+    nz: usize,
+    density: Vec<f32>,
+    temperature: Option<Vec<f32>>,
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_channel<R: Read>(reader: &mut R, len: usize) -> io::Result<Vec<f32>> {
+    let size = len.checked_mul(4).ok_or_else(|| invalid_data("voxel grid is too large"))?;
+    let mut bytes = vec![0u8; size];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes.chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect())
+}
+
+impl VoxelGrid {
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<VoxelGrid> {
+        VoxelGrid::read(&mut BufReader::new(File::open(path)?))
+    }
+
+    pub fn read<R: Read>(reader: &mut R) -> io::Result<VoxelGrid> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not a voxel grid file"));
+        }
+        let nx = read_u32(reader)? as usize;
+        let ny = read_u32(reader)? as usize;
+        let nz = read_u32(reader)? as usize;
+        let channels = read_u32(reader)?;
+        if nx == 0 || ny == 0 || nz == 0 {
+            return Err(invalid_data("voxel grid has an empty dimension"));
+        }
+        if channels != 1 && channels != 2 {
+            return Err(invalid_data("voxel grid must have 1 or 2 channels"));
+        }
+        let len = nx.checked_mul(ny).and_then(|n| n.checked_mul(nz))
+            .filter(|&len| len <= MAX_VOXELS)
+            .ok_or_else(|| invalid_data("voxel grid is too large"))?;
+        let density = read_channel(reader, len)?;
+        let temperature = if channels == 2 { Some(read_channel(reader, len)?) } else { None };
+        Ok(VoxelGrid{nx, ny, nz, density, temperature})
+    }
+
+    pub fn max_density(&self) -> f64 {
+        self.density.iter().cloned().fold(0., f32::max) as f64
+    }
+
+    fn voxel(&self, channel: &[f32], x: usize, y: usize, z: usize) -> f64 {
+        channel[(z * self.ny + y) * self.nx + x] as f64
+    }
+
+    /// Trilinearly interpolates `channel` at `q`, given in grid-local [0, 1]^3 coordinates.
+    fn lookup(&self, channel: &[f32], q: &Vec3) -> f64 {
+        let coord = |v: f64, n: usize| {
+            let x = (v * n as f64 - 0.5).max(0.).min((n - 1) as f64);
+            let i = (x as usize).min(n.saturating_sub(2));
+            (i, (i + 1).min(n - 1), x - i as f64)
+        };
+        let (x0, x1, fx) = coord(q.x, self.nx);
+        let (y0, y1, fy) = coord(q.y, self.ny);
+        let (z0, z1, fz) = coord(q.z, self.nz);
+        let lerp = |a: f64, b: f64, t: f64| a + (b - a) * t;
+        let c00 = lerp(self.voxel(channel, x0, y0, z0), self.voxel(channel, x1, y0, z0), fx);
+        let c10 = lerp(self.voxel(channel, x0, y1, z0), self.voxel(channel, x1, y1, z0), fx);
+        let c01 = lerp(self.voxel(channel, x0, y0, z1), self.voxel(channel, x1, y0, z1), fx);
+        let c11 = lerp(self.voxel(channel, x0, y1, z1), self.voxel(channel, x1, y1, z1), fx);
+        lerp(lerp(c00, c10, fy), lerp(c01, c11, fy), fz)
+    }
+}
+
+/// A participating medium whose density varies according to a voxel grid stretched over
+/// an axis-aligned box. Free-flight distances are sampled with delta tracking, so each
+/// `hit` is a real collision inside the medium, which then scatters isotropically.
+pub struct HeterogeneousVolume {
+    grid: VoxelGrid,
+    min: Point,
+    max: Point,
+    density_scale: f64,
+    sigma_max: f64,
+    albedo: f64,
+    emission_scale: f64,
+}
+
+impl HeterogeneousVolume {
+    pub fn new(grid: VoxelGrid, min: Point, max: Point, density_scale: f64, albedo: f64, emission_scale: f64) -> HeterogeneousVolume {
+        let sigma_max = density_scale * grid.max_density();
+        HeterogeneousVolume{grid, min, max, density_scale, sigma_max, albedo, emission_scale}
+    }
+
+    fn local(&self, p: &Point) -> Vec3 {
+        let extent = self.max - self.min;
+        let q = p - self.min;
+        Vec3::new(q.x / extent.x, q.y / extent.y, q.z / extent.z)
+    }
+
+    fn sigma_t(&self, p: &Point) -> f64 {
+        self.density_scale * self.grid.lookup(&self.grid.density, &self.local(p))
+    }
+
+    fn temperature(&self, p: &Point) -> f64 {
+        match &self.grid.temperature {
+            Some(temperature) => self.grid.lookup(temperature, &self.local(p)),
+            None => 0.,
+        }
+    }
+
+    /// Parametric interval of `r` inside the bounding box, clipped to [t_min, t_max].
+    fn clip(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64)> {
+        let (mut t0, mut t1) = (t_min, t_max);
+        for axis in 0..3 {
+            let inv_d = 1. / r.dir[axis];
+            let mut near = (self.min[axis] - r.origin[axis]) * inv_d;
+            let mut far = (self.max[axis] - r.origin[axis]) * inv_d;
+            if inv_d < 0. {
+                std::mem::swap(&mut near, &mut far);
+            }
+            t0 = if near > t0 { near } else { t0 };
+            t1 = if far < t1 { far } else { t1 };
+            if t1 <= t0 {
+                return None;
+            }
+        }
+        Some((t0, t1))
+    }
+
+    /// Intersection tests aren't handed a sampler, so tracking draws its distances from one
+    /// seeded by the ray, which is different for every path vertex yet the same on every run.
+    fn ray_sampler(r: &Ray) -> IndependentSampler {
+        IndependentSampler::hashed(&[r.origin.x, r.origin.y, r.origin.z, r.dir.x, r.dir.y, r.dir.z, r.time])
+    }
+}
+
+impl Hittable for HeterogeneousVolume {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let (t0, t1) = self.clip(r, t_min, t_max)?;
+        if self.sigma_max <= 0. {
+            return None;
+        }
+        let mut sampler = HeterogeneousVolume::ray_sampler(r);
+        let step = self.sigma_max * r.dir.length();
+        let mut t = t0;
+        loop {
+            t -= (1. - sampler.get_1d()).ln() / step;
+            if t >= t1 {
+                return None;
+            }
+            let p = r.at(t);
+            if self.sigma_t(&p) / self.sigma_max > sampler.get_1d() {
+                // Media have no surface, so the normal is arbitrary.
+                let normal = Vec3::new(1., 0., 0.);
+                return Some(HitRecord{p, normal, t, front_face: true, material: self, u: 0., v: 0., vertex_color: None});
+            }
+        }
+    }
+
+    fn materials<'a>(&'a self, materials: &mut Vec<&'a dyn Material>) {
+        materials.push(self);
+    }
+}
+
+impl Material for HeterogeneousVolume {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<(Ray, Color)> {
+        let scattered = Ray::new(rec.p, Vec3::random_unit_vector(sampler), r_in.time);
+        Some((scattered, Color::new(self.albedo, self.albedo, self.albedo)))
+    }
+
+    /// Blackbody emission at the collision, weighted by the absorption probability so
+    /// that scattering and absorption can share the same collision.
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        let temperature = self.temperature(&rec.p);
+        if self.emission_scale <= 0. || temperature <= 0. {
+            return Color::zeros();
+        }
+        let intensity = self.emission_scale * (temperature / 1000.).powi(4);
+        (1. - self.albedo) * intensity * blackbody(temperature)
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        Color::new(self.albedo, self.albedo, self.albedo)
+    }
+
+    /// Isotropic phase function.
+    fn eval(&self, _r_in: &Ray, _rec: &HitRecord, _wi: &Vec3) -> Color {
+        let phase = self.albedo / (4. * std::f64::consts::PI);
+        Color::new(phase, phase, phase)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid_bytes(dims: [u32; 3], channels: &[&[f32]]) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        for d in dims.iter() {
+            bytes.extend_from_slice(&d.to_le_bytes());
+        }
+        bytes.extend_from_slice(&(channels.len() as u32).to_le_bytes());
+        for channel in channels {
+            for v in channel.iter() {
+                bytes.extend_from_slice(&v.to_le_bytes());
+            }
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_read() {
+        let bytes = grid_bytes([2, 1, 1], &[&[0.5, 1.0], &[1000., 2000.]]);
+        let grid = VoxelGrid::read(&mut bytes.as_slice()).unwrap();
+        assert_eq!((grid.nx, grid.ny, grid.nz), (2, 1, 1));
+        assert_eq!(grid.max_density(), 1.);
+        assert_eq!(grid.temperature, Some(vec![1000., 2000.]));
+    }
+
+    #[test]
+    fn test_read_truncated() {
+        let bytes = grid_bytes([2, 2, 2], &[&[1.0; 3]]);
+        assert!(VoxelGrid::read(&mut bytes.as_slice()).is_err());
+        assert!(VoxelGrid::read(&mut &b"nope"[..]).is_err());
+        // Dimensions whose product overflows, or is too many voxels, are rejected up front.
+        for dims in [[u32::MAX; 3], [4096, 4096, 4096]] {
+            let error = VoxelGrid::read(&mut grid_bytes(dims, &[&[]]).as_slice()).err().unwrap();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn test_lookup_interpolates() {
+        let bytes = grid_bytes([2, 1, 1], &[&[0., 1.]]);
+        let grid = VoxelGrid::read(&mut bytes.as_slice()).unwrap();
+        assert_eq!(grid.lookup(&grid.density, &Vec3::new(0.5, 0.5, 0.5)), 0.5);
+        assert_eq!(grid.lookup(&grid.density, &Vec3::new(0., 0.5, 0.5)), 0.);
+        assert_eq!(grid.lookup(&grid.density, &Vec3::new(1., 0.5, 0.5)), 1.);
+    }
+
+    #[test]
+    fn test_empty_volume_is_transparent() {
+        let bytes = grid_bytes([1, 1, 1], &[&[0.]]);
+        let grid = VoxelGrid::read(&mut bytes.as_slice()).unwrap();
+        let volume = HeterogeneousVolume::new(grid, Point::new(-1., -1., -1.), Point::new(1., 1., 1.), 1., 0.5, 0.);
+        let r = Ray::new(Point::new(0., 0., -5.), Vec3::new(0., 0., 1.), 0.);
+        assert!(volume.hit(&r, 0.001, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn test_dense_volume_is_opaque() {
+        let bytes = grid_bytes([1, 1, 1], &[&[1.]]);
+        let grid = VoxelGrid::read(&mut bytes.as_slice()).unwrap();
+        let volume = HeterogeneousVolume::new(grid, Point::new(-1., -1., -1.), Point::new(1., 1., 1.), 1e6, 0.5, 0.);
+        let r = Ray::new(Point::new(0., 0., -5.), Vec3::new(0., 0., 1.), 0.);
+        let rec = volume.hit(&r, 0.001, f64::INFINITY).unwrap();
+        assert!(rec.t >= 4. && rec.t < 4.01);
+    }
+}
@@ -0,0 +1,46 @@
+"""Tests of the Python bindings. Build them first with `maturin develop`, then run `pytest`."""
+
+import numpy as np
+import pytest
+
+import raytracing
+
+
+def tiny_scene():
+    scene = raytracing.Scene()
+    scene.add_sphere((0, -100.5, -1), 100, raytracing.Material.lambertian((0.5, 0.5, 0.5)))
+    scene.add_sphere((0, 0, -1), 0.5, raytracing.Material.metal((0.8, 0.6, 0.2), fuzz=0.1))
+    scene.add_point_light((0, 2, 0), (5, 5, 5))
+    return scene
+
+
+def test_render():
+    image = raytracing.render(tiny_scene(), raytracing.Camera(), 8, 4, samples=2, seed=1)
+    assert image.shape == (4, 8, 3)
+    assert image.dtype == np.float32
+    assert np.isfinite(image).all()
+    assert (image >= 0).all()
+    assert (image > 0).any()
+    # The same seed renders the same image.
+    assert np.array_equal(image, raytracing.render(tiny_scene(), raytracing.Camera(), 8, 4, samples=2, seed=1))
+
+
+def test_invalid_arguments():
+    with pytest.raises(ValueError):
+        raytracing.Material.dielectric(0)
+    with pytest.raises(ValueError):
+        raytracing.Material.lambertian((float("nan"), 0.5, 0.5))
+    with pytest.raises(ValueError):
+        raytracing.Material.metal((0.8, 0.8, 0.8), fuzz=-1)
+    with pytest.raises(ValueError):
+        raytracing.Material.pbr((0.8, 0.8, 0.8), roughness=2)
+    with pytest.raises(ValueError):
+        raytracing.Scene().add_sphere((0, 0, 0), -1, raytracing.Material.lambertian((1, 1, 1)))
+    with pytest.raises(ValueError):
+        raytracing.Camera(vertical_fov=180)
+    with pytest.raises(ValueError):
+        raytracing.Camera(look_at=(0, 5, 0))
+    with pytest.raises(ValueError):
+        raytracing.Scene().add_triangles([(0, 0, 0)], [(0, 1, 2)], raytracing.Material.lambertian((1, 1, 1)))
+    with pytest.raises(ValueError):
+        raytracing.render(tiny_scene(), raytracing.Camera(), 0, 4)